@@ -1,5 +1,12 @@
 fn main() {
+    // AppKit などのフレームワークは macOS でしかリンクできない
+    if std::env::var("CARGO_CFG_TARGET_OS").as_deref() != Ok("macos") {
+        return;
+    }
     println!("cargo:rustc-link-lib=framework=AppKit");
+    println!("cargo:rustc-link-lib=framework=CoreImage");
     println!("cargo:rustc-link-lib=framework=Foundation");
+    println!("cargo:rustc-link-lib=framework=Carbon");
+    println!("cargo:rustc-link-lib=framework=Security");
     println!("cargo:rustc-link-lib=objc");
 }
@@ -0,0 +1,70 @@
+#!/usr/bin/env bash
+set -euo pipefail
+
+ROOT_DIR="$(cd "$(dirname "$0")/.." && pwd)"
+cd "$ROOT_DIR"
+
+if [[ "$(uname -s)" != "Darwin" ]]; then
+  echo "soak test requires macOS" >&2
+  exit 1
+fi
+
+ITERATIONS="${ITERATIONS:-1000}"
+WARMUP_ITERATIONS="${WARMUP_ITERATIONS:-100}"
+COPY_INTERVAL_SECS="${COPY_INTERVAL_SECS:-0.05}"
+MAX_RSS_GROWTH_KB="${MAX_RSS_GROWTH_KB:-4096}"
+SOAK_CONFIG_PATH="/tmp/cliip-show-soak-test.toml"
+
+cargo build --release >/dev/null
+BIN="$ROOT_DIR/target/release/cliip-show"
+
+ORIGINAL_CLIPBOARD="$(pbpaste 2>/dev/null || true)"
+rm -f "$SOAK_CONFIG_PATH"
+
+CLIIP_SHOW_CONFIG_PATH="$SOAK_CONFIG_PATH" \
+  CLIIP_SHOW_POLL_INTERVAL_SECS=0.05 \
+  CLIIP_SHOW_HUD_DURATION_SECS=0.1 \
+  "$BIN" &
+APP_PID=$!
+
+cleanup() {
+  kill "$APP_PID" 2>/dev/null || true
+  wait "$APP_PID" 2>/dev/null || true
+  printf '%s' "$ORIGINAL_CLIPBOARD" | pbcopy
+  rm -f "$SOAK_CONFIG_PATH"
+}
+trap cleanup EXIT
+
+rss_kb() {
+  ps -o rss= -p "$APP_PID" | tr -d ' '
+}
+
+copy_clips() {
+  local count="$1"
+  local offset="$2"
+  local i
+  for ((i = 0; i < count; i++)); do
+    printf 'soak clip %d %d' "$((offset + i))" "$RANDOM" | pbcopy
+    sleep "$COPY_INTERVAL_SECS"
+  done
+  # 最後のクリップのHUD表示とフェードが終わるのを待つ
+  sleep 1
+}
+
+sleep 1
+copy_clips "$WARMUP_ITERATIONS" 0
+baseline_rss="$(rss_kb)"
+echo "baseline rss: ${baseline_rss} KB (after ${WARMUP_ITERATIONS} warmup clips)"
+
+copy_clips "$ITERATIONS" "$WARMUP_ITERATIONS"
+final_rss="$(rss_kb)"
+growth=$((final_rss - baseline_rss))
+echo "final rss   : ${final_rss} KB (after ${ITERATIONS} more clips)"
+echo "growth      : ${growth} KB (max=${MAX_RSS_GROWTH_KB} KB)"
+
+if ((growth > MAX_RSS_GROWTH_KB)); then
+  echo "soak test failed: rss grew by ${growth} KB" >&2
+  exit 1
+fi
+
+echo "soak test passed"
@@ -0,0 +1,3468 @@
+use std::cell::RefCell;
+use std::ffi::c_void;
+use std::fmt::Write as _;
+use std::io::{Read as _, Write as _};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex, Once};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::{Local, SecondsFormat, TimeDelta};
+use objc2::declare::ClassBuilder;
+use objc2::rc::autoreleasepool;
+use objc2::runtime::{AnyClass, AnyObject, Sel};
+use objc2::{class, msg_send, sel};
+
+use cliip_show::config::{
+    apply_config_file, apply_env_overrides, config_file_path, default_display_settings,
+    display_settings, display_settings_from, history_exclude, is_sf_symbol_name, load_config_file,
+    parse_config_key, print_effective_settings, save_config_file, set_config_value,
+    settings_to_config_file, sync_dir, AppConfigFile, ConcealedBehavior, ConfigKey,
+    DedupConsecutive, DisplaySettings, HudAppearance, PollTimerBackend, DEFAULT_HUD_ICON,
+};
+use cliip_show::content::{
+    build_binary_hud_content, build_concealed_hud_content, build_files_hud_content,
+    build_hud_content, build_image_hud_content, clear_countdown_footer, detect_sensitive_clip,
+    favicon_url, join_pasteboard_items, minimal_hud_content, qr_code_url, ClipBinary, ClipContext,
+    ClipFiles, HudContent, SourceApp,
+};
+use cliip_show::control::{
+    notification_payload, observe_control_notification, parse_ps_resources,
+    post_control_notification, remove_control_observer, resource_report, status_report,
+    ClipCounter, DaemonStats, HistoryRecording, CONFIG_CHANGED_NOTIFICATION,
+    HISTORY_PAUSE_NOTIFICATION, HISTORY_RESUME_NOTIFICATION, STATS_REQUEST_NOTIFICATION,
+    STATS_RESPONSE_NOTIFICATION,
+};
+use cliip_show::encryption::{
+    decode_sync_key, encode_sync_key, history_keychain_key, save_sync_keychain_key,
+    sync_keychain_key, HistoryCipher,
+};
+use cliip_show::files::{
+    existing_path_in_text, file_display_name, file_modified, total_file_bytes,
+};
+use cliip_show::history::{
+    export_history, history_cycle_entries, history_cycle_footer, history_picker_command,
+    history_store_path, import_history, load_history, load_history_matching, parse_history_export,
+    parse_history_export_format, parse_history_line_id, parse_since, prune_history,
+    run_history_picker, select_history, set_history_pinned, summarize_history, HistoryEntry,
+    HistoryExclude, HistoryExportFormat, HistoryLimits, HistoryQuery, HistorySelector,
+    HistoryStore,
+};
+use cliip_show::hotkey::{
+    pressed_hotkey_id, Hotkey, HotkeyHandler, RegisteredHotkey, EVENT_NOT_HANDLED_ERR,
+};
+use cliip_show::image_diff::{parse_diff_threshold, DiffThreshold};
+use cliip_show::onboarding::{
+    mark_onboarded, needs_onboarding, onboarding_text, ONBOARDING_FOOTER,
+    ONBOARDING_HUD_DURATION_SECS,
+};
+use cliip_show::owned::{live_objects, DispatchTimer, Owned, ScheduledTimer};
+use cliip_show::pasteboard::{
+    cached_nsstring, has_file_type, has_rich_text_type, is_concealed, is_remote_clipboard,
+    is_self_write, is_transient, nsdata_to_vec, nsstring_from_str, nsstring_to_string,
+    nsstring_to_string_capped, pasteboard_data_len, pasteboard_file_paths, pasteboard_item_texts,
+    pasteboard_types, preferred_binary_type, preferred_image_type, preferred_rich_text_format,
+    preferred_text_type, rich_text_to_plain_capped, set_plain_text, type_badges, write_plain_text,
+};
+use cliip_show::plugin::{load_plugins, plugins_dir, ContentTransformer};
+use cliip_show::rendering::{
+    announce_for_accessibility, apply_hud_content, apply_view_settings, clip_image_from_data,
+    create_hud_window, file_icon_png, frontmost_source_app, generate_diff_png,
+    hud_font_is_available, hud_symbol_is_available, measure_hud_placement, qr_code_png,
+    render_hud_png, set_icon_image, set_label_text, HudViews, HudWindow, SnapshotOptions,
+    MAX_RENDER_SCALE_FACTOR, MIN_RENDER_SCALE_FACTOR,
+};
+use cliip_show::schedule::QuietMode;
+use cliip_show::snapshot::{
+    load_snapshot_manifest, run_snapshot_case, select_snapshot_cases, strip_final_newline,
+    update_snapshot_case, CaseOutcome, SnapshotManifest, SnapshotPaths,
+};
+use cliip_show::speech::{speakable_text, speech_voice, SpeechSynthesizer};
+use cliip_show::sync::{sync_device_name, sync_history, HistorySync};
+
+const POLL_BACKOFF_FACTOR: f64 = 1.5;
+// 発火時刻の許容誤差。macOS が他のタイマーとまとめて起床できるようにして消費電力を抑える
+const POLL_TIMER_TOLERANCE_RATIO: f64 = 0.2;
+const FADE_TICK_INTERVAL_SECS: f64 = 1.0 / 60.0;
+const AUTO_CLEAR_TICK_INTERVAL_SECS: f64 = 1.0;
+// `history_hotkey` の修飾キーを離したかを調べる間隔
+const HISTORY_CYCLE_TICK_INTERVAL_SECS: f64 = 0.05;
+// 登録するショートカットの番号。Carbon のハンドラーはどれが押されたかをこれで見分ける
+const HISTORY_HOTKEY_ID: u32 = 1;
+const APPEND_HOTKEY_ID: u32 = 2;
+const APPEND_MODE_ON_FOOTER: &str = "copies are joined until turned off";
+const EFFECTIVE_APPEARANCE_KEY_PATH: &str = "effectiveAppearance";
+// `[sync]` の同期は、コピーがなくてもこの間隔で行う
+const HISTORY_SYNC_INTERVAL: Duration = Duration::from_secs(60);
+const STATS_RESPONSE_TIMEOUT: Duration = Duration::from_secs(2);
+const STATS_RUN_LOOP_STEP_SECS: f64 = 0.05;
+// これより大きい応答はファビコンではないとみなして使わない
+const MAX_FAVICON_BYTES: usize = 256 * 1024;
+
+struct AppState {
+    last_change_count: isize,
+    last_change_at: Instant,
+    pasteboard: *mut AnyObject,
+    poll_timer: Option<PollTimer>,
+    poll_interval_secs: f64,
+    // 何もコピーされないセッションで無駄にならないよう、最初に表示するときに作る
+    hud_window: Option<HudWindow>,
+    // 読み上げを有効にして最初のクリップを読み上げるときに作る
+    speech: Option<SpeechSynthesizer>,
+    previous_text: Option<String>,
+    // `previous_text` と同じテキストを続けてコピーした回数。テキスト以外のクリップで 0 に戻す
+    clip_repeat_count: u32,
+    clip_counter: ClipCounter,
+    // 直前にHUDの内容を組み立てたクリップの時刻。`show_elapsed` に使う
+    last_clip_at: Option<Instant>,
+    // 最後に変化したクリップが Universal Clipboard で別のデバイスから届いたか
+    clip_from_other_device: bool,
+    // 最後に変化したクリップに含まれていた表現の短い名前。`show_type_badges` に使う
+    clip_type_badges: Vec<String>,
+    // 最後に変化したクリップのときに最前面だったアプリ。`show_source_app` か `history_enabled` の
+    // ときだけ調べる
+    clip_source_app: Option<SourceApp>,
+    // 履歴を扱うスレッドに記録や読み込みを送る。1本のスレッドが開いた履歴を持ち、届いた順に処理する
+    history_writer: mpsc::Sender<HistoryJob>,
+    history_exclude: Arc<HistoryExclude>,
+    history_sync_dir: Option<PathBuf>,
+    // 同期するスレッドに依頼を送る。`history_sync_timer` が間隔ごとに送る
+    history_sync: mpsc::SyncSender<HistorySyncJob>,
+    // `[sync]` を設定して履歴を記録しているときだけ持つ
+    history_sync_timer: Option<ScheduledTimer>,
+    // `history pause` で一時的に記録を止めている。HUD はいつもどおり出す
+    history_paused: bool,
+    // `history_hotkey` を登録していれば持つ。Drop で登録を外す
+    history_hotkey: Option<RegisteredHotkey>,
+    // ホットキーで履歴をさかのぼっている間だけ持つ。修飾キーを離すと選んだ履歴をコピーする
+    history_cycle: Option<HistoryCycle>,
+    // 履歴を読み込んでいる間に押された回数。読み終えたらその分だけさかのぼった履歴から出す
+    history_cycle_presses: usize,
+    history_cycle_timer: Option<ScheduledTimer>,
+    // 追記モードか。起動時と `append_mode` を変えたときは設定に合わせ、`append_hotkey` で切り替える
+    append_active: bool,
+    // 追記モードでつないできたテキスト。モードを切り替えるとリセットする
+    append_buffer: Option<String>,
+    append_hotkey: Option<RegisteredHotkey>,
+    hide_timer: Option<ScheduledTimer>,
+    fade_timer: Option<ScheduledTimer>,
+    fade_ticks_elapsed: u32,
+    fade_total_ticks: u32,
+    clear_timer: Option<ScheduledTimer>,
+    pending_clear: Option<PendingClear>,
+    plugins: Arc<Mutex<Vec<Box<dyn ContentTransformer>>>>,
+    launched_at: Instant,
+    settings: DisplaySettings,
+}
+
+// どちらも Drop でタイマーを止める。持っておくだけで読まない
+enum PollTimer {
+    NsTimer { _timer: ScheduledTimer },
+    Dispatch { _timer: DispatchTimer },
+}
+
+// 履歴を扱うスレッドへの依頼
+enum HistoryJob {
+    Append(HistoryWrite),
+    // `history_hotkey` を最初に押したとき。読んだ履歴は historyCycleLoaded: でメインスレッドに戻す
+    LoadCycle {
+        settings: DisplaySettings,
+        current_text: Option<String>,
+        delegate: usize,
+    },
+}
+
+// 履歴に記録するコピー1件分
+struct HistoryWrite {
+    copied_at: String,
+    source_app: Option<String>,
+    text: String,
+    settings: DisplaySettings,
+    exclude: Arc<HistoryExclude>,
+}
+
+// `[sync]` の同期1回分
+struct HistorySyncJob {
+    dir: PathBuf,
+    settings: DisplaySettings,
+}
+
+struct HistoryCycle {
+    // 新しい順。いまクリップボードにあるテキストは含めない
+    entries: Vec<HistoryEntry>,
+    index: usize,
+}
+
+struct PendingClear {
+    change_count: isize,
+    deadline: Instant,
+    footer_base: Option<String>,
+}
+
+impl AppState {
+    fn views(&self) -> Option<HudViews> {
+        self.hud_window.as_ref().map(HudWindow::views)
+    }
+
+    unsafe fn hud_views(&mut self) -> HudViews {
+        let settings = self.settings;
+        self.hud_window
+            .get_or_insert_with(|| create_hud_window(settings))
+            .views()
+    }
+}
+
+// バックグラウンドで組み立てたHUDの内容。メインスレッドで AppKit に反映する
+struct PreparedClip {
+    change_count: isize,
+    content: HudContent,
+    sensitive: bool,
+    // 読み上げる文字列。機密らしいクリップは自動クリアの設定に関係なく読み上げない
+    speech: Option<String>,
+    // `show_favicon` で、HUD を出した後に取得するファビコンの URL
+    favicon_url: Option<String>,
+    // `show_qr_for_urls` で QR コードにする URL。CoreImage を使うのでメインスレッドで描く
+    qr_url: Option<String>,
+}
+
+// バックグラウンドで取得したファビコン。メインスレッドで表示中の HUD のアイコンに差し替える
+struct FetchedFavicon {
+    change_count: isize,
+    data: Vec<u8>,
+}
+
+thread_local! {
+    // AppKit の操作はすべてメインスレッドで行うので、状態もメインスレッドに閉じ込める。
+    // 他のスレッドからは常に空に見えるため、Send を偽る必要もロックも要らない
+    static APP_STATE: RefCell<Option<AppState>> = const { RefCell::new(None) };
+    // `stats --resources` の応答。コマンド側のランループ(メインスレッド)でだけ読み書きする
+    static STATS_RESPONSE: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+static PREPARED_CLIP: Mutex<Option<PreparedClip>> = Mutex::new(None);
+static FETCHED_FAVICON: Mutex<Option<FetchedFavicon>> = Mutex::new(None);
+// 履歴を扱うスレッドで読んだ、ホットキーでさかのぼる履歴
+static LOADED_HISTORY_CYCLE: Mutex<Option<Vec<HistoryEntry>>> = Mutex::new(None);
+// --no-onboarding で起動したときは初回起動の案内を出さない
+static ONBOARDING_DISABLED: AtomicBool = AtomicBool::new(false);
+// タイマーなどで起こされた回数。`stats --resources` の wakeups_per_sec に使う
+static WAKEUP_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// メインスレッドの状態を借りて `f` を実行する。起動前やメインスレッド以外では `None` を返す。
+fn with_app_state<R>(f: impl FnOnce(&mut AppState) -> R) -> Option<R> {
+    APP_STATE.with(|app_state| app_state.borrow_mut().as_mut().map(f))
+}
+
+pub fn main() {
+    if handle_cli_flags() {
+        return;
+    }
+
+    unsafe {
+        let app: *mut AnyObject = msg_send![class!(NSApplication), sharedApplication];
+        let _: bool = msg_send![app, setActivationPolicy: 1isize];
+
+        let delegate_class = get_delegate_class();
+        let delegate: *mut AnyObject = msg_send![delegate_class, new];
+        let () = msg_send![app, setDelegate: delegate];
+        let () = msg_send![app, run];
+    }
+}
+
+fn handle_config_command<I: Iterator<Item = String>>(args: &mut I) -> bool {
+    let path = match config_file_path() {
+        Ok(path) => path,
+        Err(error) => {
+            eprintln!("{error}");
+            std::process::exit(1);
+        }
+    };
+    let Some(cmd) = args.next() else {
+        eprintln!("Usage: cliip-show --config <path|show|init|set>");
+        std::process::exit(2);
+    };
+
+    match cmd.as_str() {
+        "path" => {
+            if args.next().is_some() {
+                eprintln!("Usage: cliip-show --config path");
+                std::process::exit(2);
+            }
+            println!("{}", path.display());
+            true
+        }
+        "show" => {
+            if args.next().is_some() {
+                eprintln!("Usage: cliip-show --config show");
+                std::process::exit(2);
+            }
+            println!("config_path = {}", path.display());
+            let (config, loaded_from_file) = match load_config_file(&path) {
+                Ok(result) => result,
+                Err(error) => {
+                    eprintln!("{error}");
+                    std::process::exit(1);
+                }
+            };
+            if loaded_from_file {
+                println!("config_file = exists");
+                println!("[saved]");
+                if let Some(value) = config.display.poll_interval_secs {
+                    println!("poll_interval_secs = {}", value);
+                }
+                if let Some(value) = config.display.hud_duration_secs {
+                    println!("hud_duration_secs = {}", value);
+                }
+                if let Some(value) = config.display.hud_fade_duration_secs {
+                    println!("hud_fade_duration_secs = {}", value);
+                }
+                if let Some(value) = config.display.max_chars_per_line {
+                    println!("max_chars_per_line = {}", value);
+                }
+                if let Some(value) = config.display.max_lines {
+                    println!("max_lines = {}", value);
+                }
+                if let Some(value) = config.display.hud_position {
+                    println!("hud_position = {}", value.as_str());
+                }
+                if let Some(value) = config.display.hud_scale {
+                    println!("hud_scale = {}", value);
+                }
+                if let Some(value) = config.display.hud_background_color {
+                    println!("hud_background_color = {}", value.as_string());
+                }
+                if let Some(value) = config.display.show_language_tag {
+                    println!("show_language_tag = {}", value);
+                }
+                if let Some(value) = config.display.show_hash {
+                    println!("show_hash = {}", value.as_string());
+                }
+                if let Some(value) = config.display.show_diff {
+                    println!("show_diff = {}", value);
+                }
+                if let Some(value) = config.transform.pretty_json {
+                    println!("pretty_json = {}", value);
+                }
+                if let Some(value) = config.transform.decode_jwt {
+                    println!("decode_jwt = {}", value);
+                }
+                if let Some(value) = config.transform.decode_percent {
+                    println!("decode_percent = {}", value);
+                }
+                if let Some(value) = config.transform.force_plain_text {
+                    println!("force_plain_text = {}", value);
+                }
+                if let Some(value) = config.privacy.auto_clear_secs {
+                    println!("auto_clear_secs = {}", value);
+                }
+                if let Some(value) = config.display.poll_idle_after_secs {
+                    println!("poll_idle_after_secs = {}", value);
+                }
+                if let Some(value) = config.display.poll_max_interval_secs {
+                    println!("poll_max_interval_secs = {}", value);
+                }
+                if let Some(value) = config.plugins.enabled {
+                    println!("plugins_enabled = {}", value);
+                }
+                if let Some(value) = config.limits.max_display_bytes {
+                    println!("max_display_bytes = {}", value);
+                }
+                if let Some(value) = config.display.poll_timer_backend {
+                    println!("poll_timer_backend = {}", value.as_str());
+                }
+                if let Some(value) = config.accessibility.announce {
+                    println!("accessibility_announce = {}", value);
+                }
+                if let Some(value) = config.accessibility.speak {
+                    println!("accessibility_speak = {}", value);
+                }
+                if let Some(value) = config.accessibility.speak_max_chars {
+                    println!("accessibility_speak_max_chars = {}", value);
+                }
+                if let Some(value) = config.accessibility.speak_rate {
+                    println!("accessibility_speak_rate = {}", value);
+                }
+                if let Some(value) = config.schedule.quiet_hours {
+                    println!("quiet_hours = {}", value.as_string());
+                }
+                if let Some(value) = config.schedule.quiet_mode {
+                    println!("quiet_mode = {}", value.as_str());
+                }
+                if let Some(value) = config.display.show_time {
+                    println!("show_time = {}", value);
+                }
+                if let Some(value) = config.display.show_counter {
+                    println!("show_counter = {}", value);
+                }
+                if let Some(value) = config.display.show_elapsed {
+                    println!("show_elapsed = {}", value);
+                }
+                if let Some(value) = config.display.show_favicon {
+                    println!("show_favicon = {}", value);
+                }
+                if let Some(value) = config.display.show_qr_for_urls {
+                    println!("show_qr_for_urls = {}", value);
+                }
+                if let Some(value) = config.display.show_path_metadata {
+                    println!("show_path_metadata = {}", value);
+                }
+                if let Some(value) = config.display.render_markdown {
+                    println!("render_markdown = {}", value);
+                }
+                if let Some(value) = config.display.concealed_behavior {
+                    println!("concealed_behavior = {}", value.as_str());
+                }
+                if let Some(value) = config.display.ignore_transient {
+                    println!("ignore_transient = {}", value);
+                }
+                if let Some(value) = config.display.show_type_badges {
+                    println!("show_type_badges = {}", value);
+                }
+                if let Some(value) = config.display.show_counts {
+                    println!("show_counts = {}", value);
+                }
+                if let Some(value) = config.display.show_source_app {
+                    println!("show_source_app = {}", value);
+                }
+                if let Some(value) = config.history.enabled {
+                    println!("history_enabled = {}", value);
+                }
+                if let Some(value) = config.history.max_entries {
+                    println!("history_max_entries = {}", value);
+                }
+                if let Some(value) = config.history.dedup_consecutive {
+                    println!("dedup_consecutive = {}", value.as_str());
+                }
+                if let Some(value) = config.history.encrypt {
+                    println!("history_encrypt = {}", value);
+                }
+                if let Some(value) = config.history.max_age_days {
+                    println!("history_max_age_days = {}", value);
+                }
+                if let Some(value) = config.history.max_bytes {
+                    println!("history_max_bytes = {}", value);
+                }
+                if let Some(value) = config.display.diff_mode {
+                    println!("diff_mode = {}", value.as_str());
+                }
+                if let Some(value) = config.history.hotkey {
+                    println!("history_hotkey = {}", value.as_string());
+                }
+                if let Some(value) = config.history.backend {
+                    println!("history_backend = {}", value.as_str());
+                }
+                if let Some(value) = config.transform.append_mode {
+                    println!("append_mode = {}", value);
+                }
+                if let Some(value) = config.transform.append_hotkey {
+                    println!("append_hotkey = {}", value.as_string());
+                }
+                if let Some(value) = config.display.hud_text_color {
+                    println!("hud_text_color = {}", value.as_string());
+                }
+                if let Some(value) = config.display.hud_material {
+                    println!("hud_material = {}", value.as_str());
+                }
+                if let Some(value) = &config.display.hud_theme {
+                    println!("hud_theme = {value}");
+                }
+                if let Some(value) = config.display.appearance {
+                    println!("appearance = {}", value.as_str());
+                }
+                if let Some(value) = &config.display.hud_font {
+                    println!("hud_font = {value}");
+                }
+                if let Some(value) = config.display.hud_font_size {
+                    println!("hud_font_size = {}", value);
+                }
+                if let Some(value) = config.display.hud_line_spacing {
+                    println!("hud_line_spacing = {}", value);
+                }
+                if let Some(value) = &config.display.hud_icon {
+                    println!("hud_icon = {value}");
+                }
+            } else {
+                println!("config_file = not_found");
+            }
+            println!("[effective]");
+            let effective =
+                apply_env_overrides(apply_config_file(default_display_settings(), &config));
+            print_effective_settings(effective);
+            true
+        }
+        "init" => {
+            let mut force = false;
+            if let Some(arg) = args.next() {
+                if arg == "--force" {
+                    force = true;
+                    if args.next().is_some() {
+                        eprintln!("Usage: cliip-show --config init [--force]");
+                        std::process::exit(2);
+                    }
+                } else {
+                    eprintln!("Usage: cliip-show --config init [--force]");
+                    std::process::exit(2);
+                }
+            }
+
+            if !force && path.exists() {
+                eprintln!(
+                    "config file already exists: {} (use --force to overwrite)",
+                    path.display()
+                );
+                std::process::exit(2);
+            }
+
+            let config = settings_to_config_file(default_display_settings());
+            if let Err(error) = save_config_file(&path, &config) {
+                eprintln!("{error}");
+                std::process::exit(1);
+            }
+            println!("initialized config: {}", path.display());
+            true
+        }
+        "set" => {
+            let Some(key_raw) = args.next() else {
+                eprintln!("Usage: cliip-show --config set <key> <value>");
+                eprintln!(
+                    "Available keys: poll_interval_secs, hud_duration_secs, hud_fade_duration_secs, max_chars_per_line, max_lines, hud_position, hud_scale, hud_background_color, show_language_tag, show_hash, show_diff, pretty_json, decode_jwt, decode_percent, force_plain_text, auto_clear_secs, poll_idle_after_secs, poll_max_interval_secs, plugins_enabled, max_display_bytes, poll_timer_backend, accessibility_announce, accessibility_speak, accessibility_speak_max_chars, accessibility_speak_rate, quiet_hours, quiet_mode, show_time, show_counter, show_elapsed, show_favicon, show_qr_for_urls, show_path_metadata, render_markdown, concealed_behavior, ignore_transient, show_type_badges, show_counts, show_source_app, history_enabled, history_max_entries, dedup_consecutive, history_encrypt, history_max_age_days, history_max_bytes, diff_mode, history_hotkey, history_backend, append_mode, append_hotkey, hud_text_color, hud_material, hud_theme, appearance, hud_font, hud_font_size, hud_line_spacing, hud_icon"
+                );
+                std::process::exit(2);
+            };
+            let Some(value_raw) = args.next() else {
+                eprintln!("Usage: cliip-show --config set <key> <value>");
+                std::process::exit(2);
+            };
+            if args.next().is_some() {
+                eprintln!("Usage: cliip-show --config set <key> <value>");
+                std::process::exit(2);
+            }
+            let Some(key) = parse_config_key(key_raw.trim()) else {
+                eprintln!(
+                    "Unknown key: {key_raw}. Available keys: poll_interval_secs, hud_duration_secs, hud_fade_duration_secs, max_chars_per_line, max_lines, hud_position, hud_scale, hud_background_color, show_language_tag, show_hash, show_diff, pretty_json, decode_jwt, decode_percent, force_plain_text, auto_clear_secs, poll_idle_after_secs, poll_max_interval_secs, plugins_enabled, max_display_bytes, poll_timer_backend, accessibility_announce, accessibility_speak, accessibility_speak_max_chars, accessibility_speak_rate, quiet_hours, quiet_mode, show_time, show_counter, show_elapsed, show_favicon, show_qr_for_urls, show_path_metadata, render_markdown, concealed_behavior, ignore_transient, show_type_badges, show_counts, show_source_app, history_enabled, history_max_entries, dedup_consecutive, history_encrypt, history_max_age_days, history_max_bytes, diff_mode, history_hotkey, history_backend, append_mode, append_hotkey, hud_text_color, hud_material, hud_theme, appearance, hud_font, hud_font_size, hud_line_spacing, hud_icon"
+                );
+                std::process::exit(2);
+            };
+
+            let mut config = match load_config_file(&path) {
+                Ok((config, _)) => config,
+                Err(error) => {
+                    eprintln!("{error}");
+                    std::process::exit(1);
+                }
+            };
+
+            let warning = match set_config_value(&mut config, key, value_raw.trim()) {
+                Ok(warning) => warning,
+                Err(error) => {
+                    eprintln!("{error}");
+                    std::process::exit(2);
+                }
+            };
+            if let Err(error) = save_config_file(&path, &config) {
+                eprintln!("{error}");
+                std::process::exit(1);
+            }
+            if let Some(warning) = warning {
+                eprintln!("warning: {warning}");
+            }
+            if let (ConfigKey::HudFont, Some(font)) = (key, &config.display.hud_font) {
+                if !unsafe { hud_font_is_available(font) } {
+                    eprintln!("warning: font not found: {font} (the HUD falls back to Menlo)");
+                }
+            }
+            if let (ConfigKey::HudIcon, Some(icon)) = (key, &config.display.hud_icon) {
+                if is_sf_symbol_name(icon) && !unsafe { hud_symbol_is_available(icon) } {
+                    eprintln!(
+                        "warning: SF Symbol not found: {icon} (the HUD falls back to {DEFAULT_HUD_ICON})"
+                    );
+                }
+            }
+            println!("updated config: {}", path.display());
+            unsafe { post_control_notification(CONFIG_CHANGED_NOTIFICATION, None) };
+            println!("hint: a running cliip-show applies display changes immediately; restart the service for plugins_enabled: brew services restart cliip-show");
+            println!("[effective]");
+            let effective =
+                apply_env_overrides(apply_config_file(default_display_settings(), &config));
+            print_effective_settings(effective);
+            true
+        }
+        unknown => {
+            eprintln!("Unknown --config command: {unknown}");
+            eprintln!("Usage: cliip-show --config <path|show|init|set>");
+            std::process::exit(2);
+        }
+    }
+}
+
+fn handle_cli_flags() -> bool {
+    let mut args = std::env::args();
+    let _program = args.next();
+    let Some(flag) = args.next() else {
+        return false;
+    };
+
+    match flag.as_str() {
+        "--no-onboarding" => {
+            ONBOARDING_DISABLED.store(true, Ordering::Relaxed);
+            false
+        }
+        "--version" | "-V" | "-v" => {
+            println!("{}", env!("CARGO_PKG_VERSION"));
+            true
+        }
+        "--help" | "-h" => {
+            let mut help = String::new();
+            let _ = writeln!(help, "cliip-show {}", env!("CARGO_PKG_VERSION"));
+            let _ = writeln!(help, "clipboard HUD resident app for macOS");
+            let _ = writeln!(help);
+            let _ = writeln!(help, "Options:");
+            let _ = writeln!(help, "  -h, --help       Print help");
+            let _ = writeln!(help, "  -v, -V, --version    Print version");
+            let _ = writeln!(
+                help,
+                "  --no-onboarding  Start without the first-run HUD showing the config path"
+            );
+            let _ = writeln!(
+                help,
+                "  --status         Print whether cliip-show is running and recording history"
+            );
+            let _ = writeln!(
+                help,
+                "  --render-hud-png --text <TEXT|-> | --text-file <PATH> --output <PATH> [--scale-factor <1.0-3.0>] [--deterministic] [--offscreen] [--config-path <PATH>] [--hud-scale <N>] [--hud-position <POS>] [--hud-background-color <COLOR>] [--max-lines <N>] [--max-chars-per-line <N>]    Render HUD snapshot PNG and exit"
+            );
+            let _ = writeln!(
+                help,
+                "  --layout-json --text <TEXT|-> | --text-file <PATH> [--config-path <PATH>] [--hud-scale <N>] [--hud-position <POS>] [--hud-background-color <COLOR>] [--max-lines <N>] [--max-chars-per-line <N>]    Print HUD layout metrics, screen frame and origin as JSON without rendering"
+            );
+            let _ = writeln!(
+                help,
+                "  --diff-png --baseline <PATH> --current <PATH> --output <PATH> [--backend appkit|rust] [--threshold <N%|Npx>] [--fail-fast] [--summary-json <PATH|->] [--composite <PATH>] [--ignore-region <X,Y,W,H>]... [--mask <PATH>] [--metric channel|deltae] [--max-delta <N>] [--resize-policy fail|pad|crop]    Generate visual diff PNG and exit (0: within threshold, 1: over, 2: error)"
+            );
+            let _ = writeln!(
+                help,
+                "  --config <path|show|init|set ...>    Manage persistent settings file"
+            );
+            let _ = writeln!(
+                help,
+                "  stats --resources    Show RSS, CPU time and wakeups of the running app"
+            );
+            let _ = writeln!(
+                help,
+                "  stats [--since <...>] [--json]    Summarize history: copies per day, top apps, average length, busiest hours"
+            );
+            let _ = writeln!(
+                help,
+                "  snapshot test --manifest <PATH> --baseline-dir <DIR> [--artifact-dir <DIR>] [--threshold <N%|Npx>] [--scale-factor <N>]    Render manifest cases and diff against baselines (0: pass, 1: regression, 2: error)"
+            );
+            let _ = writeln!(
+                help,
+                "  snapshot update --manifest <PATH> --baseline-dir <DIR> [--only <CASE>]... [--scale-factor <N>]    Re-render manifest cases as the new baselines"
+            );
+            let _ = writeln!(
+                help,
+                "  history list [--limit <N>] [--since <30m|12h|7d|2w|YYYY-MM-DD|RFC3339>] [--json]    Print recorded clipboard history, newest first"
+            );
+            let _ = writeln!(
+                help,
+                "  history search <QUERY> [--limit <N>] [--since <...>] [--json]    Print history entries containing QUERY (case-insensitive)"
+            );
+            let _ = writeln!(
+                help,
+                "  history pick [--limit <N>] [--since <...>]    Choose a history entry with fzf (or CLIIP_SHOW_PICKER) and copy it"
+            );
+            let _ = writeln!(
+                help,
+                "  history copy <ID> | --index <N>    Copy a history entry by id (or Nth newest) and show it in the HUD"
+            );
+            let _ = writeln!(
+                help,
+                "  history pin|unpin <ID>    Keep a history entry past history_max_entries and list it first"
+            );
+            let _ = writeln!(
+                help,
+                "  history prune             Delete entries over history_max_entries/max_age_days/max_bytes now"
+            );
+            let _ = writeln!(
+                help,
+                "  history export [--format json|csv] [--output <PATH>]    Export history with timestamps, source app and character counts"
+            );
+            let _ = writeln!(
+                help,
+                "  history import <PATH>     Merge an exported JSON/CSV file into history, skipping duplicate text"
+            );
+            let _ = writeln!(
+                help,
+                "  history render <ID> | --index <N> --output <PATH> [--scale-factor <1.0-3.0>] [--deterministic] [--offscreen] [--config-path <PATH>] [--hud-scale <N>] ...    Render a past history entry as a HUD snapshot PNG"
+            );
+            let _ = writeln!(
+                help,
+                "  history pause|resume      Stop or restart recording in the running cliip-show; the HUD keeps working"
+            );
+            let _ = writeln!(help);
+            let _ = writeln!(help, "Config commands (persistent settings):");
+            let _ = writeln!(help, "  cliip-show --config init");
+            let _ = writeln!(help, "  cliip-show --config init --force");
+            let _ = writeln!(help, "  cliip-show --config show");
+            let _ = writeln!(help, "  cliip-show --config set hud_duration_secs 2.5");
+            let _ = writeln!(help, "  cliip-show --config set max_lines 3");
+            let _ = writeln!(help, "  cliip-show --config set hud_position top");
+            let _ = writeln!(help, "  cliip-show --config set hud_scale 1.2");
+            let _ = writeln!(help, "  cliip-show --config set hud_background_color blue");
+            let _ = writeln!(help, "  cliip-show --config set show_language_tag true");
+            let _ = writeln!(help, "  cliip-show --config set show_hash sha256-8");
+            let _ = writeln!(help, "  cliip-show --config set show_diff true");
+            let _ = writeln!(help, "  cliip-show --config set pretty_json true");
+            let _ = writeln!(help, "  cliip-show --config set decode_jwt true");
+            let _ = writeln!(help, "  cliip-show --config set decode_percent true");
+            let _ = writeln!(help, "  cliip-show --config set force_plain_text true");
+            let _ = writeln!(help, "  cliip-show --config set auto_clear_secs 20");
+            let _ = writeln!(help, "  cliip-show --config set poll_idle_after_secs 300");
+            let _ = writeln!(help, "  cliip-show --config set poll_max_interval_secs 3.0");
+            let _ = writeln!(help, "  cliip-show --config set plugins_enabled true");
+            let _ = writeln!(help, "  cliip-show --config set max_display_bytes 1048576");
+            let _ = writeln!(
+                help,
+                "  cliip-show --config set poll_timer_backend dispatch"
+            );
+            let _ = writeln!(
+                help,
+                "  cliip-show --config set accessibility_announce true"
+            );
+            let _ = writeln!(help, "  cliip-show --config set accessibility_speak true");
+            let _ = writeln!(
+                help,
+                "  cliip-show --config set accessibility_speak_max_chars 80"
+            );
+            let _ = writeln!(
+                help,
+                "  cliip-show --config set accessibility_speak_rate 220"
+            );
+            let _ = writeln!(help, "  cliip-show --config set quiet_hours 22:00-07:00");
+            let _ = writeln!(help, "  cliip-show --config set quiet_mode minimal");
+            let _ = writeln!(help, "  cliip-show --config set show_time true");
+            let _ = writeln!(help, "  cliip-show --config set show_counter true");
+            let _ = writeln!(help, "  cliip-show --config set show_elapsed true");
+            let _ = writeln!(help, "  cliip-show --config set show_favicon true");
+            let _ = writeln!(help, "  cliip-show --config set show_qr_for_urls true");
+            let _ = writeln!(help, "  cliip-show --config set show_path_metadata false");
+            let _ = writeln!(help, "  cliip-show --config set render_markdown true");
+            let _ = writeln!(help, "  cliip-show --config set concealed_behavior skip");
+            let _ = writeln!(help, "  cliip-show --config set ignore_transient false");
+            let _ = writeln!(help, "  cliip-show --config set show_type_badges true");
+            let _ = writeln!(help, "  cliip-show --config set show_counts true");
+            let _ = writeln!(help, "  cliip-show --config set show_source_app true");
+            let _ = writeln!(help, "  cliip-show --config set history_enabled true");
+            let _ = writeln!(help, "  cliip-show --config set history_max_entries 1000");
+            let _ = writeln!(help, "  cliip-show --config set dedup_consecutive quiet");
+            let _ = writeln!(help, "  cliip-show --config set history_encrypt true");
+            let _ = writeln!(help, "  cliip-show --config set history_max_age_days 30");
+            let _ = writeln!(help, "  cliip-show --config set history_max_bytes 10485760");
+            let _ = writeln!(help, "  cliip-show --config set diff_mode lines");
+            let _ = writeln!(help, "  cliip-show --config set history_hotkey cmd+shift+v");
+            let _ = writeln!(help, "  cliip-show --config set history_backend sqlite");
+            let _ = writeln!(help, "  cliip-show --config set append_mode true");
+            let _ = writeln!(help, "  cliip-show --config set append_hotkey cmd+shift+a");
+            let _ = writeln!(help, "  cliip-show --config set hud_text_color '#1E1E2E'");
+            let _ = writeln!(help, "  cliip-show --config set hud_material hud");
+            let _ = writeln!(help, "  cliip-show --config set hud_theme solarized");
+            let _ = writeln!(help, "  cliip-show --config set appearance auto");
+            let _ = writeln!(help, "  cliip-show --config set hud_font Monaco");
+            let _ = writeln!(help, "  cliip-show --config set hud_font_size 15");
+            let _ = writeln!(help, "  cliip-show --config set hud_line_spacing 4");
+            let _ = writeln!(help, "  cliip-show --config set hud_icon doc.on.clipboard");
+            let _ = writeln!(help);
+            let _ = writeln!(help, "Config keys:");
+            let _ = writeln!(help, "  poll_interval_secs      default=0.3 (0.05 - 5.0)");
+            let _ = writeln!(help, "  hud_duration_secs       default=1.0 (0.1 - 10.0)");
+            let _ = writeln!(help, "  hud_fade_duration_secs  default=0.3 (0.0 - 2.0)");
+            let _ = writeln!(help, "  max_chars_per_line      default=100 (1 - 500)");
+            let _ = writeln!(help, "  max_lines               default=5 (1 - 20)");
+            let _ = writeln!(
+                help,
+                "  hud_position            default=top (top|center|bottom)"
+            );
+            let _ = writeln!(help, "  hud_scale               default=1.1 (0.5 - 2.0)");
+            let _ = writeln!(
+                help,
+                "  hud_background_color    default=default (default|yellow|blue|green|red|purple|#RRGGBB|#RRGGBBAA)"
+            );
+            let _ = writeln!(help, "  show_language_tag       default=false (true|false)");
+            let _ = writeln!(
+                help,
+                "  show_hash               default=none (none|sha256-4 .. sha256-64)"
+            );
+            let _ = writeln!(help, "  show_diff               default=false (true|false)");
+            let _ = writeln!(
+                help,
+                "  pretty_json             default=false (true|false) [transform]"
+            );
+            let _ = writeln!(
+                help,
+                "  decode_jwt              default=false (true|false) [transform]"
+            );
+            let _ = writeln!(
+                help,
+                "  decode_percent          default=false (true|false) [transform]"
+            );
+            let _ = writeln!(
+                help,
+                "  force_plain_text        default=false (true|false) [transform]"
+            );
+            let _ = writeln!(
+                help,
+                "  auto_clear_secs         default=0 (0 - 600, 0 disables) [privacy]"
+            );
+            let _ = writeln!(
+                help,
+                "  poll_idle_after_secs    default=120.0 (0.0 - 3600.0, 0.0 disables backoff)"
+            );
+            let _ = writeln!(help, "  poll_max_interval_secs  default=2.0 (0.05 - 5.0)");
+            let _ = writeln!(
+                help,
+                "  plugins_enabled         default=false (true|false) [plugins]"
+            );
+            let _ = writeln!(
+                help,
+                "  max_display_bytes       default=1048576 (1024..=67108864) [limits]"
+            );
+            let _ = writeln!(
+                help,
+                "  poll_timer_backend      default=nstimer (nstimer|dispatch)"
+            );
+            let _ = writeln!(
+                help,
+                "  accessibility_announce  default=false (true|false) [accessibility]"
+            );
+            let _ = writeln!(
+                help,
+                "  accessibility_speak     default=false (true|false) [accessibility]"
+            );
+            let _ = writeln!(
+                help,
+                "  accessibility_speak_max_chars default=200 (1..=2000) [accessibility]"
+            );
+            let _ = writeln!(help, "  accessibility_speak_rate default=0 (0 - 500 words/min, 0 uses the system rate) [accessibility]");
+            let _ = writeln!(help, "  quiet_hours             default=none (comma-separated [mon-fri ]HH:MM-HH:MM) [schedule]");
+            let _ = writeln!(
+                help,
+                "  quiet_mode              default=suppress (suppress|minimal) [schedule]"
+            );
+            let _ = writeln!(help, "  show_time               default=false (true|false)");
+            let _ = writeln!(help, "  show_counter            default=false (true|false)");
+            let _ = writeln!(help, "  show_elapsed            default=false (true|false)");
+            let _ = writeln!(help, "  show_favicon            default=false (true|false)");
+            let _ = writeln!(help, "  show_qr_for_urls        default=false (true|false)");
+            let _ = writeln!(help, "  show_path_metadata      default=true (true|false)");
+            let _ = writeln!(help, "  render_markdown         default=false (true|false)");
+            let _ = writeln!(help, "  concealed_behavior      default=mask (mask|skip)");
+            let _ = writeln!(help, "  ignore_transient        default=true (true|false)");
+            let _ = writeln!(help, "  show_type_badges        default=false (true|false)");
+            let _ = writeln!(help, "  show_counts             default=false (true|false)");
+            let _ = writeln!(help, "  show_source_app         default=false (true|false)");
+            let _ = writeln!(
+                help,
+                "  history_enabled         default=false (true|false) [history]"
+            );
+            let _ = writeln!(
+                help,
+                "  history_max_entries     default=1000 (1..=100000) [history]"
+            );
+            let _ = writeln!(
+                help,
+                "  dedup_consecutive       default=history (off|history|quiet) [history]"
+            );
+            let _ = writeln!(
+                help,
+                "  history_encrypt         default=false (true|false) [history]"
+            );
+            let _ = writeln!(
+                help,
+                "  history_max_age_days    default=0 (0..=36500, 0 disables) [history]"
+            );
+            let _ = writeln!(
+                help,
+                "  history_max_bytes       default=0 (0..=1073741824, 0 disables) [history]"
+            );
+            let _ = writeln!(
+                help,
+                "  diff_mode               default=words (words|lines)"
+            );
+            let _ = writeln!(
+                help,
+                "  history_hotkey          default=none (e.g. cmd+shift+v) [history]"
+            );
+            let _ = writeln!(
+                help,
+                "  history_backend         default=jsonl (jsonl|sqlite|log) [history]"
+            );
+            let _ = writeln!(
+                help,
+                "  append_mode             default=false (true|false) [transform]"
+            );
+            let _ = writeln!(
+                help,
+                "  append_hotkey           default=none (e.g. cmd+shift+a) [transform]"
+            );
+            let _ = writeln!(
+                help,
+                "  hud_text_color          default=white (white|black|#RRGGBB|#RRGGBBAA)"
+            );
+            let _ = writeln!(
+                help,
+                "  hud_material            default=none (none|hud|popover|sidebar)"
+            );
+            let _ = writeln!(
+                help,
+                "  hud_theme               default=none (none|<name of themes/<name>.toml>)"
+            );
+            let _ = writeln!(
+                help,
+                "  appearance              default=dark (auto|light|dark)"
+            );
+            let _ = writeln!(
+                help,
+                "  hud_font                default=Menlo (font name|default)"
+            );
+            let _ = writeln!(help, "  hud_font_size           default=18 (8.0..=40.0)");
+            let _ = writeln!(help, "  hud_line_spacing        default=0 (0.0..=20.0)");
+            let _ = writeln!(
+                help,
+                "  hud_icon                default=📋 (emoji|SF Symbol name|default)"
+            );
+            let _ = writeln!(help);
+            let _ = writeln!(help, "For Homebrew service:");
+            let _ = writeln!(help, "  brew services restart cliip-show");
+            let _ = writeln!(help);
+            let _ = writeln!(help, "Persistent config file:");
+            let _ = writeln!(
+                help,
+                "  default: ~/Library/Application Support/cliip-show/config.toml"
+            );
+            let _ = writeln!(help, "  override path via: CLIIP_SHOW_CONFIG_PATH");
+            let _ = writeln!(help);
+            let _ = writeln!(help, "Display settings via env vars (override file):");
+            let _ = writeln!(
+                help,
+                "  CLIIP_SHOW_POLL_INTERVAL_SECS   Poll interval seconds (0.05 - 5.0)"
+            );
+            let _ = writeln!(
+                help,
+                "  CLIIP_SHOW_HUD_DURATION_SECS    HUD visible seconds (0.1 - 10.0)"
+            );
+            let _ = writeln!(
+                help,
+                "  CLIIP_SHOW_MAX_CHARS_PER_LINE   Max chars per line (1 - 500)"
+            );
+            let _ = writeln!(
+                help,
+                "  CLIIP_SHOW_MAX_LINES            Max lines in HUD (1 - 20)"
+            );
+            let _ = writeln!(
+                help,
+                "  CLIIP_SHOW_HUD_POSITION         HUD position (top|center|bottom)"
+            );
+            let _ = writeln!(
+                help,
+                "  CLIIP_SHOW_HUD_SCALE            HUD scale (0.5 - 2.0)"
+            );
+            let _ = writeln!(
+                help,
+                "  CLIIP_SHOW_HUD_BACKGROUND_COLOR HUD background color (default|yellow|blue|green|red|purple|#RRGGBB|#RRGGBBAA)"
+            );
+            let _ = writeln!(
+                help,
+                "  CLIIP_SHOW_SHOW_LANGUAGE_TAG    Show detected language tag (true|false)"
+            );
+            let _ = writeln!(
+                help,
+                "  CLIIP_SHOW_SHOW_HASH            Content hash in footer (none|sha256-N)"
+            );
+            let _ = writeln!(
+                help,
+                "  CLIIP_SHOW_SHOW_DIFF            Highlight diff against previous clip (true|false)"
+            );
+            let _ = writeln!(
+                help,
+                "  CLIIP_SHOW_PRETTY_JSON          Pretty-print JSON clips (true|false)"
+            );
+            let _ = writeln!(
+                help,
+                "  CLIIP_SHOW_DECODE_JWT           Preview decoded JWT claims (true|false)"
+            );
+            let _ = writeln!(
+                help,
+                "  CLIIP_SHOW_DECODE_PERCENT       Show percent-decoded URLs (true|false)"
+            );
+            let _ = writeln!(
+                help,
+                "  CLIIP_SHOW_FORCE_PLAIN_TEXT     Rewrite clips as plain text only (true|false)"
+            );
+            let _ = writeln!(
+                help,
+                "  CLIIP_SHOW_AUTO_CLEAR_SECS      Clear sensitive clips after N seconds (0 - 600)"
+            );
+            let _ = writeln!(
+                help,
+                "  CLIIP_SHOW_POLL_IDLE_AFTER_SECS Idle time before polling slows down (0.0 - 3600.0)"
+            );
+            let _ = writeln!(
+                help,
+                "  CLIIP_SHOW_POLL_MAX_INTERVAL_SECS Longest poll interval while idle (0.05 - 5.0)"
+            );
+            let _ = writeln!(
+                help,
+                "  CLIIP_SHOW_PLUGINS_ENABLED      Run content transformer plugins (true|false)"
+            );
+            let _ = writeln!(help, "  CLIIP_SHOW_PLUGINS_DIR          Plugin directory (default: <config dir>/plugins)");
+            let _ = writeln!(help, "  CLIIP_SHOW_MAX_DISPLAY_BYTES    Max clipboard bytes read for display (1024..=67108864)");
+            let _ = writeln!(
+                help,
+                "  CLIIP_SHOW_POLL_TIMER_BACKEND   Poll timer backend (nstimer|dispatch)"
+            );
+            let _ = writeln!(help, "  CLIIP_SHOW_ACCESSIBILITY_ANNOUNCE Announce copied text to VoiceOver (true|false)");
+            let _ = writeln!(
+                help,
+                "  CLIIP_SHOW_ACCESSIBILITY_SPEAK  Speak short copied text aloud (true|false)"
+            );
+            let _ = writeln!(
+                help,
+                "  CLIIP_SHOW_ACCESSIBILITY_SPEAK_MAX_CHARS Longest clip to speak, in characters"
+            );
+            let _ = writeln!(help, "  CLIIP_SHOW_ACCESSIBILITY_SPEAK_RATE Speech rate in words per minute (0 = system)");
+            let _ = writeln!(help, "  CLIIP_SHOW_ACCESSIBILITY_SPEAK_VOICE Speech voice identifier (default: system voice)");
+            let _ = writeln!(help, "  CLIIP_SHOW_QUIET_HOURS          Quiet hours, comma-separated (e.g. mon-fri 22:00-07:00)");
+            let _ = writeln!(
+                help,
+                "  CLIIP_SHOW_QUIET_MODE           HUD during quiet hours (suppress|minimal)"
+            );
+            let _ = writeln!(
+                help,
+                "  CLIIP_SHOW_SHOW_TIME            Show the copy time in the footer (true|false)"
+            );
+            let _ = writeln!(help, "  CLIIP_SHOW_SHOW_COUNTER         Show how many clips were copied today (true|false)");
+            let _ = writeln!(
+                help,
+                "  CLIIP_SHOW_SHOW_ELAPSED         Show time since the previous copy (true|false)"
+            );
+            let _ = writeln!(help, "  CLIIP_SHOW_SHOW_FAVICON         Fetch and show the site favicon for copied URLs (true|false)");
+            let _ = writeln!(
+                help,
+                "  CLIIP_SHOW_SHOW_QR_FOR_URLS     Show a QR code beside copied URLs (true|false)"
+            );
+            let _ = writeln!(help, "  CLIIP_SHOW_SHOW_PATH_METADATA   Show icon, size and modified date for copied file paths (true|false)");
+            let _ = writeln!(help, "  CLIIP_SHOW_RENDER_MARKDOWN      Render Markdown bold, code spans and headings in the HUD (true|false)");
+            let _ = writeln!(help, "  CLIIP_SHOW_CONCEALED_BEHAVIOR   Concealed (password) copies: mask or skip the HUD (mask|skip)");
+            let _ = writeln!(help, "  CLIIP_SHOW_IGNORE_TRANSIENT     Skip the HUD for transient writes by other clipboard tools (true|false)");
+            let _ = writeln!(help, "  CLIIP_SHOW_SHOW_TYPE_BADGES     List the pasteboard content types in the footer (true|false)");
+            let _ = writeln!(help, "  CLIIP_SHOW_SHOW_COUNTS          Show character, word and line counts in the footer (true|false)");
+            let _ = writeln!(help, "  CLIIP_SHOW_SHOW_SOURCE_APP      Show the app that was frontmost when the clip changed (true|false)");
+            let _ = writeln!(help, "  CLIIP_SHOW_HISTORY_ENABLED      Record copied text to the history file (true|false)");
+            let _ = writeln!(help, "  CLIIP_SHOW_HISTORY_MAX_ENTRIES  Max entries kept in the history file (1..=100000)");
+            let _ = writeln!(help, "  CLIIP_SHOW_DEDUP_CONSECUTIVE    Repeated copies of the same text: off, history (merge entries) or quiet (also skip the HUD)");
+            let _ = writeln!(help, "  CLIIP_SHOW_HISTORY_ENCRYPT      Encrypt history entries with a key kept in the Keychain (true|false)");
+            let _ = writeln!(help, "  CLIIP_SHOW_HISTORY_MAX_AGE_DAYS Delete history entries older than this many days (0 disables)");
+            let _ = writeln!(help, "  CLIIP_SHOW_HISTORY_MAX_BYTES    Max total size of history entries in bytes (0 disables)");
+            let _ = writeln!(help, "  CLIIP_SHOW_DIFF_MODE            Unit of show_diff: words, or lines for multi-line text");
+            let _ = writeln!(help, "  CLIIP_SHOW_HISTORY_HOTKEY       Global shortcut that cycles back through history (e.g. cmd+shift+v, none)");
+            let _ = writeln!(help, "  CLIIP_SHOW_HISTORY_BACKEND      History storage: jsonl, sqlite for an indexed full-text search, or log for an append-only log");
+            let _ = writeln!(help, "  CLIIP_SHOW_APPEND_MODE          Concatenate successive copies into one pasteboard entry (true|false)");
+            let _ = writeln!(help, "  CLIIP_SHOW_APPEND_HOTKEY        Global shortcut that toggles append mode (e.g. cmd+shift+a, none)");
+            let _ = writeln!(help, "  CLIIP_SHOW_HUD_TEXT_COLOR       HUD text and icon color (white|black|#RRGGBB|#RRGGBBAA)");
+            let _ = writeln!(help, "  CLIIP_SHOW_HUD_MATERIAL         Blurred system material behind the HUD: none, hud, popover or sidebar");
+            let _ = writeln!(help, "  CLIIP_SHOW_HUD_THEME            Theme name in the themes directory (overrides config colors)");
+            let _ = writeln!(help, "  CLIIP_SHOW_APPEARANCE           HUD color scheme: auto (follow macOS), light or dark");
+            let _ = writeln!(help, "  CLIIP_SHOW_HUD_FONT             Font name for the HUD text (falls back to Menlo)");
+            let _ = writeln!(help, "  CLIIP_SHOW_HUD_FONT_SIZE        Font size of the HUD text before hud_scale (8.0..=40.0)");
+            let _ = writeln!(help, "  CLIIP_SHOW_HUD_LINE_SPACING     Extra space between lines of the HUD text before hud_scale (0.0..=20.0)");
+            let _ = writeln!(help, "  CLIIP_SHOW_HUD_ICON             Emoji or SF Symbol name for the HUD icon (falls back to 📋)");
+            print!("{help}");
+            true
+        }
+        "--config" => handle_config_command(&mut args),
+        "--render-hud-png" => {
+            let mut input = HudInputArgs::default();
+            let mut output = RenderOutputArgs::default();
+
+            while let Some(arg) = args.next() {
+                if input.parse_flag(&arg, &mut args) || output.parse_flag(&arg, &mut args) {
+                    continue;
+                }
+                eprintln!("Unknown option for --render-hud-png: {arg}");
+                std::process::exit(2);
+            }
+
+            let output_path = output.output_path("--render-hud-png");
+            let (text, settings) = input.into_text_and_settings();
+            if let Err(error) = render_hud_png(&text, &output_path, settings, output.options) {
+                eprintln!("{error}");
+                std::process::exit(1);
+            }
+            true
+        }
+        "--layout-json" => {
+            let mut input = HudInputArgs::default();
+            while let Some(arg) = args.next() {
+                if !input.parse_flag(&arg, &mut args) {
+                    eprintln!("Unknown option for --layout-json: {arg}");
+                    std::process::exit(2);
+                }
+            }
+
+            let (text, settings) = input.into_text_and_settings();
+            let placement = match measure_hud_placement(&text, settings) {
+                Ok(placement) => placement,
+                Err(error) => {
+                    eprintln!("{error}");
+                    std::process::exit(1);
+                }
+            };
+            match serde_json::to_string_pretty(&placement.to_json()) {
+                Ok(json) => println!("{json}"),
+                Err(error) => {
+                    eprintln!("failed to encode layout: {error}");
+                    std::process::exit(1);
+                }
+            }
+            true
+        }
+        "--status" => handle_status_command(&mut args),
+        "stats" => handle_stats_command(&mut args),
+        "snapshot" => handle_snapshot_command(&mut args),
+        "history" => handle_history_command(&mut args),
+        "--diff-png" => {
+            crate::run_diff_png_command(&mut args, generate_diff_png);
+            true
+        }
+        unknown => {
+            eprintln!("Unknown option: {unknown}");
+            eprintln!("Use --help to see available options.");
+            std::process::exit(2);
+        }
+    }
+}
+
+// --render-hud-png と --layout-json で共通の、表示するテキストと設定の指定
+#[derive(Default)]
+struct HudInputArgs {
+    text: Option<String>,
+    config_path: Option<PathBuf>,
+    overrides: AppConfigFile,
+}
+
+impl HudInputArgs {
+    // 共通のオプションなら値まで読んで true を返す。値の誤りは終了コード 2 で終える
+    fn parse_flag<I: Iterator<Item = String>>(&mut self, flag: &str, args: &mut I) -> bool {
+        if !matches!(
+            flag,
+            "--text"
+                | "--text-file"
+                | "--config-path"
+                | "--hud-scale"
+                | "--hud-position"
+                | "--hud-background-color"
+                | "--max-lines"
+                | "--max-chars-per-line"
+        ) {
+            return false;
+        }
+        let Some(value) = args.next() else {
+            eprintln!("Missing value for {flag}");
+            std::process::exit(2);
+        };
+        match flag {
+            "--text" => {
+                self.text = Some(if value == "-" {
+                    read_snapshot_text(None)
+                } else {
+                    value
+                });
+            }
+            "--text-file" => self.text = Some(read_snapshot_text(Some(&value))),
+            "--config-path" => self.config_path = Some(PathBuf::from(value)),
+            // 設定ファイルと環境変数より優先する。値の検証は --config set と同じ
+            _ => {
+                let key =
+                    parse_config_key(&flag[2..]).expect("render override flags are config keys");
+                match set_config_value(&mut self.overrides, key, &value) {
+                    Ok(Some(warning)) => eprintln!("warning: {warning}"),
+                    Ok(None) => {}
+                    Err(error) => {
+                        eprintln!("{error}");
+                        std::process::exit(2);
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    fn into_text_and_settings(self) -> (String, DisplaySettings) {
+        let text = self.text.unwrap_or_else(|| "Clipboard text".to_string());
+        let settings = display_settings_from(self.config_path.map_or_else(config_file_path, Ok));
+        (text, apply_config_file(settings, &self.overrides))
+    }
+}
+
+// --render-hud-png と history render で共通の、PNG の書き出し先と描き方の指定
+#[derive(Default)]
+struct RenderOutputArgs {
+    output_path: Option<String>,
+    options: SnapshotOptions,
+}
+
+impl RenderOutputArgs {
+    // 共通のオプションなら値まで読んで true を返す。値の誤りは終了コード 2 で終える
+    fn parse_flag<I: Iterator<Item = String>>(&mut self, flag: &str, args: &mut I) -> bool {
+        match flag {
+            "--deterministic" => self.options.deterministic = true,
+            "--offscreen" => self.options.offscreen = true,
+            "--output" | "--scale-factor" => {
+                let Some(value) = args.next() else {
+                    eprintln!("Missing value for {flag}");
+                    std::process::exit(2);
+                };
+                if flag == "--output" {
+                    self.output_path = Some(value);
+                    return true;
+                }
+                self.options.scale_factor = match value.trim().parse::<f64>() {
+                    Ok(parsed)
+                        if (MIN_RENDER_SCALE_FACTOR..=MAX_RENDER_SCALE_FACTOR)
+                            .contains(&parsed) =>
+                    {
+                        parsed
+                    }
+                    _ => {
+                        eprintln!(
+                            "invalid --scale-factor: {value} (allowed range: {MIN_RENDER_SCALE_FACTOR}..={MAX_RENDER_SCALE_FACTOR})"
+                        );
+                        std::process::exit(2);
+                    }
+                };
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    // --output がなければ終了コード 2 で終える
+    fn output_path(&self, command: &str) -> String {
+        let Some(output_path) = self.output_path.clone() else {
+            eprintln!("--output is required for {command}");
+            std::process::exit(2);
+        };
+        output_path
+    }
+}
+
+// `path` が None なら標準入力から読む。失敗したら終了コード 2 で終える
+fn read_snapshot_text(path: Option<&str>) -> String {
+    let result = match path {
+        Some(path) => std::fs::read_to_string(path)
+            .map_err(|error| format!("failed to read --text-file {path}: {error}")),
+        None => std::io::read_to_string(std::io::stdin())
+            .map_err(|error| format!("failed to read --text from stdin: {error}")),
+    };
+    match result {
+        Ok(text) => strip_final_newline(text),
+        Err(error) => {
+            eprintln!("{error}");
+            std::process::exit(2);
+        }
+    }
+}
+
+const SNAPSHOT_USAGE: &str = "Usage: cliip-show snapshot test --manifest <PATH> --baseline-dir <DIR> [--artifact-dir <DIR>] [--threshold <N%|Npx>] [--scale-factor <N>]
+       cliip-show snapshot update --manifest <PATH> --baseline-dir <DIR> [--only <CASE>]... [--scale-factor <N>]";
+const DEFAULT_SNAPSHOT_ARTIFACT_DIR: &str = "snapshot-artifacts";
+
+fn handle_snapshot_command<I: Iterator<Item = String>>(args: &mut I) -> bool {
+    let update = match args.next().as_deref() {
+        Some("test") => false,
+        Some("update") => true,
+        Some(unknown) => {
+            eprintln!("Unknown snapshot command: {unknown}");
+            eprintln!("{SNAPSHOT_USAGE}");
+            std::process::exit(2);
+        }
+        None => {
+            eprintln!("{SNAPSHOT_USAGE}");
+            std::process::exit(2);
+        }
+    };
+    let command = if update { "update" } else { "test" };
+
+    let mut manifest_path: Option<PathBuf> = None;
+    let mut baseline_dir: Option<PathBuf> = None;
+    let mut artifact_dir = PathBuf::from(DEFAULT_SNAPSHOT_ARTIFACT_DIR);
+    let mut threshold: Option<DiffThreshold> = None;
+    let mut scale_factor: Option<f64> = None;
+    let mut only = Vec::new();
+    while let Some(arg) = args.next() {
+        let flag = arg.as_str();
+        let known = match flag {
+            "--manifest" | "--baseline-dir" | "--scale-factor" => true,
+            "--artifact-dir" | "--threshold" => !update,
+            "--only" => update,
+            _ => false,
+        };
+        if !known {
+            eprintln!("Unknown option for snapshot {command}: {flag}");
+            eprintln!("{SNAPSHOT_USAGE}");
+            std::process::exit(2);
+        }
+        let Some(value) = args.next() else {
+            eprintln!("Missing value for {flag}");
+            std::process::exit(2);
+        };
+        match flag {
+            "--manifest" => manifest_path = Some(PathBuf::from(value)),
+            "--baseline-dir" => baseline_dir = Some(PathBuf::from(value)),
+            "--artifact-dir" => artifact_dir = PathBuf::from(value),
+            "--only" => only.push(value),
+            "--threshold" => {
+                let Some(parsed) = parse_diff_threshold(&value) else {
+                    eprintln!("Invalid --threshold value: {value} (e.g. 0.5% or 120px)");
+                    std::process::exit(2);
+                };
+                threshold = Some(parsed);
+            }
+            _ => match value.trim().parse::<f64>() {
+                Ok(parsed)
+                    if (MIN_RENDER_SCALE_FACTOR..=MAX_RENDER_SCALE_FACTOR).contains(&parsed) =>
+                {
+                    scale_factor = Some(parsed);
+                }
+                _ => {
+                    eprintln!(
+                        "invalid --scale-factor: {value} (allowed range: {MIN_RENDER_SCALE_FACTOR}..={MAX_RENDER_SCALE_FACTOR})"
+                    );
+                    std::process::exit(2);
+                }
+            },
+        }
+    }
+    let (Some(manifest_path), Some(baseline_dir)) = (manifest_path, baseline_dir) else {
+        eprintln!("--manifest and --baseline-dir are required for snapshot {command}");
+        std::process::exit(2);
+    };
+
+    let mut manifest = match load_snapshot_manifest(&manifest_path) {
+        Ok(manifest) => manifest,
+        Err(error) => {
+            eprintln!("{error}");
+            std::process::exit(2);
+        }
+    };
+    // コマンドラインの指定はマニフェストより優先する（CI で閾値だけ緩めるなど）
+    if let Some(threshold) = threshold {
+        manifest.threshold = threshold;
+    }
+    if let Some(scale_factor) = scale_factor {
+        manifest.options.scale_factor = scale_factor;
+    }
+    if update {
+        return update_snapshot_baselines(&manifest, &baseline_dir, &only);
+    }
+    if let Err(error) = std::fs::create_dir_all(&artifact_dir) {
+        eprintln!(
+            "failed to create artifact dir {}: {error}",
+            artifact_dir.display()
+        );
+        std::process::exit(2);
+    }
+
+    // 0: すべて閾値以内, 1: 閾値超えかベースラインなし, 2: 描画・比較できないケースあり
+    let mut exit_code = 0;
+    for case in &manifest.cases {
+        let paths = SnapshotPaths::new(&case.name, &baseline_dir, &artifact_dir);
+        let outcome = autoreleasepool(|_| run_snapshot_case(case, &manifest, &paths));
+        match outcome {
+            Ok(CaseOutcome::Passed(summary)) if summary.diff_pixels == 0 => {
+                println!("ok: {}", case.name);
+            }
+            Ok(CaseOutcome::Passed(summary)) => {
+                println!(
+                    "ok: {} (within tolerance {}/{}, threshold={})",
+                    case.name,
+                    summary.diff_pixels,
+                    summary.total_pixels,
+                    manifest.threshold.describe()
+                );
+            }
+            Ok(CaseOutcome::Regressed(summary)) => {
+                eprintln!("ng: {}", case.name);
+                eprintln!("  baseline : {}", paths.baseline.display());
+                eprintln!("  current  : {}", paths.current.display());
+                eprintln!("  diff     : {}", paths.diff.display());
+                eprintln!("  composite: {}", paths.composite.display());
+                eprintln!(
+                    "  pixels   : {}/{} ({:.3}%, threshold={})",
+                    summary.diff_pixels,
+                    summary.total_pixels,
+                    summary.diff_ratio() * 100.0,
+                    manifest.threshold.describe()
+                );
+                exit_code = exit_code.max(1);
+            }
+            Ok(CaseOutcome::MissingBaseline) => {
+                eprintln!("ng: {}", case.name);
+                eprintln!("  missing baseline: {}", paths.baseline.display());
+                eprintln!("  current  : {}", paths.current.display());
+                exit_code = exit_code.max(1);
+            }
+            Err(error) => {
+                eprintln!("ng: {}", case.name);
+                eprintln!("  reason   : {error}");
+                exit_code = 2;
+            }
+        }
+    }
+
+    if exit_code != 0 {
+        std::process::exit(exit_code);
+    }
+    println!("snapshot test passed: {} cases", manifest.cases.len());
+    true
+}
+
+// `--only` に知らないケース名があれば、何も書き換えずに終了コード 2 で終える
+fn update_snapshot_baselines(
+    manifest: &SnapshotManifest,
+    baseline_dir: &Path,
+    only: &[String],
+) -> bool {
+    let cases = match select_snapshot_cases(manifest, only) {
+        Ok(cases) => cases,
+        Err(error) => {
+            eprintln!("{error}");
+            std::process::exit(2);
+        }
+    };
+    if let Err(error) = std::fs::create_dir_all(baseline_dir) {
+        eprintln!(
+            "failed to create baseline dir {}: {error}",
+            baseline_dir.display()
+        );
+        std::process::exit(2);
+    }
+
+    let mut failed = false;
+    for case in &cases {
+        match autoreleasepool(|_| update_snapshot_case(case, manifest, baseline_dir)) {
+            Ok(baseline) => println!("updated: {}", baseline.display()),
+            Err(error) => {
+                eprintln!("failed: {}", case.name);
+                eprintln!("  reason   : {error}");
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        std::process::exit(2);
+    }
+    println!("snapshot baselines updated: {} cases", cases.len());
+    true
+}
+
+const HISTORY_USAGE: &str = "Usage: cliip-show history list [--limit <N>] [--since <30m|12h|7d|2w|YYYY-MM-DD|RFC3339>] [--json]
+       cliip-show history search <QUERY> [--limit <N>] [--since <...>] [--json]
+       cliip-show history pick [--limit <N>] [--since <...>]
+       cliip-show history copy <ID> | --index <N>
+       cliip-show history render <ID> | --index <N> --output <PATH> [--scale-factor <1.0-3.0>] [--deterministic] [--offscreen] [--config-path <PATH>] [--hud-scale <N>] ...
+       cliip-show history pin|unpin <ID>
+       cliip-show history prune
+       cliip-show history export [--format json|csv] [--output <PATH>]
+       cliip-show history import <PATH>
+       cliip-show history sync [--export-key | --import-key]
+       cliip-show history pause|resume";
+
+fn handle_history_command<I: Iterator<Item = String>>(args: &mut I) -> bool {
+    let mut query = HistoryQuery::default();
+    let mut pick = false;
+    match args.next().as_deref() {
+        Some("list") => {}
+        Some("pick") => pick = true,
+        Some("copy") => return handle_history_copy_command(args),
+        Some("render") => return handle_history_render_command(args),
+        Some(command @ ("pin" | "unpin")) => {
+            return handle_history_pin_command(args, command == "pin")
+        }
+        Some("prune") => return handle_history_prune_command(args),
+        Some("export") => return handle_history_export_command(args),
+        Some("import") => return handle_history_import_command(args),
+        Some("sync") => return handle_history_sync_command(args),
+        Some(command @ ("pause" | "resume")) => {
+            return handle_history_recording_command(args, command == "pause")
+        }
+        Some("search") => {
+            let Some(search) = args.next() else {
+                eprintln!("{HISTORY_USAGE}");
+                std::process::exit(2);
+            };
+            query.search = Some(search);
+        }
+        Some(unknown) => {
+            eprintln!("Unknown history command: {unknown}");
+            eprintln!("{HISTORY_USAGE}");
+            std::process::exit(2);
+        }
+        None => {
+            eprintln!("{HISTORY_USAGE}");
+            std::process::exit(2);
+        }
+    }
+
+    let mut json = false;
+    while let Some(arg) = args.next() {
+        let flag = arg.as_str();
+        if flag == "--json" && !pick {
+            json = true;
+            continue;
+        }
+        if !matches!(flag, "--limit" | "--since") {
+            eprintln!("Unknown option for history: {flag}");
+            eprintln!("{HISTORY_USAGE}");
+            std::process::exit(2);
+        }
+        let Some(value) = args.next() else {
+            eprintln!("Missing value for {flag}");
+            std::process::exit(2);
+        };
+        if flag == "--limit" {
+            let Ok(limit) = value.trim().parse::<usize>() else {
+                eprintln!("invalid --limit: {value}");
+                std::process::exit(2);
+            };
+            query.limit = Some(limit);
+        } else {
+            let Some(since) = parse_since(&value, Local::now()) else {
+                eprintln!("invalid --since: {value} (e.g. 30m, 12h, 7d, 2w, 2024-05-01)");
+                std::process::exit(2);
+            };
+            query.since = Some(since);
+        }
+    }
+
+    let entries = match cli_history_cipher().and_then(|cipher| {
+        load_history_matching(
+            &cli_history_path()?,
+            query.search.as_deref(),
+            cipher.as_ref(),
+        )
+    }) {
+        Ok(entries) => entries,
+        Err(error) => {
+            eprintln!("{error}");
+            std::process::exit(1);
+        }
+    };
+    let selected = select_history(&entries, &query);
+    if pick {
+        pick_history_entry(&selected);
+    } else if json {
+        match serde_json::to_string_pretty(&selected) {
+            Ok(json) => println!("{json}"),
+            Err(error) => {
+                eprintln!("failed to serialize history: {error}");
+                std::process::exit(1);
+            }
+        }
+    } else {
+        // `| head` などで読み手が先に閉じても panic しないよう、書けなくなったら止める
+        let mut stdout = std::io::stdout().lock();
+        for entry in selected {
+            if writeln!(stdout, "{}", entry.to_line()).is_err() {
+                break;
+            }
+        }
+    }
+    true
+}
+
+fn pick_history_entry(entries: &[&HistoryEntry]) {
+    if entries.is_empty() {
+        eprintln!("no history entries (is history_enabled set?)");
+        std::process::exit(1);
+    }
+    let lines: String = entries
+        .iter()
+        .map(|entry| format!("{}\n", entry.to_line()))
+        .collect();
+    let command = history_picker_command();
+    let selected = match run_history_picker(&command, &lines) {
+        Ok(selected) => selected,
+        Err(error) => {
+            eprintln!("{error} (install fzf or set CLIIP_SHOW_PICKER)");
+            std::process::exit(2);
+        }
+    };
+    // 何も選ばずに閉じたときはクリップボードをそのままにする
+    let Some(line) = selected else {
+        std::process::exit(1);
+    };
+    let Some(entry) =
+        parse_history_line_id(&line).and_then(|id| entries.iter().find(|entry| entry.id == id))
+    else {
+        eprintln!("picker returned an unknown line: {line}");
+        std::process::exit(1);
+    };
+    copy_history_entry(entry);
+}
+
+fn handle_history_copy_command<I: Iterator<Item = String>>(args: &mut I) -> bool {
+    let selector = match (args.next(), args.next(), args.next()) {
+        (Some(flag), Some(value), None) if flag == "--index" => value
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .filter(|index| *index > 0)
+            .map(HistorySelector::Index),
+        (Some(value), None, None) => value.trim().parse::<u64>().ok().map(HistorySelector::Id),
+        _ => None,
+    };
+    let Some(selector) = selector else {
+        eprintln!("{HISTORY_USAGE}");
+        std::process::exit(2);
+    };
+
+    let entries = match cli_history_cipher()
+        .and_then(|cipher| load_history(&cli_history_path()?, cipher.as_ref()))
+    {
+        Ok(entries) => entries,
+        Err(error) => {
+            eprintln!("{error}");
+            std::process::exit(1);
+        }
+    };
+    let Some(entry) = selector.find(&entries) else {
+        match selector {
+            HistorySelector::Id(id) => eprintln!("no history entry with id {id}"),
+            HistorySelector::Index(index) => eprintln!("no history entry at index {index}"),
+        }
+        std::process::exit(1);
+    };
+    copy_history_entry(entry);
+    true
+}
+
+// 過去の履歴を、いまの設定の HUD として PNG に書き出す。不具合の報告やドキュメント用
+fn handle_history_render_command<I: Iterator<Item = String>>(args: &mut I) -> bool {
+    let mut selector = None;
+    let mut input = HudInputArgs::default();
+    let mut output = RenderOutputArgs::default();
+    while let Some(arg) = args.next() {
+        if matches!(arg.as_str(), "--text" | "--text-file") {
+            eprintln!("{arg} cannot be used with history render; the text comes from the entry");
+            std::process::exit(2);
+        }
+        if input.parse_flag(&arg, args) || output.parse_flag(&arg, args) {
+            continue;
+        }
+        let parsed = if arg == "--index" {
+            args.next()
+                .and_then(|value| value.trim().parse::<usize>().ok())
+                .filter(|index| *index > 0)
+                .map(HistorySelector::Index)
+        } else if arg.starts_with("--") {
+            eprintln!("Unknown option for history render: {arg}");
+            eprintln!("{HISTORY_USAGE}");
+            std::process::exit(2);
+        } else {
+            arg.trim().parse::<u64>().ok().map(HistorySelector::Id)
+        };
+        if selector.is_some() || parsed.is_none() {
+            eprintln!("{HISTORY_USAGE}");
+            std::process::exit(2);
+        }
+        selector = parsed;
+    }
+    let Some(selector) = selector else {
+        eprintln!("{HISTORY_USAGE}");
+        std::process::exit(2);
+    };
+    let output_path = output.output_path("history render");
+
+    let entries = match cli_history_cipher()
+        .and_then(|cipher| load_history(&cli_history_path()?, cipher.as_ref()))
+    {
+        Ok(entries) => entries,
+        Err(error) => {
+            eprintln!("{error}");
+            std::process::exit(1);
+        }
+    };
+    let Some(entry) = selector.find(&entries) else {
+        match selector {
+            HistorySelector::Id(id) => eprintln!("no history entry with id {id}"),
+            HistorySelector::Index(index) => eprintln!("no history entry at index {index}"),
+        }
+        std::process::exit(1);
+    };
+    input.text = Some(entry.text.clone());
+    let (text, settings) = input.into_text_and_settings();
+    if let Err(error) = render_hud_png(&text, &output_path, settings, output.options) {
+        eprintln!("{error}");
+        std::process::exit(1);
+    }
+    true
+}
+
+// 常駐中の cliip-show に記録の一時停止・再開を伝え、問い合わせて反映されたことを確かめる
+fn handle_history_recording_command<I: Iterator<Item = String>>(
+    args: &mut I,
+    paused: bool,
+) -> bool {
+    if args.next().is_some() {
+        eprintln!("{HISTORY_USAGE}");
+        std::process::exit(2);
+    }
+    let notification = if paused {
+        HISTORY_PAUSE_NOTIFICATION
+    } else {
+        HISTORY_RESUME_NOTIFICATION
+    };
+    autoreleasepool(|_| unsafe { post_control_notification(notification, None) });
+    let Some(stats) = request_daemon_stats() else {
+        eprintln!(
+            "no response from cliip-show within {}s (is it running?)",
+            STATS_RESPONSE_TIMEOUT.as_secs()
+        );
+        std::process::exit(1);
+    };
+    match stats.history {
+        HistoryRecording::Disabled => {
+            eprintln!("history is disabled (set history_enabled = true to record)");
+            std::process::exit(1);
+        }
+        HistoryRecording::Paused if paused => println!("history recording paused"),
+        HistoryRecording::Recording if !paused => println!("history recording resumed"),
+        recording => {
+            eprintln!(
+                "cliip-show did not apply the change (history = {})",
+                recording.as_str()
+            );
+            std::process::exit(1);
+        }
+    }
+    true
+}
+
+fn handle_history_pin_command<I: Iterator<Item = String>>(args: &mut I, pinned: bool) -> bool {
+    let (Some(Ok(id)), None) = (
+        args.next().map(|value| value.trim().parse::<u64>()),
+        args.next(),
+    ) else {
+        eprintln!("{HISTORY_USAGE}");
+        std::process::exit(2);
+    };
+    let found = cli_history_cipher()
+        .and_then(|cipher| set_history_pinned(&cli_history_path()?, id, pinned, cipher.as_ref()));
+    match found {
+        Ok(true) => true,
+        Ok(false) => {
+            eprintln!("no history entry with id {id}");
+            std::process::exit(1);
+        }
+        Err(error) => {
+            eprintln!("{error}");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn handle_history_prune_command<I: Iterator<Item = String>>(args: &mut I) -> bool {
+    if args.next().is_some() {
+        eprintln!("{HISTORY_USAGE}");
+        std::process::exit(2);
+    }
+    let limits = history_limits(&display_settings());
+    let removed = cli_history_cipher().and_then(|cipher| {
+        prune_history(
+            &cli_history_path()?,
+            limits,
+            Local::now().fixed_offset(),
+            cipher.as_ref(),
+        )
+    });
+    match removed {
+        Ok(removed) => {
+            println!("pruned {removed} history entries");
+            true
+        }
+        Err(error) => {
+            eprintln!("{error}");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn handle_history_export_command<I: Iterator<Item = String>>(args: &mut I) -> bool {
+    let mut format = HistoryExportFormat::Json;
+    let mut output = None;
+    while let Some(flag) = args.next() {
+        if !matches!(flag.as_str(), "--format" | "--output") {
+            eprintln!("Unknown option for history export: {flag}");
+            eprintln!("{HISTORY_USAGE}");
+            std::process::exit(2);
+        }
+        let Some(value) = args.next() else {
+            eprintln!("Missing value for {flag}");
+            std::process::exit(2);
+        };
+        if flag == "--format" {
+            let Some(parsed) = parse_history_export_format(&value) else {
+                eprintln!("invalid --format: {value} (json|csv)");
+                std::process::exit(2);
+            };
+            format = parsed;
+        } else {
+            output = Some(PathBuf::from(value));
+        }
+    }
+
+    let exported = cli_history_cipher()
+        .and_then(|cipher| load_history(&cli_history_path()?, cipher.as_ref()))
+        .and_then(|entries| export_history(&entries, format));
+    let exported = match exported {
+        Ok(exported) => exported,
+        Err(error) => {
+            eprintln!("{error}");
+            std::process::exit(1);
+        }
+    };
+    // 出力先を指定しなければ標準出力に書く
+    let Some(output) = output else {
+        print!("{exported}");
+        return true;
+    };
+    if let Err(error) = std::fs::write(&output, exported) {
+        eprintln!("failed to write {}: {error}", output.display());
+        std::process::exit(1);
+    }
+    true
+}
+
+fn handle_history_import_command<I: Iterator<Item = String>>(args: &mut I) -> bool {
+    let (Some(input), None) = (args.next(), args.next()) else {
+        eprintln!("{HISTORY_USAGE}");
+        std::process::exit(2);
+    };
+    let input = PathBuf::from(input);
+    let imported = std::fs::read_to_string(&input)
+        .map_err(|error| format!("failed to read {}: {error}", input.display()))
+        .and_then(|content| parse_history_export(&content))
+        .map_err(|error| format!("{}: {error}", input.display()));
+    let limits = history_limits(&display_settings());
+    let summary = imported.and_then(|imported| {
+        let cipher = cli_history_cipher()?;
+        import_history(
+            &cli_history_path()?,
+            imported,
+            limits,
+            Local::now().fixed_offset(),
+            cipher.as_ref(),
+        )
+    });
+    match summary {
+        Ok(summary) => {
+            println!(
+                "imported {} history entries ({} duplicates skipped, {} pruned by limits)",
+                summary.added, summary.duplicates, summary.pruned
+            );
+            true
+        }
+        Err(error) => {
+            eprintln!("{error}");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn handle_history_sync_command<I: Iterator<Item = String>>(args: &mut I) -> bool {
+    match (args.next().as_deref(), args.next()) {
+        (None, None) => {}
+        (Some("--export-key"), None) => return handle_sync_key_export(),
+        (Some("--import-key"), None) => return handle_sync_key_import(),
+        _ => {
+            eprintln!("{HISTORY_USAGE}");
+            std::process::exit(2);
+        }
+    }
+    let Some(dir) = sync_dir() else {
+        eprintln!("sync is not configured (set `dir` in the [sync] section of the config file)");
+        std::process::exit(1);
+    };
+    let settings = display_settings();
+    let synced =
+        cli_history_cipher().and_then(|cipher| run_history_sync(&dir, cipher.as_ref(), settings));
+    match synced {
+        Ok(synced) => {
+            println!(
+                "synced history with {} other Macs: imported {} entries ({} duplicates skipped, {} pruned by limits), shared {} entries",
+                synced.peers,
+                synced.imported.added,
+                synced.imported.duplicates,
+                synced.imported.pruned,
+                synced.pushed
+            );
+            true
+        }
+        Err(error) => {
+            eprintln!("{error}");
+            std::process::exit(1);
+        }
+    }
+}
+
+// 最初の Mac で鍵を作って表示し、ほかの Mac では `--import-key` に標準入力で渡す
+fn handle_sync_key_export() -> bool {
+    match autoreleasepool(|_| unsafe { sync_keychain_key(true) }) {
+        Ok(Some(key)) => {
+            println!("{}", encode_sync_key(&key));
+            true
+        }
+        Ok(None) => {
+            eprintln!("failed to create sync key");
+            std::process::exit(1);
+        }
+        Err(error) => {
+            eprintln!("{error}");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn handle_sync_key_import() -> bool {
+    let mut raw = String::new();
+    let saved = std::io::stdin()
+        .read_to_string(&mut raw)
+        .map_err(|error| format!("failed to read sync key from stdin: {error}"))
+        .and_then(|_| decode_sync_key(&raw))
+        .and_then(|key| autoreleasepool(|_| unsafe { save_sync_keychain_key(&key) }));
+    match saved {
+        Ok(()) => {
+            println!("saved sync key to Keychain");
+            true
+        }
+        Err(error) => {
+            eprintln!("{error}");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn cli_history_path() -> Result<PathBuf, String> {
+    history_store_path(display_settings().history_backend)
+}
+
+// 鍵を作るのは常駐プロセスが最初に記録するときだけにし、CLI は読むだけにする
+fn cli_history_cipher() -> Result<Option<HistoryCipher>, String> {
+    if !display_settings().history_encrypt {
+        return Ok(None);
+    }
+    let key = autoreleasepool(|_| unsafe { history_keychain_key(false) })?;
+    Ok(key.map(|key| HistoryCipher::new(&key)))
+}
+
+// 目印を付けずに書き込み、常駐中の cliip-show にいつものコピーとして HUD を出させる
+fn copy_history_entry(entry: &HistoryEntry) {
+    let written = autoreleasepool(|_| unsafe {
+        let pasteboard: *mut AnyObject = msg_send![class!(NSPasteboard), generalPasteboard];
+        set_plain_text(pasteboard, &entry.text)
+    });
+    if !written {
+        eprintln!(
+            "failed to write history entry {} to the pasteboard",
+            entry.id
+        );
+        std::process::exit(1);
+    }
+}
+
+const STATS_USAGE: &str =
+    "Usage: cliip-show stats [--since <30m|12h|7d|2w|YYYY-MM-DD|RFC3339>] [--json]
+       cliip-show stats --resources";
+
+fn handle_stats_command<I: Iterator<Item = String>>(args: &mut I) -> bool {
+    let args: Vec<String> = args.collect();
+    if args != ["--resources"] {
+        return handle_history_stats_command(args);
+    }
+
+    let Some(stats) = request_daemon_stats() else {
+        eprintln!(
+            "no response from cliip-show within {}s (is it running?)",
+            STATS_RESPONSE_TIMEOUT.as_secs()
+        );
+        std::process::exit(1);
+    };
+    let resources = std::process::Command::new("ps")
+        .args(["-o", "rss=,cputime=", "-p", &stats.pid.to_string()])
+        .output()
+        .ok()
+        .and_then(|output| parse_ps_resources(&String::from_utf8_lossy(&output.stdout)));
+    let (rss_kb, cpu_time) = resources.unzip();
+    println!("{}", resource_report(&stats, rss_kb, cpu_time.as_deref()));
+    true
+}
+
+// 常駐中の cliip-show に問い合わせる。応答がなければ None
+fn request_daemon_stats() -> Option<DaemonStats> {
+    let payload = autoreleasepool(|_| unsafe {
+        let observer = Owned::from_raw(msg_send![get_delegate_class(), new])?;
+        observe_control_notification(
+            observer.as_ptr(),
+            sel!(statsResponse:),
+            STATS_RESPONSE_NOTIFICATION,
+        );
+        post_control_notification(STATS_REQUEST_NOTIFICATION, None);
+
+        let run_loop: *mut AnyObject = msg_send![class!(NSRunLoop), currentRunLoop];
+        let deadline = Instant::now() + STATS_RESPONSE_TIMEOUT;
+        let mut payload = None;
+        while Instant::now() < deadline {
+            payload = STATS_RESPONSE.with(|response| response.borrow_mut().take());
+            if payload.is_some() {
+                break;
+            }
+            let until: *mut AnyObject =
+                msg_send![class!(NSDate), dateWithTimeIntervalSinceNow: STATS_RUN_LOOP_STEP_SECS];
+            let () = msg_send![run_loop, runUntilDate: until];
+        }
+        // 通知センターは observer を保持しないので、解放する前に登録を外す
+        remove_control_observer(observer.as_ptr());
+        payload
+    });
+    payload.as_deref().and_then(DaemonStats::from_payload)
+}
+
+fn handle_status_command<I: Iterator<Item = String>>(args: &mut I) -> bool {
+    if let Some(arg) = args.next() {
+        eprintln!("Unknown option for --status: {arg}");
+        std::process::exit(2);
+    }
+    let stats = request_daemon_stats();
+    println!("{}", status_report(stats.as_ref()));
+    if stats.is_none() {
+        std::process::exit(1);
+    }
+    true
+}
+
+fn handle_history_stats_command(args: Vec<String>) -> bool {
+    let mut query = HistoryQuery::default();
+    let mut json = false;
+    let mut args = args.into_iter();
+    while let Some(flag) = args.next() {
+        match flag.as_str() {
+            "--json" => json = true,
+            "--since" => {
+                let Some(value) = args.next() else {
+                    eprintln!("Missing value for {flag}");
+                    std::process::exit(2);
+                };
+                let Some(since) = parse_since(&value, Local::now()) else {
+                    eprintln!("invalid --since: {value} (e.g. 30m, 12h, 7d, 2w, 2024-05-01)");
+                    std::process::exit(2);
+                };
+                query.since = Some(since);
+            }
+            _ => {
+                eprintln!("Unknown option for stats: {flag}");
+                eprintln!("{STATS_USAGE}");
+                std::process::exit(2);
+            }
+        }
+    }
+
+    let entries = match cli_history_cipher()
+        .and_then(|cipher| load_history(&cli_history_path()?, cipher.as_ref()))
+    {
+        Ok(entries) => entries,
+        Err(error) => {
+            eprintln!("{error}");
+            std::process::exit(1);
+        }
+    };
+    let stats = summarize_history(&select_history(&entries, &query));
+    if json {
+        match serde_json::to_string_pretty(&stats) {
+            Ok(json) => println!("{json}"),
+            Err(error) => {
+                eprintln!("failed to serialize stats: {error}");
+                std::process::exit(1);
+            }
+        }
+    } else {
+        println!("{}", stats.to_report());
+    }
+    true
+}
+
+fn get_delegate_class() -> &'static AnyClass {
+    static ONCE: Once = Once::new();
+    static mut CLASS: *const AnyClass = ptr::null();
+
+    ONCE.call_once(|| unsafe {
+        let mut builder = ClassBuilder::new("ClipboardHudAppDelegate", class!(NSObject))
+            .expect("delegate class creation failed");
+
+        builder.add_method(
+            sel!(applicationDidFinishLaunching:),
+            application_did_finish_launching as extern "C" fn(_, _, _),
+        );
+        builder.add_method(
+            sel!(pollPasteboard:),
+            poll_pasteboard as extern "C" fn(_, _, _),
+        );
+        builder.add_method(sel!(hideHud:), hide_hud as extern "C" fn(_, _, _));
+        builder.add_method(sel!(fadeTick:), fade_tick as extern "C" fn(_, _, _));
+        builder.add_method(
+            sel!(autoClearTick:),
+            auto_clear_tick as extern "C" fn(_, _, _),
+        );
+        builder.add_method(
+            sel!(historyCycleTick:),
+            history_cycle_tick as extern "C" fn(_, _, _),
+        );
+        builder.add_method(
+            sel!(historyCycleLoaded:),
+            history_cycle_loaded as extern "C" fn(_, _, _),
+        );
+        builder.add_method(
+            sel!(historySyncTick:),
+            history_sync_tick as extern "C" fn(_, _, _),
+        );
+        builder.add_method(
+            sel!(configChanged:),
+            config_changed as extern "C" fn(_, _, _),
+        );
+        builder.add_method(
+            sel!(showPreparedClip:),
+            show_prepared_clip as extern "C" fn(_, _, _),
+        );
+        builder.add_method(sel!(showFavicon:), show_favicon as extern "C" fn(_, _, _));
+        builder.add_method(sel!(statsRequest:), stats_request as extern "C" fn(_, _, _));
+        builder.add_method(sel!(historyPause:), history_pause as extern "C" fn(_, _, _));
+        builder.add_method(
+            sel!(historyResume:),
+            history_resume as extern "C" fn(_, _, _),
+        );
+        builder.add_method(
+            sel!(statsResponse:),
+            stats_response as extern "C" fn(_, _, _),
+        );
+        builder.add_method(
+            sel!(observeValueForKeyPath:ofObject:change:context:),
+            observe_value_for_key_path as extern "C" fn(_, _, _, _, _, _),
+        );
+
+        let class = builder.register();
+        CLASS = class as *const AnyClass;
+    });
+
+    unsafe { &*CLASS }
+}
+
+extern "C" fn application_did_finish_launching(this: &AnyObject, _: Sel, _: *mut AnyObject) {
+    unsafe {
+        let settings = display_settings();
+        let pasteboard: *mut AnyObject = msg_send![class!(NSPasteboard), generalPasteboard];
+        let last_change_count: isize = msg_send![pasteboard, changeCount];
+
+        let plugins = if settings.plugins_enabled {
+            match plugins_dir() {
+                Ok(dir) => load_plugins(&dir),
+                Err(error) => {
+                    eprintln!("warning: {error}");
+                    Vec::new()
+                }
+            }
+        } else {
+            Vec::new()
+        };
+
+        let state = AppState {
+            last_change_count,
+            last_change_at: Instant::now(),
+            pasteboard,
+            poll_timer: None,
+            poll_interval_secs: settings.poll_interval_secs,
+            hud_window: None,
+            speech: None,
+            previous_text: None,
+            clip_repeat_count: 0,
+            clip_counter: ClipCounter::default(),
+            last_clip_at: None,
+            clip_from_other_device: false,
+            clip_type_badges: Vec::new(),
+            clip_source_app: None,
+            history_writer: spawn_history_writer(),
+            history_exclude: Arc::new(history_exclude()),
+            history_sync_dir: sync_dir(),
+            history_sync: spawn_history_sync(),
+            history_sync_timer: None,
+            history_paused: false,
+            history_hotkey: None,
+            history_cycle: None,
+            history_cycle_presses: 0,
+            history_cycle_timer: None,
+            append_active: settings.append_mode,
+            append_buffer: None,
+            append_hotkey: None,
+            hide_timer: None,
+            fade_timer: None,
+            fade_ticks_elapsed: 0,
+            fade_total_ticks: 0,
+            clear_timer: None,
+            pending_clear: None,
+            plugins: Arc::new(Mutex::new(plugins)),
+            launched_at: Instant::now(),
+            settings,
+        };
+        APP_STATE.with(|app_state| {
+            let mut app_state = app_state.borrow_mut();
+            let state = app_state.insert(state);
+            schedule_poll_timer(this, state, settings.poll_interval_secs);
+            sync_history_hotkey(this, state);
+            sync_append_hotkey(this, state);
+            sync_history_sync_timer(this, state);
+            show_onboarding_hud(this, state);
+        });
+
+        observe_control_notification(this, sel!(configChanged:), CONFIG_CHANGED_NOTIFICATION);
+        observe_control_notification(this, sel!(statsRequest:), STATS_REQUEST_NOTIFICATION);
+        observe_control_notification(this, sel!(historyPause:), HISTORY_PAUSE_NOTIFICATION);
+        observe_control_notification(this, sel!(historyResume:), HISTORY_RESUME_NOTIFICATION);
+        // ライト・ダークの切り替えを KVO で受け取り、`appearance = "auto"` の配色を入れ替える
+        let app: *mut AnyObject = msg_send![class!(NSApplication), sharedApplication];
+        let () = msg_send![
+            app,
+            addObserver: this
+            forKeyPath: cached_nsstring(EFFECTIVE_APPEARANCE_KEY_PATH)
+            options: 0usize
+            context: ptr::null_mut::<c_void>()
+        ];
+    }
+}
+
+extern "C" fn observe_value_for_key_path(
+    _this: &AnyObject,
+    _: Sel,
+    _key_path: *mut AnyObject,
+    _object: *mut AnyObject,
+    _change: *mut AnyObject,
+    _context: *mut c_void,
+) {
+    // 監視しているのは NSApp の effectiveAppearance だけ
+    autoreleasepool(|_| unsafe {
+        with_app_state(|state| {
+            if state.settings.appearance == HudAppearance::Auto {
+                apply_settings(state, state.settings);
+            }
+        });
+    });
+}
+
+extern "C" fn stats_request(_this: &AnyObject, _: Sel, _: *mut AnyObject) {
+    autoreleasepool(|_| unsafe {
+        let Some(stats) = with_app_state(|state| DaemonStats {
+            pid: std::process::id(),
+            uptime_secs: state.launched_at.elapsed().as_secs_f64(),
+            wakeups: WAKEUP_COUNT.load(Ordering::Relaxed),
+            objc_objects: live_objects(),
+            clips_total: state.clip_counter.total,
+            clips_today: state.clip_counter.today_on(Local::now().date_naive()),
+            history: if !state.settings.history_enabled {
+                HistoryRecording::Disabled
+            } else if state.history_paused {
+                HistoryRecording::Paused
+            } else {
+                HistoryRecording::Recording
+            },
+        }) else {
+            return;
+        };
+        post_control_notification(STATS_RESPONSE_NOTIFICATION, Some(&stats.to_payload()));
+    });
+}
+
+extern "C" fn history_pause(_this: &AnyObject, _: Sel, _: *mut AnyObject) {
+    with_app_state(|state| state.history_paused = true);
+}
+
+extern "C" fn history_resume(_this: &AnyObject, _: Sel, _: *mut AnyObject) {
+    with_app_state(|state| state.history_paused = false);
+}
+
+extern "C" fn stats_response(_this: &AnyObject, _: Sel, notification: *mut AnyObject) {
+    let payload = unsafe { notification_payload(notification) };
+    STATS_RESPONSE.with(|response| *response.borrow_mut() = payload);
+}
+
+extern "C" fn config_changed(this: &AnyObject, _: Sel, _: *mut AnyObject) {
+    autoreleasepool(|_| unsafe {
+        with_app_state(|state| {
+            let settings = display_settings();
+            let backend_changed = settings.poll_timer_backend != state.settings.poll_timer_backend;
+            if settings.append_mode != state.settings.append_mode {
+                state.append_active = settings.append_mode;
+                state.append_buffer = None;
+            }
+            apply_settings(state, settings);
+            state.history_exclude = Arc::new(history_exclude());
+            state.history_sync_dir = sync_dir();
+            if backend_changed {
+                schedule_poll_timer(this, state, state.poll_interval_secs);
+            }
+            sync_history_hotkey(this, state);
+            sync_append_hotkey(this, state);
+            sync_history_sync_timer(this, state);
+        });
+    });
+}
+
+// ウィンドウを作り直すとちらつくので、既存のビューをそのまま更新する。
+// ポーリング間隔は次のタイマー発火時に新しい設定値へ戻る。
+unsafe fn apply_settings(state: &mut AppState, settings: DisplaySettings) {
+    if let Some(views) = state.views() {
+        apply_view_settings(&views, settings);
+    }
+    if settings.auto_clear_secs <= 0.0 {
+        cancel_pending_clear(state);
+    }
+    state.settings = settings;
+}
+
+unsafe fn schedule_poll_timer(target: &AnyObject, state: &mut AppState, interval_secs: f64) {
+    // 古いタイマーは代入で Drop され、止まる
+    let tolerance_secs = interval_secs * POLL_TIMER_TOLERANCE_RATIO;
+    state.poll_timer = match state.settings.poll_timer_backend {
+        PollTimerBackend::NsTimer => {
+            ScheduledTimer::schedule(interval_secs, target, sel!(pollPasteboard:), true).map(
+                |timer| {
+                    let () = msg_send![timer.as_ptr(), setTolerance: tolerance_secs];
+                    PollTimer::NsTimer { _timer: timer }
+                },
+            )
+        }
+        PollTimerBackend::Dispatch => {
+            DispatchTimer::schedule(interval_secs, tolerance_secs, target, sel!(pollPasteboard:))
+                .map(|timer| PollTimer::Dispatch { _timer: timer })
+        }
+    };
+    state.poll_interval_secs = interval_secs;
+}
+
+fn next_poll_interval_secs(current_secs: f64, idle_secs: f64, settings: DisplaySettings) -> f64 {
+    let base = settings.poll_interval_secs;
+    if settings.poll_idle_after_secs <= 0.0 || idle_secs < settings.poll_idle_after_secs {
+        return base;
+    }
+    // 変化のない時間が続いたら上限に向けて少しずつ間隔を延ばす
+    let max = settings.poll_max_interval_secs.max(base);
+    (current_secs * POLL_BACKOFF_FACTOR).clamp(base, max)
+}
+
+extern "C" fn poll_pasteboard(this: &AnyObject, _: Sel, _: *mut AnyObject) {
+    WAKEUP_COUNT.fetch_add(1, Ordering::Relaxed);
+    autoreleasepool(|_| unsafe {
+        with_app_state(|state| {
+            let change_count: isize = msg_send![state.pasteboard, changeCount];
+            if change_count == state.last_change_count {
+                let idle_secs = state.last_change_at.elapsed().as_secs_f64();
+                let next =
+                    next_poll_interval_secs(state.poll_interval_secs, idle_secs, state.settings);
+                if next != state.poll_interval_secs {
+                    schedule_poll_timer(this, state, next);
+                }
+                return;
+            }
+            state.last_change_count = change_count;
+            state.last_change_at = Instant::now();
+            if state.poll_interval_secs != state.settings.poll_interval_secs {
+                schedule_poll_timer(this, state, state.settings.poll_interval_secs);
+            }
+
+            // 種類は変化ごとに1回だけ読み、選んだ表現の内容だけを取り出す
+            let types = pasteboard_types(state.pasteboard);
+            // changeCount の記録だけでは、別プロセスの cliip-show による書き込みを見分けられない
+            if is_self_write(&types) {
+                return;
+            }
+            state.clip_from_other_device = is_remote_clipboard(&types);
+            state.clip_type_badges = type_badges(&types);
+            // コピーした直後のポーリングなので、最前面のアプリをコピー元とみなす
+            state.clip_source_app = (state.settings.show_source_app
+                || state.settings.history_enabled)
+                .then(|| frontmost_source_app())
+                .flatten();
+            // 自動入力などはすぐに元の内容へ書き戻すので、そのたびに HUD を出さない
+            if state.settings.ignore_transient && is_transient(&types) {
+                return;
+            }
+            let repeat_count = std::mem::take(&mut state.clip_repeat_count);
+            // パスワードは中身を読まず、前のテキストとしても残さない
+            if is_concealed(&types) {
+                if state.settings.concealed_behavior == ConcealedBehavior::Mask {
+                    prepare_main_thread_clip(this, state, build_concealed_hud_content);
+                }
+                return;
+            }
+            if has_file_type(&types) {
+                let paths = pasteboard_file_paths(state.pasteboard);
+                if !paths.is_empty() {
+                    prepare_file_clip(this, state, &paths);
+                    return;
+                }
+            }
+            let max_bytes = state.settings.max_display_bytes;
+            // stringForType: は先頭のアイテムしか返さないので、複数あれば1つずつ読む
+            let (items, items_cut) = pasteboard_item_texts(state.pasteboard, max_bytes);
+            let multi_item = items.len() > 1;
+            let (capped, source_format) = if multi_item {
+                (Some((join_pasteboard_items(&items), items_cut)), None)
+            } else if let Some(text_type_name) = preferred_text_type(&types) {
+                let text_type = cached_nsstring(text_type_name);
+                let raw_text: *mut AnyObject =
+                    msg_send![state.pasteboard, stringForType: text_type];
+                (nsstring_to_string_capped(raw_text, max_bytes), None)
+            } else if let Some(format) = preferred_rich_text_format(&types) {
+                // Word や Pages のコピーには書式付きの表現しかないことがある
+                (
+                    rich_text_to_plain_capped(state.pasteboard, format, max_bytes),
+                    Some(format.badge()),
+                )
+            } else {
+                if let Some(image_type_name) = preferred_image_type(&types) {
+                    prepare_image_clip(this, state, image_type_name);
+                } else if let Some(type_name) = preferred_binary_type(&types) {
+                    // 中身を表示できなくても、何がコピーされたかは知らせる
+                    prepare_binary_clip(this, state, type_name);
+                }
+                return;
+            };
+            let Some((text, cut)) = capped else {
+                return;
+            };
+            // 読み込みを打ち切ったテキストはつなぐと内容が失われるので、そこで追記をやり直す
+            let appending = state.append_active && !cut;
+            if state.append_active && cut {
+                state.append_buffer = None;
+            }
+            let repeated = !appending && state.previous_text.as_deref() == Some(text.as_str());
+            state.clip_repeat_count = if repeated { repeat_count + 1 } else { 1 };
+            if state.settings.history_enabled && !state.history_paused {
+                record_history(state, &text);
+            }
+            // 同じ内容を続けてコピーしただけなら、見た目の変わらない HUD を出し直さない
+            if repeated && state.settings.dedup_consecutive == DedupConsecutive::Quiet {
+                return;
+            }
+            // 追記モードでは、これまでのテキストにつないだ全体でクリップボードを書き直して表示する
+            let (text, append_lines) = if appending {
+                let joined = match state.append_buffer.take() {
+                    Some(buffer) => {
+                        let joined = format!("{buffer}\n{text}");
+                        // 自分の書き込みで changeCount が進むので、再検出しないよう記録しておく
+                        write_plain_text(state.pasteboard, &joined);
+                        state.last_change_count = msg_send![state.pasteboard, changeCount];
+                        joined
+                    }
+                    None => text,
+                };
+                let lines = joined.lines().count();
+                state.append_buffer = Some(joined.clone());
+                (joined, Some(lines))
+            } else {
+                (text, None)
+            };
+            if state.settings.show_path_metadata && !multi_item && append_lines.is_none() {
+                if let Some(path) = existing_path_in_text(&text) {
+                    prepare_path_clip(this, state, &path);
+                    return;
+                }
+            }
+
+            let mut formatting_stripped = false;
+            // 読み込みを打ち切ったテキストや、見出しを付けて連結した複数アイテムで書き直すと
+            // 内容が失われるので、その場合は書式を残す
+            if state.settings.force_plain_text
+                && !cut
+                && !multi_item
+                && append_lines.is_none()
+                && has_rich_text_type(&types)
+            {
+                // 自分の書き込みで changeCount が進むので、再検出しないよう記録しておく
+                let written = write_plain_text(state.pasteboard, &text);
+                state.last_change_count = msg_send![state.pasteboard, changeCount];
+                formatting_stripped = written;
+            }
+
+            // 長いクリップの切り詰めや変換でメインスレッドを止めないよう、内容の組み立ては
+            // バックグラウンドで行い、AppKit への反映だけを showPreparedClip: で戻して実行する
+            let previous_text = state.previous_text.replace(text.clone());
+            let cut_at_bytes = cut.then_some(state.settings.max_display_bytes);
+            let change_count = state.last_change_count;
+            let now = Local::now();
+            let copied_at = now.time();
+            let clip_number_today = state.clip_counter.record(now.date_naive());
+            let secs_since_previous = state
+                .last_clip_at
+                .replace(Instant::now())
+                .map(|previous| previous.elapsed().as_secs() as i64);
+            let from_other_device = state.clip_from_other_device;
+            let type_badges = state.clip_type_badges.clone();
+            let source_app = state.clip_source_app.clone();
+            let repeat_count = state.clip_repeat_count;
+            let settings = state.settings;
+            let plugins = Arc::clone(&state.plugins);
+            let delegate = this as *const AnyObject as usize;
+            thread::spawn(move || {
+                let plugins = plugins.lock().expect("plugins lock poisoned");
+                let context = ClipContext {
+                    previous_text: previous_text.as_deref(),
+                    formatting_stripped,
+                    cut_at_bytes,
+                    copied_at: Some(copied_at),
+                    clip_number_today: Some(clip_number_today),
+                    secs_since_previous,
+                    source_format,
+                    from_other_device,
+                    type_badges: &type_badges,
+                    source_app: source_app.as_ref(),
+                    repeat_count,
+                    append_lines,
+                    transformers: &plugins,
+                };
+                let content = build_hud_content(&text, context, settings);
+                let sensitive =
+                    settings.auto_clear_secs > 0.0 && detect_sensitive_clip(&text).is_some();
+                let speech =
+                    if settings.accessibility_speak && detect_sensitive_clip(&text).is_none() {
+                        speakable_text(&text, settings.accessibility_speak_max_chars)
+                            .map(str::to_string)
+                    } else {
+                        None
+                    };
+                let favicon_url = settings.show_favicon.then(|| favicon_url(&text)).flatten();
+                let qr_url = settings
+                    .show_qr_for_urls
+                    .then(|| qr_code_url(&text).map(str::to_string))
+                    .flatten();
+                drop(plugins);
+
+                let mut prepared = PREPARED_CLIP.lock().expect("PREPARED_CLIP lock poisoned");
+                // 後から終わった古いクリップで新しいクリップの結果を上書きしない
+                if prepared
+                    .as_ref()
+                    .is_some_and(|newer| newer.change_count > change_count)
+                {
+                    return;
+                }
+                *prepared = Some(PreparedClip {
+                    change_count,
+                    content,
+                    sensitive,
+                    speech,
+                    favicon_url,
+                    qr_url,
+                });
+                drop(prepared);
+                autoreleasepool(|_| {
+                    let delegate = delegate as *mut AnyObject;
+                    let () = msg_send![
+                        delegate,
+                        performSelectorOnMainThread: sel!(showPreparedClip:)
+                        withObject: ptr::null_mut::<AnyObject>()
+                        waitUntilDone: false
+                    ];
+                });
+            });
+        });
+    });
+}
+
+fn record_history(state: &mut AppState, text: &str) {
+    let copied_at = Local::now().to_rfc3339_opts(SecondsFormat::Secs, false);
+    let source_app = state.clip_source_app.as_ref().map(|app| app.name.clone());
+    let text = text.to_string();
+    let settings = state.settings;
+    let exclude = Arc::clone(&state.history_exclude);
+    let write = HistoryWrite {
+        copied_at,
+        source_app,
+        text,
+        settings,
+        exclude,
+    };
+    if state
+        .history_writer
+        .send(HistoryJob::Append(write))
+        .is_err()
+    {
+        eprintln!("warning: history writer thread has stopped");
+    }
+}
+
+// ファイルの読み書きや Keychain でメインスレッドを止めないよう、履歴はバックグラウンドで扱う。
+// コピーごとにスレッドを立てると追記の順番が入れ替わるので、1本のスレッドが履歴を開いて順に処理する
+fn spawn_history_writer() -> mpsc::Sender<HistoryJob> {
+    let (sender, receiver) = mpsc::channel::<HistoryJob>();
+    thread::spawn(move || {
+        // 履歴を有効にして最初のテキストを記録するときに開く
+        let mut history = None;
+        for job in receiver {
+            match job {
+                HistoryJob::Append(write) => {
+                    // 除外の規則に一致したテキストは、HUD に出しても履歴には書かない
+                    if write.exclude.matching_rule(&write.text).is_none() {
+                        append_history(
+                            &mut history,
+                            write.copied_at,
+                            write.source_app,
+                            write.text,
+                            write.settings,
+                        );
+                    }
+                }
+                HistoryJob::LoadCycle {
+                    settings,
+                    current_text,
+                    delegate,
+                } => {
+                    let entries = match daemon_history_entries(&mut history, &settings) {
+                        Ok(entries) => history_cycle_entries(entries, current_text.as_deref()),
+                        Err(error) => {
+                            eprintln!("warning: {error}");
+                            Vec::new()
+                        }
+                    };
+                    *LOADED_HISTORY_CYCLE
+                        .lock()
+                        .expect("LOADED_HISTORY_CYCLE lock poisoned") = Some(entries);
+                    autoreleasepool(|_| unsafe {
+                        let delegate = delegate as *mut AnyObject;
+                        let () = msg_send![
+                            delegate,
+                            performSelectorOnMainThread: sel!(historyCycleLoaded:)
+                            withObject: ptr::null_mut::<AnyObject>()
+                            waitUntilDone: false
+                        ];
+                    });
+                }
+            }
+        }
+    });
+    sender
+}
+
+// 共有フォルダーの読み書きは遅いことがあるので、記録とは別のスレッドで同期する。
+// 記録のロックは取らない。履歴ファイルの書き換えは HistoryStore と同じファイルロックで守られ、
+// 開いている HistoryStore は次の追記でファイルが変わったことに気付いて読み直す
+fn spawn_history_sync() -> mpsc::SyncSender<HistorySyncJob> {
+    // 1回の同期が間隔より長くかかっても依頼が溜まらないよう、待たせるのは1件だけにする
+    let (sender, receiver) = mpsc::sync_channel::<HistorySyncJob>(1);
+    thread::spawn(move || {
+        for job in receiver {
+            let result = history_cipher(&job.settings)
+                .and_then(|cipher| run_history_sync(&job.dir, cipher.as_ref(), job.settings));
+            if let Err(error) = result {
+                eprintln!("warning: {error}");
+            }
+        }
+    });
+    sender
+}
+
+// `[sync]` を設定して履歴を記録しているときだけ、同期のタイマーを動かす
+unsafe fn sync_history_sync_timer(this: &AnyObject, state: &mut AppState) {
+    let wanted = state.settings.history_enabled && state.history_sync_dir.is_some();
+    if !wanted {
+        state.history_sync_timer = None;
+    } else if state.history_sync_timer.is_none() {
+        state.history_sync_timer = ScheduledTimer::schedule(
+            HISTORY_SYNC_INTERVAL.as_secs_f64(),
+            this,
+            sel!(historySyncTick:),
+            true,
+        );
+    }
+}
+
+extern "C" fn history_sync_tick(_this: &AnyObject, _: Sel, _: *mut AnyObject) {
+    WAKEUP_COUNT.fetch_add(1, Ordering::Relaxed);
+    with_app_state(|state| {
+        let Some(dir) = state.history_sync_dir.clone() else {
+            return;
+        };
+        if state.history_paused {
+            return;
+        }
+        let job = HistorySyncJob {
+            dir,
+            settings: state.settings,
+        };
+        match state.history_sync.try_send(job) {
+            // 前の同期がまだ終わっていなければ、今回は見送る
+            Ok(()) | Err(mpsc::TrySendError::Full(_)) => {}
+            Err(mpsc::TrySendError::Disconnected(_)) => {
+                eprintln!("warning: history sync thread has stopped");
+            }
+        }
+    });
+}
+
+fn run_history_sync(
+    dir: &Path,
+    history_cipher: Option<&HistoryCipher>,
+    settings: DisplaySettings,
+) -> Result<HistorySync, String> {
+    let (key, device) = autoreleasepool(|_| unsafe {
+        let key = sync_keychain_key(false)?;
+        let process_info: *mut AnyObject = msg_send![class!(NSProcessInfo), processInfo];
+        let host_name: *mut AnyObject = msg_send![process_info, hostName];
+        let device = sync_device_name(&nsstring_to_string(host_name).unwrap_or_default());
+        Ok::<_, String>((key, device))
+    })?;
+    let key = key.ok_or_else(|| {
+        "sync key not found in Keychain (run `cliip-show history sync --export-key` on one Mac and `--import-key` on the others)".to_string()
+    })?;
+    sync_history(
+        &history_store_path(settings.history_backend)?,
+        history_cipher,
+        dir,
+        &device,
+        &HistoryCipher::new(&key),
+        history_limits(&settings),
+        Local::now().fixed_offset(),
+    )
+}
+
+fn is_history_store_outdated(store: &HistoryStore, settings: &DisplaySettings) -> bool {
+    store.is_encrypted() != settings.history_encrypt || store.backend() != settings.history_backend
+}
+
+fn history_cipher(settings: &DisplaySettings) -> Result<Option<HistoryCipher>, String> {
+    if !settings.history_encrypt {
+        return Ok(None);
+    }
+    let key = autoreleasepool(|_| unsafe { history_keychain_key(true) })?;
+    Ok(key.map(|key| HistoryCipher::new(&key)))
+}
+
+fn open_history_store(settings: &DisplaySettings) -> Result<HistoryStore, String> {
+    HistoryStore::open(
+        history_store_path(settings.history_backend)?,
+        history_cipher(settings)?,
+    )
+}
+
+// 開いている履歴を返す。まだ開いていないか、暗号化や保存形式の設定を変えていれば開き直す
+fn daemon_history_store<'a>(
+    history: &'a mut Option<HistoryStore>,
+    settings: &DisplaySettings,
+) -> Result<&'a mut HistoryStore, String> {
+    let store = match history.take() {
+        Some(store) if !is_history_store_outdated(&store, settings) => store,
+        _ => open_history_store(settings)?,
+    };
+    Ok(history.insert(store))
+}
+
+fn append_history(
+    history: &mut Option<HistoryStore>,
+    copied_at: String,
+    source_app: Option<String>,
+    text: String,
+    settings: DisplaySettings,
+) {
+    let dedup = settings.dedup_consecutive != DedupConsecutive::Off;
+    let limits = history_limits(&settings);
+    let result = daemon_history_store(history, &settings)
+        .and_then(|store| store.append(copied_at, source_app, text, limits, dedup));
+    if let Err(error) = result {
+        eprintln!("warning: {error}");
+    }
+}
+
+fn daemon_history_entries(
+    history: &mut Option<HistoryStore>,
+    settings: &DisplaySettings,
+) -> Result<Vec<HistoryEntry>, String> {
+    daemon_history_store(history, settings)?.entries()
+}
+
+// 設定と登録済みのショートカットが違えば登録し直す。履歴を記録しないなら登録しない
+unsafe fn sync_history_hotkey(this: &AnyObject, state: &mut AppState) {
+    let hotkey = state.settings.history_hotkey;
+    let wanted = (state.settings.history_enabled && !hotkey.is_empty()).then_some(hotkey);
+    if reregister_hotkey(
+        this,
+        &mut state.history_hotkey,
+        wanted,
+        HISTORY_HOTKEY_ID,
+        history_hotkey_pressed,
+    ) {
+        state.history_cycle = None;
+        state.history_cycle_presses = 0;
+        state.history_cycle_timer = None;
+    }
+}
+
+unsafe fn sync_append_hotkey(this: &AnyObject, state: &mut AppState) {
+    let hotkey = state.settings.append_hotkey;
+    reregister_hotkey(
+        this,
+        &mut state.append_hotkey,
+        (!hotkey.is_empty()).then_some(hotkey),
+        APPEND_HOTKEY_ID,
+        append_hotkey_pressed,
+    );
+}
+
+// 登録済みのショートカットが `wanted` と違えば登録し直し、true を返す。None なら登録を外す
+unsafe fn reregister_hotkey(
+    this: &AnyObject,
+    registered: &mut Option<RegisteredHotkey>,
+    wanted: Option<Hotkey>,
+    id: u32,
+    handler: HotkeyHandler,
+) -> bool {
+    if registered.as_ref().map(RegisteredHotkey::hotkey) == wanted {
+        return false;
+    }
+    // 同じ組み合わせを登録し直せるよう、先に古い登録を外す
+    *registered = None;
+    if let Some(hotkey) = wanted {
+        let delegate = this as *const AnyObject as *mut c_void;
+        match RegisteredHotkey::register(hotkey, id, handler, delegate) {
+            Ok(hotkey) => *registered = Some(hotkey),
+            Err(error) => eprintln!("warning: {error}"),
+        }
+    }
+    true
+}
+
+// Carbon から呼ばれる。`user_data` は登録したデリゲート
+extern "C" fn history_hotkey_pressed(
+    _: *mut c_void,
+    event: *mut c_void,
+    user_data: *mut c_void,
+) -> i32 {
+    if unsafe { pressed_hotkey_id(event) } != Some(HISTORY_HOTKEY_ID) {
+        return EVENT_NOT_HANDLED_ERR;
+    }
+    WAKEUP_COUNT.fetch_add(1, Ordering::Relaxed);
+    autoreleasepool(|_| unsafe {
+        let this = &*(user_data as *const AnyObject);
+        with_app_state(|state| step_history_cycle(this, state));
+    });
+    0
+}
+
+// 押すたびに1つ古い履歴を HUD に出す。最初に押したときは履歴を扱うスレッドに読み込みを頼み、
+// 読み終えてから historyCycleLoaded: で修飾キーの監視を始める
+unsafe fn step_history_cycle(this: &AnyObject, state: &mut AppState) {
+    if let Some(cycle) = state.history_cycle.as_mut() {
+        cycle.index = (cycle.index + 1) % cycle.entries.len();
+        show_history_cycle(this, state);
+        return;
+    }
+    state.history_cycle_presses += 1;
+    if state.history_cycle_presses > 1 {
+        return;
+    }
+    let job = HistoryJob::LoadCycle {
+        settings: state.settings,
+        current_text: state.previous_text.clone(),
+        delegate: this as *const AnyObject as usize,
+    };
+    if state.history_writer.send(job).is_err() {
+        eprintln!("warning: history writer thread has stopped");
+        state.history_cycle_presses = 0;
+    }
+}
+
+extern "C" fn history_cycle_loaded(this: &AnyObject, _: Sel, _: *mut AnyObject) {
+    autoreleasepool(|_| unsafe {
+        let Some(entries) = LOADED_HISTORY_CYCLE
+            .lock()
+            .expect("LOADED_HISTORY_CYCLE lock poisoned")
+            .take()
+        else {
+            return;
+        };
+        with_app_state(|state| {
+            // 読み込みの間にショートカットを登録し直していれば、押した回数も忘れている
+            let presses = std::mem::take(&mut state.history_cycle_presses);
+            if presses == 0 || entries.is_empty() {
+                return;
+            }
+            let index = (presses - 1) % entries.len();
+            state.history_cycle = Some(HistoryCycle { entries, index });
+            state.history_cycle_timer = ScheduledTimer::schedule(
+                HISTORY_CYCLE_TICK_INTERVAL_SECS,
+                this,
+                sel!(historyCycleTick:),
+                true,
+            );
+            show_history_cycle(this, state);
+        });
+    });
+}
+
+unsafe fn show_history_cycle(this: &AnyObject, state: &mut AppState) {
+    let Some(cycle) = state.history_cycle.as_ref() else {
+        return;
+    };
+    let entry = &cycle.entries[cycle.index];
+    let context = ClipContext {
+        copied_at: entry
+            .copied_at_time()
+            .map(|time| time.with_timezone(&Local).time()),
+        ..ClipContext::default()
+    };
+    // どれだけ前の内容か分かるよう、`show_time` にかかわらずコピーした時刻を出す
+    let settings = DisplaySettings {
+        show_time: true,
+        ..state.settings
+    };
+    let mut content = build_hud_content(&entry.text, context, settings);
+    content.footer = Some(history_cycle_footer(
+        cycle.index,
+        cycle.entries.len(),
+        content.footer.as_deref(),
+    ));
+    present_hud(this, state, &content, state.settings.hud_duration_secs);
+    // 選んでいる間は隠さない。確定したときに改めて隠すタイマーを仕掛ける
+    state.hide_timer = None;
+}
+
+extern "C" fn append_hotkey_pressed(
+    _: *mut c_void,
+    event: *mut c_void,
+    user_data: *mut c_void,
+) -> i32 {
+    if unsafe { pressed_hotkey_id(event) } != Some(APPEND_HOTKEY_ID) {
+        return EVENT_NOT_HANDLED_ERR;
+    }
+    WAKEUP_COUNT.fetch_add(1, Ordering::Relaxed);
+    autoreleasepool(|_| unsafe {
+        let this = &*(user_data as *const AnyObject);
+        with_app_state(|state| toggle_append_mode(this, state));
+    });
+    0
+}
+
+// 追記モードを切り替え、どちらになったかを HUD で知らせる。つないできたテキストは
+// クリップボードに残したまま、次にオンにしたときは新しくつなぎ始める
+unsafe fn toggle_append_mode(this: &AnyObject, state: &mut AppState) {
+    state.append_active = !state.append_active;
+    state.append_buffer = None;
+    let (text, footer) = if state.append_active {
+        ("Append mode on", Some(APPEND_MODE_ON_FOOTER.to_string()))
+    } else {
+        ("Append mode off", None)
+    };
+    let content = HudContent {
+        footer,
+        ..build_hud_content(text, ClipContext::default(), state.settings)
+    };
+    present_hud(this, state, &content, state.settings.hud_duration_secs);
+}
+
+extern "C" fn history_cycle_tick(this: &AnyObject, _: Sel, timer: *mut AnyObject) {
+    WAKEUP_COUNT.fetch_add(1, Ordering::Relaxed);
+    autoreleasepool(|_| unsafe {
+        let handled = with_app_state(|state| {
+            let Some(hotkey) = state.history_hotkey.as_ref().map(RegisteredHotkey::hotkey) else {
+                state.history_cycle = None;
+                state.history_cycle_timer = None;
+                return;
+            };
+            let flags: usize = msg_send![class!(NSEvent), modifierFlags];
+            if flags & hotkey.modifier_flags() == 0 {
+                confirm_history_cycle(this, state);
+            }
+        });
+        if handled.is_none() {
+            let () = msg_send![timer, invalidate];
+        }
+    });
+}
+
+// 選んでいた履歴をクリップボードに戻す。自分の書き込みなので HUD は出し直さない
+unsafe fn confirm_history_cycle(this: &AnyObject, state: &mut AppState) {
+    state.history_cycle_timer = None;
+    let Some(mut cycle) = state.history_cycle.take() else {
+        return;
+    };
+    let text = cycle.entries.swap_remove(cycle.index).text;
+    if write_plain_text(state.pasteboard, &text) {
+        state.last_change_count = msg_send![state.pasteboard, changeCount];
+        state.previous_text = Some(text);
+        state.clip_repeat_count = 1;
+    }
+    state.hide_timer = ScheduledTimer::schedule(
+        state.settings.hud_duration_secs,
+        this,
+        sel!(hideHud:),
+        false,
+    );
+}
+
+fn history_limits(settings: &DisplaySettings) -> HistoryLimits {
+    let max_age_days = settings.history_max_age_days as i64;
+    HistoryLimits {
+        max_entries: settings.history_max_entries,
+        max_age: (max_age_days > 0).then(|| TimeDelta::days(max_age_days)),
+        max_bytes: (settings.history_max_bytes > 0).then_some(settings.history_max_bytes as u64),
+    }
+}
+
+unsafe fn prepare_image_clip(
+    this: &AnyObject,
+    state: &mut AppState,
+    image_type_name: &'static str,
+) {
+    let image_type = cached_nsstring(image_type_name);
+    let data: *mut AnyObject = msg_send![state.pasteboard, dataForType: image_type];
+    let Some(image) = clip_image_from_data(data) else {
+        return;
+    };
+    prepare_main_thread_clip(this, state, |context, settings| {
+        build_image_hud_content(&image, context, settings)
+    });
+}
+
+unsafe fn prepare_binary_clip(this: &AnyObject, state: &mut AppState, type_name: &str) {
+    let binary = ClipBinary {
+        type_name: type_name.to_string(),
+        byte_len: pasteboard_data_len(state.pasteboard, type_name).map(|len| len as u64),
+    };
+    prepare_main_thread_clip(this, state, |context, settings| {
+        build_binary_hud_content(&binary, context, settings)
+    });
+}
+
+unsafe fn prepare_file_clip(this: &AnyObject, state: &mut AppState, paths: &[PathBuf]) {
+    let files = ClipFiles {
+        names: paths.iter().map(|path| file_display_name(path)).collect(),
+        total_bytes: total_file_bytes(paths),
+        icon_png: file_icon_png(paths),
+        modified: match paths {
+            [path] => file_modified(path),
+            _ => None,
+        },
+    };
+    prepare_main_thread_clip(this, state, |context, settings| {
+        build_files_hud_content(&files, context, settings)
+    });
+}
+
+// パスの文字列をコピーしたときは、名前だけでなくどこにあるかも分かるようパス全体を出す
+unsafe fn prepare_path_clip(this: &AnyObject, state: &mut AppState, path: &Path) {
+    let paths = [path];
+    let files = ClipFiles {
+        names: vec![path.display().to_string()],
+        total_bytes: total_file_bytes(&paths),
+        icon_png: file_icon_png(&paths),
+        modified: file_modified(path),
+    };
+    prepare_main_thread_clip(this, state, |context, settings| {
+        build_files_hud_content(&files, context, settings)
+    });
+}
+
+// 画像の縮小やファイルのアイコンには AppKit を使うので、テキストと違ってメインスレッドで
+// 内容を組み立てる
+unsafe fn prepare_main_thread_clip(
+    this: &AnyObject,
+    state: &mut AppState,
+    build: impl FnOnce(ClipContext, DisplaySettings) -> HudContent,
+) {
+    let now = Local::now();
+    let context = ClipContext {
+        copied_at: Some(now.time()),
+        clip_number_today: Some(state.clip_counter.record(now.date_naive())),
+        secs_since_previous: state
+            .last_clip_at
+            .replace(Instant::now())
+            .map(|previous| previous.elapsed().as_secs() as i64),
+        from_other_device: state.clip_from_other_device,
+        type_badges: &state.clip_type_badges,
+        source_app: state.clip_source_app.as_ref(),
+        repeat_count: state.clip_repeat_count,
+        ..ClipContext::default()
+    };
+    let content = build(context, state.settings);
+    *PREPARED_CLIP.lock().expect("PREPARED_CLIP lock poisoned") = Some(PreparedClip {
+        change_count: state.last_change_count,
+        content,
+        sensitive: false,
+        speech: None,
+        favicon_url: None,
+        qr_url: None,
+    });
+    // 状態を借りている間は表示できないので、ポーリングから戻ってから showPreparedClip: で出す
+    let () = msg_send![
+        this,
+        performSelectorOnMainThread: sel!(showPreparedClip:)
+        withObject: ptr::null_mut::<AnyObject>()
+        waitUntilDone: false
+    ];
+}
+
+extern "C" fn show_prepared_clip(this: &AnyObject, _: Sel, _: *mut AnyObject) {
+    WAKEUP_COUNT.fetch_add(1, Ordering::Relaxed);
+    autoreleasepool(|_| unsafe {
+        let Some(prepared) = PREPARED_CLIP
+            .lock()
+            .expect("PREPARED_CLIP lock poisoned")
+            .take()
+        else {
+            return;
+        };
+        with_app_state(|state| {
+            // 組み立て中に次のコピーがあった場合は古い内容を表示しない
+            if prepared.change_count != state.last_change_count {
+                return;
+            }
+
+            let mut content = prepared.content;
+            cancel_pending_clear(state);
+            if prepared.sensitive {
+                let footer_base = content.footer.take();
+                content.footer = Some(clear_countdown_footer(
+                    footer_base.as_deref(),
+                    state.settings.auto_clear_secs.ceil() as i64,
+                ));
+                state.pending_clear = Some(PendingClear {
+                    change_count: state.last_change_count,
+                    deadline: Instant::now()
+                        + Duration::from_secs_f64(state.settings.auto_clear_secs),
+                    footer_base,
+                });
+                state.clear_timer = ScheduledTimer::schedule(
+                    AUTO_CLEAR_TICK_INTERVAL_SECS,
+                    this,
+                    sel!(autoClearTick:),
+                    true,
+                );
+            }
+            // 自動クリアは静かな時間帯でも行い、表示だけを抑える
+            if state.settings.quiet_hours.contains_now() {
+                match state.settings.quiet_mode {
+                    QuietMode::Suppress => return,
+                    QuietMode::Minimal => content = minimal_hud_content(content, state.settings),
+                }
+            }
+            // 1行だけの HUD ではリンクのアイコンも出さないので、QR コードもファビコンも出さない
+            if let Some(url) = prepared.qr_url.filter(|_| content.icon.is_some()) {
+                content.thumbnail = qr_code_png(&url);
+            }
+            present_hud(this, state, &content, state.settings.hud_duration_secs);
+            // QR コードはアイコンと同じ位置に出すので、出している間はファビコンを取りに行かない
+            if let Some(url) = prepared
+                .favicon_url
+                .filter(|_| content.icon.is_some() && content.thumbnail.is_none())
+            {
+                fetch_favicon(this, prepared.change_count, url);
+            }
+
+            if state.settings.accessibility_announce {
+                let app: *mut AnyObject = msg_send![class!(NSApplication), sharedApplication];
+                announce_for_accessibility(app, &content.text);
+            }
+            if let Some(text) = prepared.speech.as_deref() {
+                if state.speech.is_none() {
+                    state.speech = SpeechSynthesizer::new(speech_voice().as_deref());
+                }
+                if let Some(speech) = &state.speech {
+                    speech.speak(text, state.settings.accessibility_speak_rate);
+                }
+            }
+        });
+    });
+}
+
+// 表示を待たせないよう、ファビコンは HUD を出してから別スレッドで取得する
+fn fetch_favicon(this: &AnyObject, change_count: isize, url: String) {
+    let delegate = this as *const AnyObject as usize;
+    thread::spawn(move || {
+        autoreleasepool(|_| unsafe {
+            let url_string = nsstring_from_str(&url);
+            let ns_url: *mut AnyObject = msg_send![class!(NSURL), URLWithString: url_string];
+            let () = msg_send![url_string, release];
+            if ns_url.is_null() {
+                return;
+            }
+            let data: *mut AnyObject = msg_send![class!(NSData), dataWithContentsOfURL: ns_url];
+            let data = nsdata_to_vec(data);
+            if data.is_empty() || data.len() > MAX_FAVICON_BYTES {
+                return;
+            }
+            *FETCHED_FAVICON
+                .lock()
+                .expect("FETCHED_FAVICON lock poisoned") =
+                Some(FetchedFavicon { change_count, data });
+            let delegate = delegate as *mut AnyObject;
+            let () = msg_send![
+                delegate,
+                performSelectorOnMainThread: sel!(showFavicon:)
+                withObject: ptr::null_mut::<AnyObject>()
+                waitUntilDone: false
+            ];
+        });
+    });
+}
+
+extern "C" fn show_favicon(_this: &AnyObject, _: Sel, _: *mut AnyObject) {
+    WAKEUP_COUNT.fetch_add(1, Ordering::Relaxed);
+    autoreleasepool(|_| unsafe {
+        let Some(favicon) = FETCHED_FAVICON
+            .lock()
+            .expect("FETCHED_FAVICON lock poisoned")
+            .take()
+        else {
+            return;
+        };
+        with_app_state(|state| {
+            // 取得中に次のコピーがあった場合は、新しいクリップの HUD に古いファビコンを出さない
+            if favicon.change_count != state.last_change_count {
+                return;
+            }
+            if let Some(views) = state.views() {
+                set_icon_image(&views, &favicon.data);
+            }
+        });
+    });
+}
+
+// 内容を反映して前面に出し、`duration_secs` 後に隠すタイマーを仕掛ける
+unsafe fn present_hud(
+    target: &AnyObject,
+    state: &mut AppState,
+    content: &HudContent,
+    duration_secs: f64,
+) {
+    let views = state.hud_views();
+    apply_hud_content(&views, content, state.settings);
+
+    // フェード中なら止めてアルファを戻す
+    state.fade_timer = None;
+    let () = msg_send![views.window, setAlphaValue: 1.0f64];
+
+    let () = msg_send![views.window, orderFrontRegardless];
+
+    state.hide_timer = ScheduledTimer::schedule(duration_secs, target, sel!(hideHud:), false);
+}
+
+// 初回起動なら、常駐していることと設定ファイルの場所を一度だけ HUD で知らせる
+unsafe fn show_onboarding_hud(target: &AnyObject, state: &mut AppState) {
+    if ONBOARDING_DISABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let Ok(config_path) = config_file_path() else {
+        return;
+    };
+    if !needs_onboarding(&config_path) {
+        return;
+    }
+    let text = onboarding_text(&config_path);
+    // max_lines を小さくしていても案内は最後の行まで見せる
+    let mut settings = state.settings;
+    settings.truncate_max_lines = settings.truncate_max_lines.max(text.lines().count());
+    let content = HudContent {
+        footer: Some(ONBOARDING_FOOTER.to_string()),
+        ..build_hud_content(&text, ClipContext::default(), settings)
+    };
+    let duration_secs = state
+        .settings
+        .hud_duration_secs
+        .max(ONBOARDING_HUD_DURATION_SECS);
+    present_hud(target, state, &content, duration_secs);
+    if let Err(error) = mark_onboarded(&config_path) {
+        eprintln!("warning: {error}");
+    }
+}
+
+extern "C" fn hide_hud(this: &AnyObject, _: Sel, _: *mut AnyObject) {
+    WAKEUP_COUNT.fetch_add(1, Ordering::Relaxed);
+    autoreleasepool(|_| unsafe {
+        with_app_state(|state| {
+            state.hide_timer = None;
+
+            let fade_duration = state.settings.hud_fade_duration_secs;
+            if fade_duration <= 0.0 {
+                // フェードなし: 即時非表示
+                state.fade_timer = None;
+                if let Some(views) = state.views() {
+                    let () = msg_send![views.window, orderOut: ptr::null_mut::<AnyObject>()];
+                }
+                return;
+            }
+
+            // フェードアウト開始
+            let total_fade_ticks = (fade_duration / FADE_TICK_INTERVAL_SECS).ceil() as u32;
+            state.fade_total_ticks = total_fade_ticks;
+            state.fade_timer = None;
+            state.fade_ticks_elapsed = 0;
+
+            state.fade_timer =
+                ScheduledTimer::schedule(FADE_TICK_INTERVAL_SECS, this, sel!(fadeTick:), true);
+        });
+    });
+}
+
+extern "C" fn fade_tick(_: &AnyObject, _: Sel, timer: *mut AnyObject) {
+    WAKEUP_COUNT.fetch_add(1, Ordering::Relaxed);
+    autoreleasepool(|_| unsafe {
+        // 状態の借用を返してからウィンドウを操作する。フェードを終えるときは
+        // fade_timer を手放すだけで invalidate される
+        let step = with_app_state(|state| {
+            let Some(views) = state.views() else {
+                state.fade_timer = None;
+                return None;
+            };
+            state.fade_ticks_elapsed += 1;
+
+            if state.fade_ticks_elapsed >= state.fade_total_ticks {
+                debug_assert!(state.fade_timer.is_some());
+                state.fade_timer = None;
+                Some((views.window, None))
+            } else {
+                let alpha = 1.0 - (state.fade_ticks_elapsed as f64 / state.fade_total_ticks as f64);
+                Some((views.window, Some(alpha)))
+            }
+        });
+
+        match step {
+            Some(Some((window, Some(alpha)))) => {
+                let () = msg_send![window, setAlphaValue: alpha];
+            }
+            Some(Some((window, None))) => {
+                let () = msg_send![window, setAlphaValue: 0.0f64];
+                let () = msg_send![window, orderOut: ptr::null_mut::<AnyObject>()];
+                let () = msg_send![window, setAlphaValue: 1.0f64];
+            }
+            Some(None) => {}
+            // 状態がなければ誰もこのタイマーを所有していないので、ここで止める
+            None => {
+                let () = msg_send![timer, invalidate];
+            }
+        }
+    });
+}
+
+extern "C" fn auto_clear_tick(_: &AnyObject, _: Sel, timer: *mut AnyObject) {
+    WAKEUP_COUNT.fetch_add(1, Ordering::Relaxed);
+    autoreleasepool(|_| unsafe {
+        let handled = with_app_state(|state| {
+            let Some(pending) = state.pending_clear.as_ref() else {
+                cancel_pending_clear(state);
+                return;
+            };
+
+            // 別の内容がコピーされていたら消さない
+            let change_count: isize = msg_send![state.pasteboard, changeCount];
+            if change_count != pending.change_count {
+                cancel_pending_clear(state);
+                return;
+            }
+
+            let remaining = pending.deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                let _: isize = msg_send![state.pasteboard, clearContents];
+                state.last_change_count = msg_send![state.pasteboard, changeCount];
+                cancel_pending_clear(state);
+                return;
+            }
+
+            let Some(views) = state.views() else {
+                return;
+            };
+            let visible: bool = msg_send![views.window, isVisible];
+            if visible {
+                let footer = clear_countdown_footer(
+                    pending.footer_base.as_deref(),
+                    remaining.as_secs_f64().ceil() as i64,
+                );
+                set_label_text(views.footer_label, &footer);
+            }
+        });
+        if handled.is_none() {
+            let () = msg_send![timer, invalidate];
+        }
+    });
+}
+
+fn cancel_pending_clear(state: &mut AppState) {
+    state.clear_timer = None;
+    state.pending_clear = None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{next_poll_interval_secs, FADE_TICK_INTERVAL_SECS};
+    use cliip_show::config::{default_display_settings, DEFAULT_HUD_FADE_DURATION_SECS};
+
+    #[test]
+    fn next_poll_interval_backs_off_only_after_idle_threshold() {
+        let mut settings = default_display_settings();
+        settings.poll_interval_secs = 0.3;
+        settings.poll_idle_after_secs = 120.0;
+        settings.poll_max_interval_secs = 2.0;
+
+        assert_eq!(next_poll_interval_secs(0.3, 60.0, settings), 0.3);
+        assert!((next_poll_interval_secs(0.3, 120.0, settings) - 0.45).abs() < 1e-9);
+        assert_eq!(next_poll_interval_secs(1.8, 600.0, settings), 2.0);
+        assert_eq!(next_poll_interval_secs(2.0, 600.0, settings), 2.0);
+
+        settings.poll_idle_after_secs = 0.0;
+        assert_eq!(next_poll_interval_secs(2.0, 600.0, settings), 0.3);
+
+        settings.poll_idle_after_secs = 120.0;
+        settings.poll_max_interval_secs = 0.1;
+        assert_eq!(next_poll_interval_secs(0.3, 600.0, settings), 0.3);
+    }
+
+    #[test]
+    fn fade_total_ticks_calculation_is_exact() {
+        // fade_duration=DEFAULT_HUD_FADE_DURATION_SECS, FADE_TICK_INTERVAL_SECS=1/60 → 18 ticks
+        let total = (DEFAULT_HUD_FADE_DURATION_SECS / FADE_TICK_INTERVAL_SECS).ceil() as u32;
+        assert_eq!(total, 18);
+    }
+
+    #[test]
+    fn fade_alpha_is_positive_at_penultimate_tick() {
+        // elapsed >= total のとき fade_tick は termination ブランチへ進み alpha 計算は行われない。
+        // 計算式が呼ばれる最後の tick は elapsed = total - 1。
+        // そのとき alpha は 0.0 より大きく 1/total 以下になることを確認する。
+        let total: u32 = 18;
+        let elapsed: u32 = total - 1;
+        let alpha = 1.0 - (elapsed as f64 / total as f64);
+        assert!(alpha > 0.0, "alpha should be > 0.0, got {}", alpha);
+        // 浮動小数点誤差を考慮し、等値確認する
+        assert!(
+            (alpha - 1.0 / total as f64).abs() < 1e-10,
+            "alpha should be approximately 1/total={}, got {}",
+            1.0 / total as f64,
+            alpha
+        );
+    }
+
+    #[test]
+    fn fade_alpha_is_half_at_midpoint() {
+        let total: u32 = 18;
+        let elapsed: u32 = 9; // 半分経過
+        let alpha = 1.0 - (elapsed as f64 / total as f64);
+        assert!((alpha - 0.5).abs() < 1e-10, "alpha={}", alpha);
+    }
+}
@@ -0,0 +1,300 @@
+use std::ptr;
+
+use chrono::NaiveDate;
+use objc2::runtime::{AnyObject, Sel};
+use objc2::{class, msg_send};
+
+use crate::pasteboard::{cached_nsstring, nsstring_from_str, nsstring_to_string};
+
+pub const CONFIG_CHANGED_NOTIFICATION: &str = "io.github.somei-san.cliip-show.config-changed";
+pub const STATS_REQUEST_NOTIFICATION: &str = "io.github.somei-san.cliip-show.stats-request";
+pub const STATS_RESPONSE_NOTIFICATION: &str = "io.github.somei-san.cliip-show.stats-response";
+pub const HISTORY_PAUSE_NOTIFICATION: &str = "io.github.somei-san.cliip-show.history-pause";
+pub const HISTORY_RESUME_NOTIFICATION: &str = "io.github.somei-san.cliip-show.history-resume";
+
+/// 常駐プロセスが履歴を記録しているか。`history pause` の一時停止は再起動すると解ける。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryRecording {
+    /// `history_enabled = false`
+    Disabled,
+    Recording,
+    Paused,
+}
+
+impl HistoryRecording {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Disabled => "disabled",
+            Self::Recording => "recording",
+            Self::Paused => "paused",
+        }
+    }
+
+    fn parse(raw: &str) -> Option<Self> {
+        [Self::Disabled, Self::Recording, Self::Paused]
+            .into_iter()
+            .find(|recording| recording.as_str() == raw)
+    }
+}
+
+/// 常駐プロセスが `stats` の問い合わせに返す値。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DaemonStats {
+    pub pid: u32,
+    pub uptime_secs: f64,
+    pub wakeups: u64,
+    pub objc_objects: usize,
+    pub clips_total: u64,
+    pub clips_today: u64,
+    pub history: HistoryRecording,
+}
+
+impl DaemonStats {
+    // 分散通知の object には文字列しか載せられないので key=value の行で受け渡す
+    pub fn to_payload(&self) -> String {
+        format!(
+            "pid={}\nuptime_secs={}\nwakeups={}\nobjc_objects={}\nclips_total={}\nclips_today={}\nhistory={}",
+            self.pid,
+            self.uptime_secs,
+            self.wakeups,
+            self.objc_objects,
+            self.clips_total,
+            self.clips_today,
+            self.history.as_str()
+        )
+    }
+
+    pub fn from_payload(payload: &str) -> Option<Self> {
+        let value = |key: &str| {
+            payload.lines().find_map(|line| {
+                let (name, value) = line.split_once('=')?;
+                (name.trim() == key).then(|| value.trim())
+            })
+        };
+        Some(Self {
+            pid: value("pid")?.parse().ok()?,
+            uptime_secs: value("uptime_secs")?.parse().ok()?,
+            wakeups: value("wakeups")?.parse().ok()?,
+            objc_objects: value("objc_objects")?.parse().ok()?,
+            clips_total: value("clips_total")?.parse().ok()?,
+            clips_today: value("clips_today")?.parse().ok()?,
+            history: HistoryRecording::parse(value("history")?)?,
+        })
+    }
+
+    pub fn wakeups_per_sec(&self) -> f64 {
+        if self.uptime_secs <= 0.0 {
+            return 0.0;
+        }
+        self.wakeups as f64 / self.uptime_secs
+    }
+}
+
+/// 表示したクリップの数。起動からの合計と、ローカル時刻の日付が変わるたびに数え直す今日の分を持つ。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ClipCounter {
+    pub total: u64,
+    today: u64,
+    day: Option<NaiveDate>,
+}
+
+impl ClipCounter {
+    /// `date` に1件数え、その日の何件目かを返す。
+    pub fn record(&mut self, date: NaiveDate) -> u64 {
+        if self.day != Some(date) {
+            self.day = Some(date);
+            self.today = 0;
+        }
+        self.total += 1;
+        self.today += 1;
+        self.today
+    }
+
+    /// 日付をまたいでから数えていなければ、今日の分は 0 とみなす。
+    pub fn today_on(&self, date: NaiveDate) -> u64 {
+        if self.day == Some(date) {
+            self.today
+        } else {
+            0
+        }
+    }
+}
+
+/// `ps` で取得した RSS と CPU 時間を合わせて `stats --resources` の出力を組み立てる。
+pub fn resource_report(stats: &DaemonStats, rss_kb: Option<u64>, cpu_time: Option<&str>) -> String {
+    let unknown = || "unknown".to_string();
+    [
+        format!("pid = {}", stats.pid),
+        format!(
+            "rss_kb = {}",
+            rss_kb.map_or_else(unknown, |rss| rss.to_string())
+        ),
+        format!(
+            "cpu_time = {}",
+            cpu_time.map_or_else(unknown, str::to_string)
+        ),
+        format!("uptime_secs = {:.0}", stats.uptime_secs),
+        format!("wakeups = {}", stats.wakeups),
+        format!("wakeups_per_sec = {:.2}", stats.wakeups_per_sec()),
+        format!("objc_objects = {}", stats.objc_objects),
+        format!("clips_total = {}", stats.clips_total),
+        format!("clips_today = {}", stats.clips_today),
+    ]
+    .join("\n")
+}
+
+/// `--status` の出力。常駐プロセスが応答しなければ `running = false` だけを出す。
+pub fn status_report(stats: Option<&DaemonStats>) -> String {
+    let Some(stats) = stats else {
+        return "running = false".to_string();
+    };
+    [
+        "running = true".to_string(),
+        format!("pid = {}", stats.pid),
+        format!("uptime_secs = {:.0}", stats.uptime_secs),
+        format!("clips_today = {}", stats.clips_today),
+        format!("history = {}", stats.history.as_str()),
+    ]
+    .join("\n")
+}
+
+/// `ps -o rss=,cputime=` の出力から RSS(KB) と CPU 時間を取り出す。
+pub fn parse_ps_resources(output: &str) -> Option<(u64, String)> {
+    let mut fields = output.split_whitespace();
+    let rss_kb = fields.next()?.parse().ok()?;
+    let cpu_time = fields.next()?.to_string();
+    Some((rss_kb, cpu_time))
+}
+
+/// # Safety
+///
+/// Objective-C ランタイムが利用可能であること。
+pub unsafe fn post_control_notification(name: &'static str, payload: Option<&str>) {
+    let center: *mut AnyObject = msg_send![class!(NSDistributedNotificationCenter), defaultCenter];
+    let name = cached_nsstring(name);
+    let object = payload.map_or(ptr::null_mut(), |payload| nsstring_from_str(payload));
+    let () = msg_send![
+        center,
+        postNotificationName: name
+        object: object
+        userInfo: ptr::null_mut::<AnyObject>()
+        deliverImmediately: true
+    ];
+    if !object.is_null() {
+        let () = msg_send![object, release];
+    }
+}
+
+/// # Safety
+///
+/// `observer` は `selector` を実装したオブジェクトで、通知を受け取る間は解放しないこと。
+pub unsafe fn observe_control_notification(
+    observer: *const AnyObject,
+    selector: Sel,
+    name: &'static str,
+) {
+    let center: *mut AnyObject = msg_send![class!(NSDistributedNotificationCenter), defaultCenter];
+    let name = cached_nsstring(name);
+    let () = msg_send![
+        center,
+        addObserver: observer
+        selector: selector
+        name: name
+        object: ptr::null_mut::<AnyObject>()
+    ];
+}
+
+/// # Safety
+///
+/// `observer` は [`observe_control_notification`] で登録したオブジェクトであること。
+pub unsafe fn remove_control_observer(observer: *const AnyObject) {
+    let center: *mut AnyObject = msg_send![class!(NSDistributedNotificationCenter), defaultCenter];
+    let () = msg_send![center, removeObserver: observer];
+}
+
+/// # Safety
+///
+/// `notification` は null か有効な `NSNotification` であること。
+pub unsafe fn notification_payload(notification: *mut AnyObject) -> Option<String> {
+    if notification.is_null() {
+        return None;
+    }
+    let object: *mut AnyObject = msg_send![notification, object];
+    nsstring_to_string(object)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::{
+        parse_ps_resources, resource_report, status_report, ClipCounter, DaemonStats,
+        HistoryRecording,
+    };
+
+    #[test]
+    fn daemon_stats_round_trips_through_payload() {
+        let stats = DaemonStats {
+            pid: 4242,
+            uptime_secs: 120.5,
+            wakeups: 400,
+            objc_objects: 7,
+            clips_total: 52,
+            clips_today: 47,
+            history: HistoryRecording::Paused,
+        };
+        assert_eq!(DaemonStats::from_payload(&stats.to_payload()), Some(stats));
+        assert_eq!(DaemonStats::from_payload("pid=1\nwakeups=2"), None);
+    }
+
+    #[test]
+    fn resource_report_includes_wakeup_rate() {
+        let stats = DaemonStats {
+            pid: 4242,
+            uptime_secs: 200.0,
+            wakeups: 500,
+            objc_objects: 7,
+            clips_total: 52,
+            clips_today: 47,
+            history: HistoryRecording::Recording,
+        };
+        let (rss_kb, cpu_time) = parse_ps_resources(" 18432   0:01.25\n").expect("ps output");
+        assert_eq!(
+            resource_report(&stats, Some(rss_kb), Some(&cpu_time)),
+            "pid = 4242\nrss_kb = 18432\ncpu_time = 0:01.25\nuptime_secs = 200\nwakeups = 500\nwakeups_per_sec = 2.50\nobjc_objects = 7\nclips_total = 52\nclips_today = 47"
+        );
+        assert!(resource_report(&stats, None, None).contains("rss_kb = unknown"));
+    }
+
+    #[test]
+    fn status_report_shows_history_recording_state() {
+        let stats = DaemonStats {
+            pid: 4242,
+            uptime_secs: 59.6,
+            wakeups: 10,
+            objc_objects: 7,
+            clips_total: 3,
+            clips_today: 2,
+            history: HistoryRecording::Paused,
+        };
+        assert_eq!(
+            status_report(Some(&stats)),
+            "running = true\npid = 4242\nuptime_secs = 60\nclips_today = 2\nhistory = paused"
+        );
+        assert_eq!(status_report(None), "running = false");
+    }
+
+    #[test]
+    fn clip_counter_resets_today_at_midnight() {
+        let monday = NaiveDate::from_ymd_opt(2024, 1, 1).expect("date");
+        let tuesday = NaiveDate::from_ymd_opt(2024, 1, 2).expect("date");
+        let mut counter = ClipCounter::default();
+        assert_eq!(counter.today_on(monday), 0);
+        assert_eq!(counter.record(monday), 1);
+        assert_eq!(counter.record(monday), 2);
+        assert_eq!(counter.today_on(tuesday), 0);
+        assert_eq!(counter.record(tuesday), 1);
+        assert_eq!(counter.total, 3);
+        assert_eq!(counter.today_on(tuesday), 1);
+    }
+}
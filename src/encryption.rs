@@ -0,0 +1,253 @@
+use std::ptr;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use hmac::{Hmac, Mac};
+use objc2::runtime::AnyObject;
+use objc2::{class, msg_send};
+use sha2::Sha256;
+
+use crate::pasteboard::{nsdata_to_vec, nsstring_from_str};
+
+// 暗号化した行の先頭。平文の行は JSON なので `{` で始まり、取り違えない
+const ENCRYPTED_LINE_PREFIX: &str = "enc1:";
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 24;
+const TAG_LEN: usize = 16;
+const KEYCHAIN_SERVICE: &str = "io.github.somei-san.cliip-show";
+const KEYCHAIN_ACCOUNT: &str = "history-encryption-key";
+const SYNC_KEYCHAIN_ACCOUNT: &str = "history-sync-key";
+const ERR_SEC_SUCCESS: i32 = 0;
+const ERR_SEC_ITEM_NOT_FOUND: i32 = -25300;
+
+extern "C" {
+    static kSecClass: *mut AnyObject;
+    static kSecClassGenericPassword: *mut AnyObject;
+    static kSecAttrService: *mut AnyObject;
+    static kSecAttrAccount: *mut AnyObject;
+    static kSecReturnData: *mut AnyObject;
+    static kSecValueData: *mut AnyObject;
+
+    fn SecItemCopyMatching(query: *mut AnyObject, result: *mut *mut AnyObject) -> i32;
+    fn SecItemAdd(attributes: *mut AnyObject, result: *mut *mut AnyObject) -> i32;
+    fn SecItemDelete(query: *mut AnyObject) -> i32;
+}
+
+/// 履歴の1行ずつを暗号化・復号する鍵。
+///
+/// XChaCha20-Poly1305 で暗号化し、鍵の違いや改ざんは認証タグで検出する。nonce が 192 ビット
+/// あるので、行ごとに乱数で作ってよい。Keychain の鍵はそのまま使わず、HMAC-SHA256 で
+/// 履歴の暗号化用の鍵を導く。
+#[derive(Clone)]
+pub struct HistoryCipher {
+    aead: XChaCha20Poly1305,
+}
+
+impl std::fmt::Debug for HistoryCipher {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter.write_str("HistoryCipher(..)")
+    }
+}
+
+impl HistoryCipher {
+    pub fn new(master_key: &[u8; KEY_LEN]) -> Self {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(master_key)
+            .expect("HMAC accepts keys of any length");
+        mac.update(b"cliip-show history encryption");
+        Self {
+            aead: XChaCha20Poly1305::new(&mac.finalize().into_bytes()),
+        }
+    }
+
+    pub fn encrypt_line(&self, plaintext: &str) -> Result<String, String> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .aead
+            .encrypt(&nonce, plaintext.as_bytes())
+            .map_err(|_| "failed to encrypt history line".to_string())?;
+        let mut sealed = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(format!(
+            "{ENCRYPTED_LINE_PREFIX}{}",
+            STANDARD.encode(sealed)
+        ))
+    }
+
+    /// 暗号化した行を復号する。タグが合わなければ（鍵が違う・改ざんされた）エラー。
+    pub fn decrypt_line(&self, line: &str) -> Result<String, String> {
+        let encoded = line
+            .strip_prefix(ENCRYPTED_LINE_PREFIX)
+            .ok_or_else(|| "history line is not encrypted".to_string())?;
+        let sealed = STANDARD
+            .decode(encoded.trim_end())
+            .map_err(|err| format!("failed to decode encrypted history line: {err}"))?;
+        if sealed.len() < NONCE_LEN + TAG_LEN {
+            return Err("encrypted history line is too short".to_string());
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let plaintext = self
+            .aead
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| {
+                "failed to authenticate encrypted history line (wrong key or corrupted file)"
+                    .to_string()
+            })?;
+        String::from_utf8(plaintext)
+            .map_err(|_| "decrypted history line is not valid UTF-8".to_string())
+    }
+}
+
+/// 暗号化した履歴の行か。
+pub fn is_encrypted_line(line: &str) -> bool {
+    line.starts_with(ENCRYPTED_LINE_PREFIX)
+}
+
+/// Keychain から履歴の鍵を読む。まだなければ `create` のときだけ作って保存する。
+///
+/// 鍵がなく `create` でもなければ `Ok(None)`。
+///
+/// # Safety
+///
+/// Objective-C ランタイムが利用可能であること。
+pub unsafe fn history_keychain_key(create: bool) -> Result<Option<[u8; KEY_LEN]>, String> {
+    keychain_key(KEYCHAIN_ACCOUNT, "history key", create)
+}
+
+/// `[sync]` の同期ファイルを暗号化する鍵を読む。同期するすべての Mac で同じ鍵を使う。
+///
+/// # Safety
+///
+/// [`history_keychain_key`] と同じ。
+pub unsafe fn sync_keychain_key(create: bool) -> Result<Option<[u8; KEY_LEN]>, String> {
+    keychain_key(SYNC_KEYCHAIN_ACCOUNT, "sync key", create)
+}
+
+/// ほかの Mac から書き出した同期の鍵を保存する。すでにあれば置き換える。
+///
+/// # Safety
+///
+/// Objective-C ランタイムが利用可能であること。
+pub unsafe fn save_sync_keychain_key(key: &[u8; KEY_LEN]) -> Result<(), String> {
+    let query = keychain_query(SYNC_KEYCHAIN_ACCOUNT);
+    let status = SecItemDelete(query);
+    let () = msg_send![query, release];
+    if status != ERR_SEC_SUCCESS && status != ERR_SEC_ITEM_NOT_FOUND {
+        return Err(format!(
+            "failed to replace sync key in Keychain (OSStatus {status})"
+        ));
+    }
+    add_keychain_key(SYNC_KEYCHAIN_ACCOUNT, "sync key", key)
+}
+
+/// 同期の鍵を、ほかの Mac に貼り付けて渡せる Base64 の文字列にする。
+pub fn encode_sync_key(key: &[u8; KEY_LEN]) -> String {
+    STANDARD.encode(key)
+}
+
+pub fn decode_sync_key(raw: &str) -> Result<[u8; KEY_LEN], String> {
+    STANDARD
+        .decode(raw.trim())
+        .ok()
+        .and_then(|key| key.try_into().ok())
+        .ok_or_else(|| {
+            "invalid sync key (expected the output of `history sync --export-key`)".to_string()
+        })
+}
+
+unsafe fn keychain_key(
+    account: &str,
+    label: &str,
+    create: bool,
+) -> Result<Option<[u8; KEY_LEN]>, String> {
+    let query = keychain_query(account);
+    let yes: *mut AnyObject = msg_send![class!(NSNumber), numberWithBool: true];
+    let () = msg_send![query, setObject: yes forKey: kSecReturnData];
+    let mut data: *mut AnyObject = ptr::null_mut();
+    let status = SecItemCopyMatching(query, &mut data);
+    let () = msg_send![query, release];
+    match status {
+        ERR_SEC_SUCCESS => {
+            let key = nsdata_to_vec(data);
+            let () = msg_send![data, release];
+            let key: [u8; KEY_LEN] = key
+                .try_into()
+                .map_err(|_| format!("{label} in Keychain has an unexpected length"))?;
+            Ok(Some(key))
+        }
+        ERR_SEC_ITEM_NOT_FOUND if create => {
+            let key: [u8; KEY_LEN] = XChaCha20Poly1305::generate_key(&mut OsRng).into();
+            add_keychain_key(account, label, &key)?;
+            Ok(Some(key))
+        }
+        ERR_SEC_ITEM_NOT_FOUND => Ok(None),
+        status => Err(format!(
+            "failed to read {label} from Keychain (OSStatus {status})"
+        )),
+    }
+}
+
+unsafe fn add_keychain_key(account: &str, label: &str, key: &[u8; KEY_LEN]) -> Result<(), String> {
+    let attributes = keychain_query(account);
+    let value: *mut AnyObject =
+        msg_send![class!(NSData), dataWithBytes: key.as_ptr() length: key.len()];
+    let () = msg_send![attributes, setObject: value forKey: kSecValueData];
+    let status = SecItemAdd(attributes, ptr::null_mut());
+    let () = msg_send![attributes, release];
+    if status != ERR_SEC_SUCCESS {
+        return Err(format!(
+            "failed to save {label} to Keychain (OSStatus {status})"
+        ));
+    }
+    Ok(())
+}
+
+// 呼び出し側で `release` する
+unsafe fn keychain_query(account: &str) -> *mut AnyObject {
+    let query: *mut AnyObject = msg_send![class!(NSMutableDictionary), new];
+    let () = msg_send![query, setObject: kSecClassGenericPassword forKey: kSecClass];
+    for (value, key) in [
+        (KEYCHAIN_SERVICE, kSecAttrService),
+        (account, kSecAttrAccount),
+    ] {
+        let value = nsstring_from_str(value);
+        let () = msg_send![query, setObject: value forKey: key];
+        let () = msg_send![value, release];
+    }
+    query
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_sync_key, encode_sync_key, is_encrypted_line, HistoryCipher};
+
+    #[test]
+    fn history_cipher_round_trips_and_rejects_other_keys() {
+        let cipher = HistoryCipher::new(&[7; 32]);
+        let plaintext = r#"{"id":1,"text":"パスワード: hunter2 and a line longer than one block"}"#;
+        let line = cipher.encrypt_line(plaintext).expect("encrypt");
+        assert!(is_encrypted_line(&line));
+        assert!(!line.contains("hunter2"));
+        assert_eq!(cipher.decrypt_line(&line).expect("decrypt"), plaintext);
+        // 同じ本文でも nonce が違えば別の行になる
+        assert_ne!(cipher.encrypt_line(plaintext).expect("encrypt"), line);
+
+        assert!(HistoryCipher::new(&[8; 32]).decrypt_line(&line).is_err());
+        let mut tampered = line.clone().into_bytes();
+        let last = tampered.len() - 5;
+        tampered[last] = if tampered[last] == b'A' { b'B' } else { b'A' };
+        let tampered = String::from_utf8(tampered).expect("utf8");
+        assert!(cipher.decrypt_line(&tampered).is_err());
+    }
+
+    #[test]
+    fn sync_key_round_trips_through_base64() {
+        let key = [42; 32];
+        let encoded = encode_sync_key(&key);
+        assert_eq!(decode_sync_key(&format!("{encoded}\n")), Ok(key));
+        assert!(decode_sync_key("not a key").is_err());
+        assert!(decode_sync_key(&encode_sync_key(&[1; 32])[..20]).is_err());
+    }
+}
@@ -0,0 +1,130 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local, NaiveDateTime};
+
+const BYTE_UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+
+/// HUD に出すファイル名。ルート（`/`）のように名前がなければパス全体を使う。
+pub fn file_display_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+/// 通常のファイルの合計サイズ。フォルダの中身は数えず、サイズの分かるファイルがなければ `None`。
+pub fn total_file_bytes(paths: &[impl AsRef<Path>]) -> Option<u64> {
+    paths
+        .iter()
+        .filter_map(|path| fs::metadata(path).ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .reduce(|total, len| total.saturating_add(len))
+}
+
+/// 更新日時をローカル時刻で返す。読めなければ `None`。
+pub fn file_modified(path: &Path) -> Option<NaiveDateTime> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    Some(DateTime::<Local>::from(modified).naive_local())
+}
+
+/// テキスト全体が実在するファイルやフォルダの絶対パス（`~/` 始まりを含む）なら、
+/// シンボリックリンクをたどった実際のパスを返す。
+pub fn existing_path_in_text(text: &str) -> Option<PathBuf> {
+    let trimmed = text.trim();
+    if trimmed.contains('\n') {
+        return None;
+    }
+    let path = match trimmed.strip_prefix("~/") {
+        Some(rest) => PathBuf::from(env::var_os("HOME")?).join(rest),
+        None => PathBuf::from(trimmed),
+    };
+    if !path.is_absolute() {
+        return None;
+    }
+    fs::canonicalize(path).ok()
+}
+
+/// Finder と同じく 1000 区切りで、`3.2 MB` のように表す。
+pub fn format_byte_size(bytes: u64) -> String {
+    if bytes < 1000 {
+        return format!("{bytes} B");
+    }
+    let mut value = bytes as f64 / 1000.0;
+    let mut unit = BYTE_UNITS[0];
+    for next in &BYTE_UNITS[1..] {
+        if value < 999.95 {
+            break;
+        }
+        value /= 1000.0;
+        unit = next;
+    }
+    format!("{value:.1} {unit}")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use super::{existing_path_in_text, file_display_name, format_byte_size, total_file_bytes};
+
+    #[test]
+    fn format_byte_size_uses_decimal_units() {
+        assert_eq!(format_byte_size(0), "0 B");
+        assert_eq!(format_byte_size(999), "999 B");
+        assert_eq!(format_byte_size(1_000), "1.0 KB");
+        assert_eq!(format_byte_size(3_210_000), "3.2 MB");
+        assert_eq!(format_byte_size(999_960), "1.0 MB");
+        assert_eq!(format_byte_size(5_000_000_000_000_000), "5000.0 TB");
+    }
+
+    #[test]
+    fn total_file_bytes_skips_directories_and_missing_paths() {
+        let dir = std::env::temp_dir().join(format!("cliip-show-files-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("folder")).expect("create dir");
+        fs::write(dir.join("a.txt"), "hello").expect("write a");
+        fs::write(dir.join("b.txt"), "world!").expect("write b");
+
+        let paths = [
+            dir.join("a.txt"),
+            dir.join("b.txt"),
+            dir.join("folder"),
+            dir.join("missing.txt"),
+        ];
+        assert_eq!(total_file_bytes(&paths), Some(11));
+        assert_eq!(total_file_bytes(&[dir.join("folder")]), None);
+        fs::remove_dir_all(&dir).expect("cleanup");
+    }
+
+    #[test]
+    fn existing_path_in_text_accepts_only_existing_absolute_paths() {
+        let dir = std::env::temp_dir().join(format!("cliip-show-path-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("create dir");
+        fs::write(dir.join("a.txt"), "hello").expect("write a");
+        let resolved = fs::canonicalize(dir.join("a.txt")).expect("canonicalize");
+
+        let text = format!("  {}\n", dir.join("a.txt").display());
+        assert_eq!(existing_path_in_text(&text), Some(resolved));
+        assert_eq!(
+            existing_path_in_text(&dir.join("b.txt").to_string_lossy()),
+            None
+        );
+        assert_eq!(existing_path_in_text("a.txt"), None);
+        let two_lines = format!("{}\n{}", dir.display(), dir.display());
+        assert_eq!(existing_path_in_text(&two_lines), None);
+        fs::remove_dir_all(&dir).expect("cleanup");
+    }
+
+    #[test]
+    fn file_display_name_falls_back_to_full_path() {
+        assert_eq!(
+            file_display_name(Path::new("/Users/me/report.pdf")),
+            "report.pdf"
+        );
+        assert_eq!(file_display_name(Path::new("/")), "/");
+    }
+}
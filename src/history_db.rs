@@ -0,0 +1,282 @@
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use rusqlite::{params, Connection, OptionalExtension, Params, Row, TransactionBehavior};
+
+use crate::history::HistoryEntry;
+
+// CLI と常駐プロセスが同時に書くときに、すぐには失敗させない
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+// fts5 の trigram トークナイザーは SQLite 3.34.0 から使える
+const MIN_TRIGRAM_SQLITE_VERSION: i32 = 3_034_000;
+// trigram は3文字ごとに索引を作るので、それより短い検索語は索引で絞れない
+pub const MIN_INDEXED_SEARCH_CHARS: usize = 3;
+
+// 並び順は `seq`（挿入順）で持ち、`id` は JSON Lines と同じ履歴の番号として別に持つ
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS entries (
+    seq INTEGER PRIMARY KEY,
+    id INTEGER NOT NULL UNIQUE,
+    copied_at TEXT NOT NULL,
+    source_app TEXT,
+    text TEXT NOT NULL,
+    pinned INTEGER NOT NULL DEFAULT 0,
+    copy_count INTEGER NOT NULL DEFAULT 1
+);
+CREATE VIRTUAL TABLE IF NOT EXISTS entries_fts USING fts5(
+    text, content='entries', content_rowid='seq', tokenize='trigram'
+);
+CREATE TRIGGER IF NOT EXISTS entries_after_insert AFTER INSERT ON entries BEGIN
+    INSERT INTO entries_fts(rowid, text) VALUES (new.seq, new.text);
+END;
+CREATE TRIGGER IF NOT EXISTS entries_after_delete AFTER DELETE ON entries BEGIN
+    INSERT INTO entries_fts(entries_fts, rowid, text) VALUES ('delete', old.seq, old.text);
+END;
+CREATE TRIGGER IF NOT EXISTS entries_after_update AFTER UPDATE OF text ON entries BEGIN
+    INSERT INTO entries_fts(entries_fts, rowid, text) VALUES ('delete', old.seq, old.text);
+    INSERT INTO entries_fts(rowid, text) VALUES (new.seq, new.text);
+END;
+";
+const SELECT_COLUMNS: &str = "id, copied_at, source_app, text, pinned, copy_count";
+
+const INSERT_SQL: &str = "INSERT INTO entries (id, copied_at, source_app, text, pinned, copy_count) VALUES (?, ?, ?, ?, ?, ?)";
+
+// ファイルがなければ作り、表と索引を用意する
+fn open(path: &Path) -> Result<Connection, String> {
+    // rusqlite に同梱した SQLite を使うので足りるはずだが、古い SQLite では索引を作れない
+    if rusqlite::version_number() < MIN_TRIGRAM_SQLITE_VERSION {
+        return Err(format!(
+            "history_backend = \"sqlite\" needs SQLite 3.34.0 or later for the trigram index (found {})",
+            rusqlite::version()
+        ));
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|err| {
+            format!(
+                "failed to create history directory {}: {err}",
+                parent.display()
+            )
+        })?;
+    }
+    let connection = Connection::open(path).map_err(sql_error("failed to open", path))?;
+    connection
+        .busy_timeout(BUSY_TIMEOUT)
+        .map_err(sql_error("failed to open", path))?;
+    connection
+        .execute_batch(SCHEMA)
+        .map_err(sql_error("failed to update", path))?;
+    Ok(connection)
+}
+
+fn sql_error<'a>(action: &'a str, path: &'a Path) -> impl Fn(rusqlite::Error) -> String + 'a {
+    move |err| format!("{action} {}: {err}", path.display())
+}
+
+fn entry_from_row(row: &Row) -> rusqlite::Result<HistoryEntry> {
+    Ok(HistoryEntry {
+        id: row.get::<_, i64>(0)? as u64,
+        copied_at: row.get(1)?,
+        source_app: row.get(2)?,
+        text: row.get(3)?,
+        pinned: row.get(4)?,
+        copy_count: row.get::<_, i64>(5)?.clamp(1, u32::MAX as i64) as u32,
+    })
+}
+
+fn query_entries(
+    connection: &Connection,
+    path: &Path,
+    sql: &str,
+    values: impl Params,
+) -> Result<Vec<HistoryEntry>, String> {
+    let mut statement = connection
+        .prepare(sql)
+        .map_err(sql_error("failed to query", path))?;
+    let rows = statement
+        .query_map(values, entry_from_row)
+        .map_err(sql_error("failed to query", path))?;
+    rows.collect::<Result<_, _>>()
+        .map_err(sql_error("failed to query", path))
+}
+
+fn insert(connection: &Connection, entry: &HistoryEntry) -> rusqlite::Result<usize> {
+    connection.prepare_cached(INSERT_SQL)?.execute(params![
+        entry.id as i64,
+        entry.copied_at,
+        entry.source_app,
+        entry.text,
+        entry.pinned,
+        entry.copy_count,
+    ])
+}
+
+/// 履歴を古い順に読む。ファイルがなければ空。
+pub fn load_entries(path: &Path) -> Result<Vec<HistoryEntry>, String> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let connection = open(path)?;
+    query_entries(
+        &connection,
+        path,
+        &format!("SELECT {SELECT_COLUMNS} FROM entries ORDER BY seq"),
+        [],
+    )
+}
+
+/// 本文に `needle` を含む履歴を、全文検索の索引で古い順に読む。大文字・小文字は区別しない。
+///
+/// `needle` が [`MIN_INDEXED_SEARCH_CHARS`] 文字より短いと索引で絞れないので、呼び出し側で
+/// 全件から探すこと。
+pub fn search_entries(path: &Path, needle: &str) -> Result<Vec<HistoryEntry>, String> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let connection = open(path)?;
+    // 記号を演算子として読まないよう、引用符で囲んだ1つの語として探す
+    let phrase = format!("\"{}\"", needle.replace('"', "\"\""));
+    query_entries(
+        &connection,
+        path,
+        &format!(
+            "SELECT {SELECT_COLUMNS} FROM entries WHERE seq IN \
+             (SELECT rowid FROM entries_fts WHERE entries_fts MATCH ?) ORDER BY seq"
+        ),
+        [phrase],
+    )
+}
+
+/// 最後の（最も新しい）履歴。
+pub fn last_entry(path: &Path) -> Result<Option<HistoryEntry>, String> {
+    let connection = open(path)?;
+    connection
+        .query_row(
+            &format!("SELECT {SELECT_COLUMNS} FROM entries ORDER BY seq DESC LIMIT 1"),
+            [],
+            entry_from_row,
+        )
+        .optional()
+        .map_err(sql_error("failed to query", path))
+}
+
+/// 1件を最後に追加する。
+pub fn insert_entry(path: &Path, entry: &HistoryEntry) -> Result<(), String> {
+    let connection = open(path)?;
+    insert(&connection, entry).map_err(sql_error("failed to update", path))?;
+    Ok(())
+}
+
+/// 番号が同じ履歴の時刻・固定・回数を書き換える。
+pub fn update_entry(path: &Path, entry: &HistoryEntry) -> Result<(), String> {
+    let connection = open(path)?;
+    connection
+        .execute(
+            "UPDATE entries SET copied_at = ?, pinned = ?, copy_count = ? WHERE id = ?",
+            params![
+                entry.copied_at,
+                entry.pinned,
+                entry.copy_count,
+                entry.id as i64
+            ],
+        )
+        .map_err(sql_error("failed to update", path))?;
+    Ok(())
+}
+
+/// 指定した番号の履歴を削除する。
+pub fn delete_entries(path: &Path, ids: &[u64]) -> Result<(), String> {
+    let mut connection = open(path)?;
+    // 途中で失敗したら書きかけの変更を残さない（commit しなければ Drop で戻す）
+    let transaction = connection
+        .transaction_with_behavior(TransactionBehavior::Immediate)
+        .map_err(sql_error("failed to update", path))?;
+    for id in ids {
+        transaction
+            .prepare_cached("DELETE FROM entries WHERE id = ?")
+            .and_then(|mut statement| statement.execute([*id as i64]))
+            .map_err(sql_error("failed to update", path))?;
+    }
+    transaction
+        .commit()
+        .map_err(sql_error("failed to update", path))
+}
+
+/// 履歴全体を `entries` の順に書き直す。
+pub fn replace_entries(path: &Path, entries: &[HistoryEntry]) -> Result<(), String> {
+    let mut connection = open(path)?;
+    let transaction = connection
+        .transaction_with_behavior(TransactionBehavior::Immediate)
+        .map_err(sql_error("failed to update", path))?;
+    transaction
+        .execute("DELETE FROM entries", [])
+        .map_err(sql_error("failed to update", path))?;
+    for entry in entries {
+        insert(&transaction, entry).map_err(sql_error("failed to update", path))?;
+    }
+    transaction
+        .commit()
+        .map_err(sql_error("failed to update", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::history::HistoryEntry;
+
+    use super::{
+        delete_entries, insert_entry, last_entry, load_entries, replace_entries, search_entries,
+        update_entry,
+    };
+
+    fn entry(id: u64, text: &str) -> HistoryEntry {
+        HistoryEntry {
+            id,
+            copied_at: format!("2024-05-0{id}T09:00:00+09:00"),
+            source_app: (id == 1).then(|| "Safari".to_string()),
+            text: text.to_string(),
+            pinned: false,
+            copy_count: 1,
+        }
+    }
+
+    #[test]
+    fn history_database_stores_entries_and_searches_the_index() {
+        let path = std::env::temp_dir().join(format!(
+            "cliip-show-history-db-{}.sqlite3",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        assert!(load_entries(&path).expect("missing").is_empty());
+
+        replace_entries(&path, &[entry(3, "Hello World"), entry(1, "cargo test")])
+            .expect("replace");
+        insert_entry(&path, &entry(4, "say \"hello\" again")).expect("insert");
+        let ids = |entries: Vec<HistoryEntry>| -> Vec<u64> {
+            entries.iter().map(|entry| entry.id).collect()
+        };
+        assert_eq!(ids(load_entries(&path).expect("load")), [3, 1, 4]);
+        assert_eq!(ids(search_entries(&path, "HELLO").expect("search")), [3, 4]);
+        assert_eq!(
+            ids(search_entries(&path, "\"hello\"").expect("quoted")),
+            [4]
+        );
+        assert!(search_entries(&path, "o AND")
+            .expect("operators")
+            .is_empty());
+
+        let mut last = last_entry(&path).expect("last").expect("some");
+        last.copy_count = 3;
+        last.pinned = true;
+        update_entry(&path, &last).expect("update");
+        delete_entries(&path, &[3]).expect("delete");
+        let loaded = load_entries(&path).expect("reload");
+        assert_eq!(ids(loaded.clone()), [1, 4]);
+        assert_eq!(loaded[0].source_app.as_deref(), Some("Safari"));
+        assert_eq!((loaded[1].copy_count, loaded[1].pinned), (3, true));
+        assert!(search_entries(&path, "World").expect("deleted").is_empty());
+
+        let _ = fs::remove_file(&path);
+    }
+}
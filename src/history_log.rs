@@ -0,0 +1,418 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+
+use crate::history::HistoryEntry;
+
+// ファイルの先頭に置く。記録の形式を変えたら番号を上げる
+const MAGIC: &[u8; 8] = b"CLIPLOG1";
+// 記録ごとに、本文の長さと本文の CRC-32 を前に置く
+const FRAME_HEADER_LEN: usize = 8;
+// 上書きや削除で使わなくなった記録がこれを超え、生きている記録より大きくなったら詰めて書き直す
+const COMPACT_MIN_GARBAGE_BYTES: usize = 64 * 1024;
+
+// 同じ `id` の `Put` は、あとの記録が前の記録を置き換える
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Record {
+    Put(HistoryEntry),
+    Delete(Vec<u64>),
+}
+
+// 索引を作るときは本文を読まず、番号だけを取り出す
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum RecordKey {
+    Put { id: u64 },
+    Delete(Vec<u64>),
+}
+
+// ファイルを mmap して作る索引。本文は読むときまで JSON のままにしておく
+#[derive(Debug, Default)]
+struct LogIndex {
+    map: Option<Mmap>,
+    // 生きている `Put` の本文の範囲を、最初に書いた順に並べる。消した履歴は `None`
+    slots: Vec<Option<(u64, Range<usize>)>>,
+    positions: HashMap<u64, usize>,
+    // ここまでが読めた記録。後ろに残りがあれば書きかけで終わった記録
+    valid_len: usize,
+    live_bytes: usize,
+    garbage_bytes: usize,
+}
+
+impl LogIndex {
+    fn read(path: &Path) -> Result<Self, String> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(format!("failed to read {}: {err}", path.display())),
+        };
+        let len = file
+            .metadata()
+            .map_err(|err| format!("failed to read {}: {err}", path.display()))?
+            .len();
+        if len == 0 {
+            return Ok(Self::default());
+        }
+        // SAFETY: ログは末尾への追記と、別名で書いたファイルとの置き換えでしか変えないので、
+        // 対応づけた範囲の中身が読んでいる途中で変わったり、ファイルが短くなったりしない
+        let map = unsafe { Mmap::map(&file) }
+            .map_err(|err| format!("failed to map {}: {err}", path.display()))?;
+        let mut index = Self::default();
+        if !map.starts_with(MAGIC) {
+            // 先頭を書いている途中で終了した空のログ。全体を書きかけの記録として扱う
+            if MAGIC.starts_with(&map) {
+                index.map = Some(map);
+                return Ok(index);
+            }
+            return Err(format!(
+                "{} is not a cliip-show history log",
+                path.display()
+            ));
+        }
+        let mut offset = MAGIC.len();
+        while let Some(header) = map.get(offset..offset + FRAME_HEADER_LEN) {
+            let len = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as usize;
+            let crc = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+            let body = offset + FRAME_HEADER_LEN..offset + FRAME_HEADER_LEN + len;
+            let Some(payload) = map.get(body.clone()) else {
+                break;
+            };
+            if crc32fast::hash(payload) != crc {
+                break;
+            }
+            // 新しい版が書いた種類の記録は読み飛ばす
+            index.record(serde_json::from_slice(payload).ok(), body.clone());
+            offset = body.end;
+        }
+        index.valid_len = offset;
+        index.map = Some(map);
+        Ok(index)
+    }
+
+    fn record(&mut self, key: Option<RecordKey>, body: Range<usize>) {
+        match key {
+            Some(RecordKey::Put { id }) => self.put(id, body),
+            Some(RecordKey::Delete(ids)) => {
+                self.delete(&ids);
+                self.garbage_bytes += FRAME_HEADER_LEN + body.len();
+            }
+            None => self.garbage_bytes += FRAME_HEADER_LEN + body.len(),
+        }
+    }
+
+    fn put(&mut self, id: u64, body: Range<usize>) {
+        let frame_len = FRAME_HEADER_LEN + body.len();
+        self.live_bytes += frame_len;
+        match self.positions.get(&id) {
+            Some(&position) => {
+                if let Some((_, old)) = self.slots[position].replace((id, body)) {
+                    self.live_bytes -= FRAME_HEADER_LEN + old.len();
+                    self.garbage_bytes += FRAME_HEADER_LEN + old.len();
+                }
+            }
+            None => {
+                self.positions.insert(id, self.slots.len());
+                self.slots.push(Some((id, body)));
+            }
+        }
+    }
+
+    fn delete(&mut self, ids: &[u64]) {
+        for id in ids {
+            let Some(position) = self.positions.remove(id) else {
+                continue;
+            };
+            if let Some((_, old)) = self.slots[position].take() {
+                self.live_bytes -= FRAME_HEADER_LEN + old.len();
+                self.garbage_bytes += FRAME_HEADER_LEN + old.len();
+            }
+        }
+    }
+
+    fn is_torn(&self) -> bool {
+        self.map
+            .as_ref()
+            .is_some_and(|map| self.valid_len < map.len())
+    }
+
+    fn needs_compaction(&self) -> bool {
+        self.garbage_bytes >= COMPACT_MIN_GARBAGE_BYTES && self.garbage_bytes > self.live_bytes
+    }
+
+    fn entry(&self, path: &Path, body: &Range<usize>) -> Result<HistoryEntry, String> {
+        let map = self.map.as_deref().unwrap_or_default();
+        match serde_json::from_slice(&map[body.clone()]) {
+            Ok(Record::Put(entry)) => Ok(entry),
+            _ => Err(format!("{}: unreadable history record", path.display())),
+        }
+    }
+
+    fn entries(&self, path: &Path) -> Result<Vec<HistoryEntry>, String> {
+        self.slots
+            .iter()
+            .flatten()
+            .map(|(_, body)| self.entry(path, body))
+            .collect()
+    }
+}
+
+/// 履歴を古い順（最初に書いた順）に読む。
+pub fn load_entries(path: &Path) -> Result<Vec<HistoryEntry>, String> {
+    LogIndex::read(path)?.entries(path)
+}
+
+/// 開いたままにするログ。索引を持ち続け、追記した記録はその場で索引に足すので、
+/// 追記や消去のたびにファイルを読み直さない。
+///
+/// ほかのプロセスがファイルを書き換えたら索引は使えないので、開き直す。
+#[derive(Debug)]
+pub struct HistoryLog {
+    path: PathBuf,
+    index: LogIndex,
+}
+
+impl HistoryLog {
+    /// 書きかけで終わった記録が末尾に残っていれば、読めた記録だけで書き直してから開く。
+    ///
+    /// 追記はファイルの末尾に足すだけなので、書きかけの記録の後ろに足すと読めなくなる。
+    pub fn open(path: PathBuf) -> Result<Self, String> {
+        let mut index = LogIndex::read(&path)?;
+        if index.is_torn() {
+            let entries = index.entries(&path)?;
+            drop(index);
+            replace_entries(&path, &entries)?;
+            index = LogIndex::read(&path)?;
+        }
+        Ok(Self { path, index })
+    }
+
+    /// 古い順（最初に書いた順）に返す。
+    pub fn entries(&self) -> Result<Vec<HistoryEntry>, String> {
+        self.index.entries(&self.path)
+    }
+
+    pub fn last_entry(&self) -> Result<Option<HistoryEntry>, String> {
+        self.index
+            .slots
+            .iter()
+            .rev()
+            .flatten()
+            .next()
+            .map(|(_, body)| self.index.entry(&self.path, body))
+            .transpose()
+    }
+
+    pub fn insert(&mut self, entry: &HistoryEntry) -> Result<(), String> {
+        self.append(&Record::Put(entry.clone()))
+    }
+
+    /// 番号が同じ履歴を置き換える。並び順は変えない。
+    pub fn update(&mut self, entry: &HistoryEntry) -> Result<(), String> {
+        self.append(&Record::Put(entry.clone()))
+    }
+
+    /// 使わなくなった記録が増えていれば、詰めて書き直す。
+    pub fn delete(&mut self, ids: &[u64]) -> Result<(), String> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        self.append(&Record::Delete(ids.to_vec()))?;
+        if self.index.needs_compaction() {
+            let entries = self.entries()?;
+            self.index = LogIndex::default();
+            replace_entries(&self.path, &entries)?;
+            self.index = LogIndex::read(&self.path)?;
+        }
+        Ok(())
+    }
+
+    fn append(&mut self, record: &Record) -> Result<(), String> {
+        let (file, body) = append_record(&self.path, record)?;
+        // 足した記録まで読めるよう対応づけ直す。索引には足した記録だけを加える
+        // SAFETY: LogIndex::read と同じ
+        let map = unsafe { Mmap::map(&file) }
+            .map_err(|err| format!("failed to map {}: {err}", self.path.display()))?;
+        self.index.map = Some(map);
+        self.index.valid_len = body.end;
+        let key = match record {
+            Record::Put(entry) => RecordKey::Put { id: entry.id },
+            Record::Delete(ids) => RecordKey::Delete(ids.clone()),
+        };
+        self.index.record(Some(key), body);
+        Ok(())
+    }
+}
+
+/// 生きている履歴だけの新しいログを別名で書き、置き換える。
+pub fn replace_entries(path: &Path, entries: &[HistoryEntry]) -> Result<(), String> {
+    create_parent_dir(path)?;
+    let mut content = MAGIC.to_vec();
+    for entry in entries {
+        push_frame(&mut content, &Record::Put(entry.clone()))?;
+    }
+    let temp_path = path.with_extension("log.tmp");
+    let mut file = File::create(&temp_path)
+        .map_err(|err| format!("failed to write {}: {err}", temp_path.display()))?;
+    // 電源が落ちても、中身がまだディスクにない新しいファイルで置き換わらないようにする
+    file.write_all(&content)
+        .and_then(|()| file.sync_all())
+        .map_err(|err| format!("failed to write {}: {err}", temp_path.display()))?;
+    fs::rename(&temp_path, path)
+        .map_err(|err| format!("failed to replace {}: {err}", path.display()))
+}
+
+// 足した記録を読めるように開いたファイルと、ファイルの中での本文の範囲を返す。
+//
+// 書いたあとに `sync_all` はしないので、守れるのはプロセスが途中で終了した場合だけ。
+// OS ごと止まった場合は、書いたはずの記録が消えたり末尾が壊れたりすることがある
+// （壊れた末尾は次に開くときに取り除く）
+fn append_record(path: &Path, record: &Record) -> Result<(File, Range<usize>), String> {
+    create_parent_dir(path)?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .read(true)
+        .append(true)
+        .open(path)
+        .map_err(|err| format!("failed to open {}: {err}", path.display()))?;
+    let len = file
+        .metadata()
+        .map_err(|err| format!("failed to open {}: {err}", path.display()))?
+        .len() as usize;
+    let mut content = if len == 0 { MAGIC.to_vec() } else { Vec::new() };
+    let body = push_frame(&mut content, record)?;
+    // 1回で書くので、途中で終了しても残るのは末尾の書きかけの記録だけになる
+    file.write_all(&content)
+        .map_err(|err| format!("failed to write {}: {err}", path.display()))?;
+    Ok((file, len + body.start..len + body.end))
+}
+
+// 足した本文の `content` の中での範囲を返す
+fn push_frame(content: &mut Vec<u8>, record: &Record) -> Result<Range<usize>, String> {
+    let payload = serde_json::to_vec(record)
+        .map_err(|err| format!("failed to serialize history entry: {err}"))?;
+    let len = u32::try_from(payload.len())
+        .map_err(|_| "history entry is too large for the history log".to_string())?;
+    content.extend_from_slice(&len.to_le_bytes());
+    content.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+    let start = content.len();
+    content.extend_from_slice(&payload);
+    Ok(start..content.len())
+}
+
+fn create_parent_dir(path: &Path) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|err| {
+            format!(
+                "failed to create history directory {}: {err}",
+                parent.display()
+            )
+        })?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+
+    use crate::history::HistoryEntry;
+
+    use super::{load_entries, replace_entries, HistoryLog};
+
+    fn entry(id: u64, text: &str) -> HistoryEntry {
+        HistoryEntry {
+            id,
+            copied_at: "2024-05-01T09:30:00+09:00".to_string(),
+            source_app: None,
+            text: text.to_string(),
+            pinned: false,
+            copy_count: 1,
+        }
+    }
+
+    #[test]
+    fn history_log_replays_records_and_drops_a_torn_tail() {
+        let dir =
+            std::env::temp_dir().join(format!("cliip-show-history-log-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("history.log");
+        assert!(load_entries(&path).expect("load").is_empty());
+        let mut log = HistoryLog::open(path.clone()).expect("open");
+        assert!(log.entries().expect("entries").is_empty());
+
+        for (id, text) in [(1, "one"), (2, "two"), (3, "three")] {
+            log.insert(&entry(id, text)).expect("insert");
+        }
+        log.update(&HistoryEntry {
+            pinned: true,
+            ..entry(1, "one")
+        })
+        .expect("update");
+        log.delete(&[2]).expect("delete");
+        let entries = log.entries().expect("entries");
+        let summary: Vec<_> = entries
+            .iter()
+            .map(|entry| (entry.id, entry.text.as_str(), entry.pinned))
+            .collect();
+        assert_eq!(summary, [(1, "one", true), (3, "three", false)]);
+        // 追記しながら伸ばした索引と、ファイルを読み直して作った索引は同じ履歴を返す
+        assert_eq!(load_entries(&path).expect("load"), entries);
+        assert_eq!(
+            log.last_entry().expect("last").map(|entry| entry.id),
+            Some(3)
+        );
+        drop(log);
+
+        // 書きかけで終わった記録は読み飛ばし、開き直すときに取り除いてから追記する
+        let len = fs::metadata(&path).expect("metadata").len();
+        let mut file = OpenOptions::new().append(true).open(&path).expect("open");
+        file.write_all(&[40, 0, 0, 0, 1, 2]).expect("write");
+        drop(file);
+        assert_eq!(load_entries(&path).expect("load").len(), 2);
+        let mut log = HistoryLog::open(path.clone()).expect("open");
+        assert!(fs::metadata(&path).expect("metadata").len() <= len);
+        log.insert(&entry(4, "four")).expect("insert");
+        let ids: Vec<_> = load_entries(&path)
+            .expect("load")
+            .iter()
+            .map(|entry| entry.id)
+            .collect();
+        assert_eq!(ids, [1, 3, 4]);
+
+        // 使わなくなった記録が増えたら、消すときに詰めて書き直す
+        let long = "x".repeat(1024);
+        for _ in 0..100 {
+            log.update(&entry(4, &long)).expect("update");
+        }
+        log.delete(&[1]).expect("delete");
+        assert!(fs::metadata(&path).expect("metadata").len() < 4096);
+        let ids: Vec<_> = load_entries(&path)
+            .expect("load")
+            .iter()
+            .map(|entry| entry.id)
+            .collect();
+        assert_eq!(ids, [3, 4]);
+        // 詰めたあとも、開いたままのログに続けて追記できる
+        log.insert(&entry(5, "five")).expect("insert");
+        assert_eq!(
+            load_entries(&path).expect("load"),
+            log.entries().expect("entries")
+        );
+        drop(log);
+
+        replace_entries(&path, &[entry(9, "nine")]).expect("replace");
+        assert_eq!(load_entries(&path).expect("load"), [entry(9, "nine")]);
+
+        fs::write(&path, "{\"id\":1}\n").expect("write");
+        assert!(load_entries(&path).is_err());
+        assert!(HistoryLog::open(path.clone()).is_err());
+        fs::remove_dir_all(&dir).expect("cleanup");
+    }
+}
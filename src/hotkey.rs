@@ -0,0 +1,372 @@
+use std::ffi::c_void;
+use std::ptr;
+
+use serde::{Deserialize, Serialize};
+
+// Carbon の修飾キー（cmdKey など）
+const CARBON_CMD: u32 = 0x0100;
+const CARBON_SHIFT: u32 = 0x0200;
+const CARBON_OPTION: u32 = 0x0800;
+const CARBON_CONTROL: u32 = 0x1000;
+// NSEventModifierFlags の対応するビット
+const NS_EVENT_SHIFT: usize = 1 << 17;
+const NS_EVENT_CONTROL: usize = 1 << 18;
+const NS_EVENT_OPTION: usize = 1 << 19;
+const NS_EVENT_COMMAND: usize = 1 << 20;
+// macOS のメニューと同じ ⌃⌥⇧⌘ の順に並べる
+const MODIFIERS: [(&str, &[&str], u32, usize); 4] = [
+    (
+        "ctrl",
+        &["ctrl", "control", "⌃"],
+        CARBON_CONTROL,
+        NS_EVENT_CONTROL,
+    ),
+    (
+        "option",
+        &["option", "opt", "alt", "⌥"],
+        CARBON_OPTION,
+        NS_EVENT_OPTION,
+    ),
+    ("shift", &["shift", "⇧"], CARBON_SHIFT, NS_EVENT_SHIFT),
+    (
+        "cmd",
+        &["cmd", "command", "⌘"],
+        CARBON_CMD,
+        NS_EVENT_COMMAND,
+    ),
+];
+// US 配列の仮想キーコード（kVK_ANSI_* など）
+const KEYS: [(&str, u32); 62] = [
+    ("a", 0x00),
+    ("s", 0x01),
+    ("d", 0x02),
+    ("f", 0x03),
+    ("h", 0x04),
+    ("g", 0x05),
+    ("z", 0x06),
+    ("x", 0x07),
+    ("c", 0x08),
+    ("v", 0x09),
+    ("b", 0x0B),
+    ("q", 0x0C),
+    ("w", 0x0D),
+    ("e", 0x0E),
+    ("r", 0x0F),
+    ("y", 0x10),
+    ("t", 0x11),
+    ("1", 0x12),
+    ("2", 0x13),
+    ("3", 0x14),
+    ("4", 0x15),
+    ("6", 0x16),
+    ("5", 0x17),
+    ("=", 0x18),
+    ("9", 0x19),
+    ("7", 0x1A),
+    ("-", 0x1B),
+    ("8", 0x1C),
+    ("0", 0x1D),
+    ("]", 0x1E),
+    ("o", 0x1F),
+    ("u", 0x20),
+    ("[", 0x21),
+    ("i", 0x22),
+    ("p", 0x23),
+    ("return", 0x24),
+    ("l", 0x25),
+    ("j", 0x26),
+    ("'", 0x27),
+    ("k", 0x28),
+    (";", 0x29),
+    ("\\", 0x2A),
+    (",", 0x2B),
+    ("/", 0x2C),
+    ("n", 0x2D),
+    ("m", 0x2E),
+    (".", 0x2F),
+    ("tab", 0x30),
+    ("space", 0x31),
+    ("`", 0x32),
+    ("f1", 0x7A),
+    ("f2", 0x78),
+    ("f3", 0x63),
+    ("f4", 0x76),
+    ("f5", 0x60),
+    ("f6", 0x61),
+    ("f7", 0x62),
+    ("f8", 0x64),
+    ("f9", 0x65),
+    ("f10", 0x6D),
+    ("f11", 0x67),
+    ("f12", 0x6F),
+];
+const K_EVENT_CLASS_KEYBOARD: u32 = u32::from_be_bytes(*b"keyb");
+const K_EVENT_HOT_KEY_PRESSED: u32 = 5;
+const HOTKEY_SIGNATURE: u32 = u32::from_be_bytes(*b"cliP");
+const K_EVENT_PARAM_DIRECT_OBJECT: u32 = u32::from_be_bytes(*b"----");
+const TYPE_EVENT_HOT_KEY_ID: u32 = u32::from_be_bytes(*b"hkid");
+const NO_ERR: i32 = 0;
+/// ハンドラーが自分のショートカットでないイベントを次のハンドラーに回すときに返す値。
+pub const EVENT_NOT_HANDLED_ERR: i32 = -9874;
+
+#[repr(C)]
+struct EventTypeSpec {
+    event_class: u32,
+    event_kind: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct EventHotKeyId {
+    signature: u32,
+    id: u32,
+}
+
+/// Carbon のイベントハンドラー。3つ目の引数に登録時の `user_data` が渡る。
+pub type HotkeyHandler = extern "C" fn(*mut c_void, *mut c_void, *mut c_void) -> i32;
+
+extern "C" {
+    fn GetApplicationEventTarget() -> *mut c_void;
+    fn InstallEventHandler(
+        target: *mut c_void,
+        handler: HotkeyHandler,
+        num_types: usize,
+        list: *const EventTypeSpec,
+        user_data: *mut c_void,
+        out_ref: *mut *mut c_void,
+    ) -> i32;
+    fn RemoveEventHandler(handler_ref: *mut c_void) -> i32;
+    fn RegisterEventHotKey(
+        key_code: u32,
+        modifiers: u32,
+        id: EventHotKeyId,
+        target: *mut c_void,
+        options: u32,
+        out_ref: *mut *mut c_void,
+    ) -> i32;
+    fn UnregisterEventHotKey(hotkey_ref: *mut c_void) -> i32;
+    fn GetEventParameter(
+        event: *mut c_void,
+        name: u32,
+        desired_type: u32,
+        out_actual_type: *mut u32,
+        buffer_size: usize,
+        out_actual_size: *mut usize,
+        out_data: *mut c_void,
+    ) -> i32;
+}
+
+/// `cmd+shift+v` のようなグローバルショートカット。設定ファイルでは文字列で書く。
+///
+/// 既定値は「割り当てなし」で、[`Hotkey::is_empty`] が真になる。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Hotkey {
+    key_code: u32,
+    // Carbon の修飾キーの組み合わせ。0 なら割り当てなし
+    modifiers: u32,
+}
+
+impl Hotkey {
+    pub fn is_empty(&self) -> bool {
+        self.modifiers == 0
+    }
+
+    /// 押している修飾キーを `[NSEvent modifierFlags]` で調べるときのマスク。
+    pub fn modifier_flags(&self) -> usize {
+        MODIFIERS
+            .iter()
+            .filter(|(_, _, carbon, _)| self.modifiers & carbon != 0)
+            .fold(0, |flags, (_, _, _, ns_event)| flags | ns_event)
+    }
+
+    /// `--config set` や環境変数向けの表記。割り当てがなければ空文字列。
+    pub fn as_string(&self) -> String {
+        if self.is_empty() {
+            return String::new();
+        }
+        let key = KEYS
+            .iter()
+            .find(|(_, code)| *code == self.key_code)
+            .map_or("?", |(name, _)| name);
+        MODIFIERS
+            .iter()
+            .filter(|(_, _, carbon, _)| self.modifiers & carbon != 0)
+            .map(|(name, _, _, _)| *name)
+            .chain([key])
+            .collect::<Vec<_>>()
+            .join("+")
+    }
+}
+
+impl TryFrom<String> for Hotkey {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        parse_hotkey(&value)
+    }
+}
+
+impl From<Hotkey> for String {
+    fn from(value: Hotkey) -> Self {
+        value.as_string()
+    }
+}
+
+/// `cmd+shift+v` のように `+` でつないだ修飾キーとキーを読む。空文字列と `none` は割り当てなし。
+///
+/// ほかのアプリの入力を奪わないよう、修飾キーを1つ以上必須にする。
+pub fn parse_hotkey(raw: &str) -> Result<Hotkey, String> {
+    let raw = raw.trim().to_ascii_lowercase();
+    if raw.is_empty() || raw == "none" || raw == "off" {
+        return Ok(Hotkey::default());
+    }
+    let invalid = || format!("invalid hotkey: {raw} (e.g. cmd+shift+v)");
+    // `cmd++` のように `+` 自体は使えないので、最後の `+` より後ろがキー
+    let (modifier_names, key_name) = raw.rsplit_once('+').ok_or_else(invalid)?;
+    let key_code = KEYS
+        .iter()
+        .find(|(name, _)| *name == key_name.trim())
+        .map(|(_, code)| *code)
+        .ok_or_else(invalid)?;
+    let mut modifiers = 0;
+    for name in modifier_names.split('+') {
+        let (_, _, carbon, _) = MODIFIERS
+            .iter()
+            .find(|(_, aliases, _, _)| aliases.contains(&name.trim()))
+            .ok_or_else(invalid)?;
+        modifiers |= carbon;
+    }
+    Ok(Hotkey {
+        key_code,
+        modifiers,
+    })
+}
+
+/// `RegisterEventHotKey` で登録したショートカット。Drop で登録とハンドラーを外す。
+#[derive(Debug)]
+pub struct RegisteredHotkey {
+    hotkey: Hotkey,
+    handler_ref: *mut c_void,
+    hotkey_ref: *mut c_void,
+}
+
+impl RegisteredHotkey {
+    /// アプリのイベントターゲットにハンドラーを付け、`hotkey` を押すたびに呼ばれるようにする。
+    ///
+    /// ハンドラーはほかのショートカットのイベントも受け取るので、[`pressed_hotkey_id`] で
+    /// `id` と比べ、違えば [`EVENT_NOT_HANDLED_ERR`] を返して次のハンドラーに回すこと。
+    ///
+    /// # Safety
+    ///
+    /// メインスレッドから呼び出すこと。`user_data` は登録している間は有効であること。
+    pub unsafe fn register(
+        hotkey: Hotkey,
+        id: u32,
+        handler: HotkeyHandler,
+        user_data: *mut c_void,
+    ) -> Result<Self, String> {
+        if hotkey.is_empty() {
+            return Err("hotkey has no modifiers".to_string());
+        }
+        let target = GetApplicationEventTarget();
+        let event_type = EventTypeSpec {
+            event_class: K_EVENT_CLASS_KEYBOARD,
+            event_kind: K_EVENT_HOT_KEY_PRESSED,
+        };
+        let mut handler_ref = ptr::null_mut();
+        let status =
+            InstallEventHandler(target, handler, 1, &event_type, user_data, &mut handler_ref);
+        if status != NO_ERR {
+            return Err(format!(
+                "failed to install hotkey handler (OSStatus {status})"
+            ));
+        }
+        let hotkey_id = EventHotKeyId {
+            signature: HOTKEY_SIGNATURE,
+            id,
+        };
+        let mut hotkey_ref = ptr::null_mut();
+        let status = RegisterEventHotKey(
+            hotkey.key_code,
+            hotkey.modifiers,
+            hotkey_id,
+            target,
+            0,
+            &mut hotkey_ref,
+        );
+        if status != NO_ERR {
+            RemoveEventHandler(handler_ref);
+            return Err(format!(
+                "failed to register hotkey {} (OSStatus {status}); another app may already use it",
+                hotkey.as_string()
+            ));
+        }
+        Ok(Self {
+            hotkey,
+            handler_ref,
+            hotkey_ref,
+        })
+    }
+
+    pub fn hotkey(&self) -> Hotkey {
+        self.hotkey
+    }
+}
+
+/// ハンドラーに渡ったイベントが、cliip-show が登録したどのショートカットのものか。
+///
+/// # Safety
+///
+/// `event` はハンドラーに渡った `EventRef` であること。
+pub unsafe fn pressed_hotkey_id(event: *mut c_void) -> Option<u32> {
+    let mut hotkey_id = EventHotKeyId::default();
+    let status = GetEventParameter(
+        event,
+        K_EVENT_PARAM_DIRECT_OBJECT,
+        TYPE_EVENT_HOT_KEY_ID,
+        ptr::null_mut(),
+        std::mem::size_of::<EventHotKeyId>(),
+        ptr::null_mut(),
+        &mut hotkey_id as *mut EventHotKeyId as *mut c_void,
+    );
+    (status == NO_ERR && hotkey_id.signature == HOTKEY_SIGNATURE).then_some(hotkey_id.id)
+}
+
+impl Drop for RegisteredHotkey {
+    fn drop(&mut self) {
+        unsafe {
+            UnregisterEventHotKey(self.hotkey_ref);
+            RemoveEventHandler(self.handler_ref);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_hotkey, Hotkey};
+
+    #[test]
+    fn parse_hotkey_reads_modifiers_and_keys() {
+        let hotkey = parse_hotkey("Shift+Command+V").expect("hotkey");
+        assert_eq!(hotkey.as_string(), "shift+cmd+v");
+        assert_eq!(hotkey.modifier_flags(), (1 << 17) | (1 << 20));
+        assert_eq!(
+            parse_hotkey("alt + ctrl + space")
+                .expect("hotkey")
+                .as_string(),
+            "ctrl+option+space"
+        );
+        assert_eq!(
+            parse_hotkey("⌘+⇧+v").expect("symbols"),
+            parse_hotkey("cmd+shift+v").expect("names")
+        );
+        assert_eq!(parse_hotkey("none").expect("none"), Hotkey::default());
+        assert_eq!(parse_hotkey("").expect("empty").as_string(), "");
+
+        // 修飾キーなしや未知のキーは受け付けない
+        assert!(parse_hotkey("v").is_err());
+        assert!(parse_hotkey("cmd+hyper+v").is_err());
+        assert!(parse_hotkey("cmd+shift+f13").is_err());
+    }
+}
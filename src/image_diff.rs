@@ -0,0 +1,1262 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+
+use serde_json::json;
+
+pub const RGBA_CHANNELS: usize = 4;
+// これより小さい画像はスレッド起動のコストの方が大きいので分割しない
+const MIN_PIXELS_PER_THREAD: usize = 64 * 1024;
+const PIXEL_CHANNEL_TOLERANCE: u8 = 2;
+const UNCHANGED_PIXEL_ALPHA: f64 = 0.08;
+const CHANGED_PIXEL_ALPHA: f64 = 0.9;
+const CHANGED_PIXEL_MIN_INTENSITY: u8 = 128;
+const IGNORED_PIXEL_ALPHA: f64 = 0.25;
+/// `--metric deltae` で `--max-delta` を省略したときの許容差。2前後が目で見分けられる境目とされる。
+pub const DEFAULT_MAX_DELTA_E: f64 = 2.0;
+// マスク画像でこれ以上のアルファを持つピクセルを比較から除外する
+const MASK_ALPHA_THRESHOLD: u8 = 128;
+// 打ち切り判定の単位。小さすぎると共有カウンタの更新が増える
+const FAIL_FAST_BLOCK_PIXELS: usize = 4096;
+const COMPOSITE_GAP: usize = 8;
+const COMPOSITE_PADDING: usize = 4;
+const LABEL_GLYPH_WIDTH: usize = 5;
+const LABEL_GLYPH_HEIGHT: usize = 7;
+const LABEL_GLYPH_SCALE: usize = 2;
+const LABEL_COLOR: [u8; RGBA_CHANNELS] = [40, 40, 40, 255];
+const COMPOSITE_BACKGROUND: [u8; RGBA_CHANNELS] = [255, 255, 255, 255];
+
+/// 合成画像の各パネルに付けるラベル（左から順に）。
+pub const COMPOSITE_LABELS: [&str; 3] = ["BASELINE", "CURRENT", "DIFF"];
+
+/// `--diff-png` の比較オプション。
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DiffOptions {
+    /// 差分がこの閾値を超えた時点で比較を打ち切る。
+    pub fail_fast_threshold: Option<DiffThreshold>,
+    /// ベースライン・現在・差分を横に並べた画像の書き出し先。
+    pub composite_path: Option<String>,
+    /// 比較から除外する矩形。
+    pub ignore_regions: Vec<DiffBounds>,
+    /// 不透明なピクセルを比較から除外するマスク画像。比較する画像と同じサイズであること。
+    pub mask_path: Option<String>,
+    pub metric: DiffMetric,
+    pub resize_policy: ResizePolicy,
+}
+
+/// 比較する2枚の画像のサイズが違うときの扱い。どちらも左上を揃えて重ねる。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResizePolicy {
+    /// エラーにする。
+    #[default]
+    Fail,
+    /// 大きい方に合わせて透明なピクセルで埋める。埋めた領域は変化として数える。
+    Pad,
+    /// 小さい方に合わせて右端・下端を切り落とす。
+    Crop,
+}
+
+impl ResizePolicy {
+    /// 比較に使うキャンバスの `(幅, 高さ)`。`Fail` でサイズが違う場合は `None`。
+    pub fn canvas_size(
+        self,
+        baseline: (usize, usize),
+        current: (usize, usize),
+    ) -> Option<(usize, usize)> {
+        if baseline == current {
+            return Some(current);
+        }
+        match self {
+            Self::Fail => None,
+            Self::Pad => Some((baseline.0.max(current.0), baseline.1.max(current.1))),
+            Self::Crop => Some((baseline.0.min(current.0), baseline.1.min(current.1))),
+        }
+    }
+
+    /// `Pad` で埋めた場合の、両方の画像が重なっている範囲。[`DiffParams::overlap`] に渡す。
+    pub fn overlap(
+        self,
+        baseline: (usize, usize),
+        current: (usize, usize),
+    ) -> Option<(usize, usize)> {
+        (self == Self::Pad && baseline != current)
+            .then_some((baseline.0.min(current.0), baseline.1.min(current.1)))
+    }
+}
+
+pub fn parse_resize_policy(raw: &str) -> Option<ResizePolicy> {
+    match raw.trim().to_ascii_lowercase().as_str() {
+        "fail" => Some(ResizePolicy::Fail),
+        "pad" => Some(ResizePolicy::Pad),
+        "crop" => Some(ResizePolicy::Crop),
+        _ => None,
+    }
+}
+
+/// `size` の画像を左上に揃えて `canvas` の大きさにする。はみ出した分は捨て、足りない分は透明で埋める。
+pub fn fit_rgba(pixels: &[u8], size: (usize, usize), canvas: (usize, usize)) -> Vec<u8> {
+    let mut fitted = vec![0u8; canvas.0 * canvas.1 * RGBA_CHANNELS];
+    let row_len = size.0.min(canvas.0) * RGBA_CHANNELS;
+    for (src, dest) in pixels
+        .chunks_exact(size.0 * RGBA_CHANNELS)
+        .zip(fitted.chunks_exact_mut(canvas.0 * RGBA_CHANNELS))
+    {
+        dest[..row_len].copy_from_slice(&src[..row_len]);
+    }
+    fitted
+}
+
+/// ピクセルが変化したとみなす基準。
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum DiffMetric {
+    /// RGBA のいずれかのチャンネルの差が許容範囲を超えたら変化とみなす。
+    #[default]
+    Channel,
+    /// 白背景に合成した色を Lab 色空間に変換し、CIEDE2000 の色差がこの値を超えたら変化とみなす。
+    DeltaE(f64),
+}
+
+/// `--metric` の値を読む。`deltae` の許容差は `max_delta`（省略時は [`DEFAULT_MAX_DELTA_E`]）。
+pub fn parse_diff_metric(raw: &str, max_delta: Option<f64>) -> Option<DiffMetric> {
+    match raw.trim().to_ascii_lowercase().as_str() {
+        "channel" if max_delta.is_none() => Some(DiffMetric::Channel),
+        "deltae" | "ciede2000" => {
+            let max_delta = max_delta.unwrap_or(DEFAULT_MAX_DELTA_E);
+            (max_delta.is_finite() && max_delta >= 0.0).then_some(DiffMetric::DeltaE(max_delta))
+        }
+        _ => None,
+    }
+}
+
+impl DiffOptions {
+    /// 読み込んだマスク画像（アルファ乗算済み RGBA8）と合わせて、除外するピクセルの一覧を作る。
+    /// 除外するものがなければ `None`。
+    pub fn ignore_mask(
+        &self,
+        width: usize,
+        height: usize,
+        mask_pixels: Option<&[u8]>,
+    ) -> Option<Vec<bool>> {
+        if self.ignore_regions.is_empty() && mask_pixels.is_none() {
+            return None;
+        }
+        let mut mask = match mask_pixels {
+            Some(pixels) => pixels
+                .chunks_exact(RGBA_CHANNELS)
+                .map(|pixel| pixel[3] >= MASK_ALPHA_THRESHOLD)
+                .collect(),
+            None => vec![false; width * height],
+        };
+        for region in &self.ignore_regions {
+            for y in region.y..(region.y + region.height).min(height) {
+                let row = y * width;
+                let columns = region.x.min(width)..(region.x + region.width).min(width);
+                mask[row + columns.start..row + columns.end].fill(true);
+            }
+        }
+        Some(mask)
+    }
+
+    /// `width`x`height` の画像を比較するときの [`DiffParams`]。
+    pub fn params<'a>(
+        &self,
+        width: usize,
+        height: usize,
+        ignore_mask: Option<&'a [bool]>,
+    ) -> DiffParams<'a> {
+        let compared_pixels = ignore_mask.map_or(width * height, |mask| {
+            mask.iter().filter(|&&ignored| !ignored).count()
+        });
+        DiffParams {
+            width,
+            limit: self
+                .fail_fast_threshold
+                .map(|threshold| threshold.pixel_limit(compared_pixels)),
+            ignore_mask,
+            metric: self.metric,
+            overlap: None,
+        }
+    }
+}
+
+/// 画像全体に共通する比較の設定。
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DiffParams<'a> {
+    /// 1行のピクセル数。変化した領域の座標を求めるのに使う。
+    pub width: usize,
+    /// 差分ピクセル数がこれを超えた時点で全スレッドの比較を打ち切る。
+    pub limit: Option<usize>,
+    /// `true` のピクセルは比較しない。画像と同じ並びで全ピクセル分持つ。
+    pub ignore_mask: Option<&'a [bool]>,
+    pub metric: DiffMetric,
+    /// 両方の画像がある左上からの `(幅, 高さ)`。外側は [`ResizePolicy::Pad`] で埋めた領域なので変化として数える。
+    pub overlap: Option<(usize, usize)>,
+}
+
+/// 画像上の矩形（左上原点のピクセル座標）。変化した領域や、比較から除外する領域を表す。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiffBounds {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl DiffBounds {
+    fn union(self, other: DiffBounds) -> DiffBounds {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        DiffBounds {
+            x,
+            y,
+            width: (self.x + self.width).max(other.x + other.width) - x,
+            height: (self.y + self.height).max(other.y + other.height) - y,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiffSummary {
+    pub diff_pixels: usize,
+    /// 比較したピクセル数。除外したピクセルは含まない。
+    pub total_pixels: usize,
+    pub ignored_pixels: usize,
+    /// 差分が上限を超えたため比較を途中で打ち切った。`diff_pixels` はそれまでに数えた分だけ。
+    pub stopped_early: bool,
+    pub bounds: Option<DiffBounds>,
+    /// 比較した全ピクセルでの R, G, B, A ごとの差の最大値（許容範囲内の差も含む）。
+    pub max_channel_delta: [u8; RGBA_CHANNELS],
+}
+
+impl DiffSummary {
+    fn merge(self, other: DiffSummary) -> DiffSummary {
+        let mut max_channel_delta = self.max_channel_delta;
+        for (max, other) in max_channel_delta.iter_mut().zip(other.max_channel_delta) {
+            *max = (*max).max(other);
+        }
+        DiffSummary {
+            diff_pixels: self.diff_pixels + other.diff_pixels,
+            total_pixels: self.total_pixels + other.total_pixels,
+            ignored_pixels: self.ignored_pixels + other.ignored_pixels,
+            stopped_early: self.stopped_early || other.stopped_early,
+            bounds: match (self.bounds, other.bounds) {
+                (Some(bounds), Some(other)) => Some(bounds.union(other)),
+                (bounds, other) => bounds.or(other),
+            },
+            max_channel_delta,
+        }
+    }
+
+    /// `--summary-json` で書き出す内容。
+    pub fn to_json(&self) -> serde_json::Value {
+        let [r, g, b, a] = self.max_channel_delta;
+        json!({
+            "diff_pixels": self.diff_pixels,
+            "total_pixels": self.total_pixels,
+            "ignored_pixels": self.ignored_pixels,
+            "ratio": self.diff_ratio(),
+            "stopped_early": self.stopped_early,
+            "bounds": self.bounds.map(|bounds| json!({
+                "x": bounds.x,
+                "y": bounds.y,
+                "width": bounds.width,
+                "height": bounds.height,
+            })),
+            "max_channel_delta": { "r": r, "g": g, "b": b, "a": a },
+        })
+    }
+
+    pub fn diff_ratio(&self) -> f64 {
+        if self.total_pixels == 0 {
+            return 0.0;
+        }
+        self.diff_pixels as f64 / self.total_pixels as f64
+    }
+
+    pub fn exceeds(&self, threshold: DiffThreshold) -> bool {
+        self.diff_pixels > threshold.pixel_limit(self.total_pixels)
+    }
+}
+
+/// 許容する差分の量。画像全体に対する割合か、ピクセル数で指定する。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiffThreshold {
+    Ratio(f64),
+    Pixels(usize),
+}
+
+impl DiffThreshold {
+    /// `total_pixels` の画像で許容できる差分ピクセル数。これを超えると閾値超えとみなす。
+    pub fn pixel_limit(self, total_pixels: usize) -> usize {
+        match self {
+            Self::Ratio(ratio) => (ratio * total_pixels as f64).floor() as usize,
+            Self::Pixels(pixels) => pixels,
+        }
+    }
+
+    pub fn describe(self) -> String {
+        match self {
+            Self::Ratio(ratio) => format!("{}%", ratio * 100.0),
+            Self::Pixels(pixels) => format!("{pixels}px"),
+        }
+    }
+}
+
+/// `--ignore-region` の `x,y,w,h` を読む。幅と高さは1以上。
+pub fn parse_ignore_region(raw: &str) -> Option<DiffBounds> {
+    let values = raw
+        .split(',')
+        .map(|value| value.trim().parse::<usize>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    let [x, y, width, height] = values[..] else {
+        return None;
+    };
+    (width > 0 && height > 0).then_some(DiffBounds {
+        x,
+        y,
+        width,
+        height,
+    })
+}
+
+/// `0.5%` のような百分率か、`120` / `120px` のようなピクセル数を読む。
+pub fn parse_diff_threshold(raw: &str) -> Option<DiffThreshold> {
+    let raw = raw.trim();
+    if let Some(percent) = raw.strip_suffix('%') {
+        let ratio = percent.trim().parse::<f64>().ok()? / 100.0;
+        return (0.0..=1.0)
+            .contains(&ratio)
+            .then_some(DiffThreshold::Ratio(ratio));
+    }
+    let pixels = raw.strip_suffix("px").unwrap_or(raw).trim();
+    pixels.parse::<usize>().ok().map(DiffThreshold::Pixels)
+}
+
+/// [`diff_rgba_pixels`] を画像を等分したチャンクごとに複数スレッドで実行し、結果を集計する。
+///
+/// `width` は1行のピクセル数で、変化した領域の座標を求めるのに使う。
+pub fn diff_rgba_image(
+    baseline: &[u8],
+    current: &[u8],
+    output: &mut [u8],
+    width: usize,
+) -> DiffSummary {
+    diff_rgba_image_with_params(
+        baseline,
+        current,
+        output,
+        DiffParams {
+            width,
+            ..DiffParams::default()
+        },
+    )
+}
+
+/// [`diff_rgba_image`] に打ち切りの上限や除外するピクセルを指定する。
+/// 打ち切った場合、未比較の領域の `output` は書き込まれない。
+pub fn diff_rgba_image_with_params(
+    baseline: &[u8],
+    current: &[u8],
+    output: &mut [u8],
+    params: DiffParams,
+) -> DiffSummary {
+    let total_pixels = baseline.len() / RGBA_CHANNELS;
+    let workers = thread::available_parallelism()
+        .map(|count| count.get())
+        .unwrap_or(1)
+        .min(total_pixels / MIN_PIXELS_PER_THREAD)
+        .max(1);
+    diff_rgba_image_with_workers(baseline, current, output, params, workers)
+}
+
+fn diff_rgba_image_with_workers(
+    baseline: &[u8],
+    current: &[u8],
+    output: &mut [u8],
+    params: DiffParams,
+    workers: usize,
+) -> DiffSummary {
+    let total_pixels = baseline.len() / RGBA_CHANNELS;
+    let chunk_pixels = total_pixels.div_ceil(workers.max(1)).max(1);
+    let chunk_len = chunk_pixels * RGBA_CHANNELS;
+    let seen_diff_pixels = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let diff_chunk = |(start, base, cur, out): (usize, &[u8], &[u8], &mut [u8])| {
+        let Some(limit) = params.limit else {
+            return diff_rgba_region(base, cur, out, start, params);
+        };
+        let block_len = FAIL_FAST_BLOCK_PIXELS * RGBA_CHANNELS;
+        let pixels = base.len() / RGBA_CHANNELS;
+        let ignored_pixels = params.ignore_mask.map_or(0, |mask| {
+            mask[start..start + pixels]
+                .iter()
+                .filter(|&&ignored| ignored)
+                .count()
+        });
+        let mut summary = DiffSummary {
+            total_pixels: pixels - ignored_pixels,
+            ignored_pixels,
+            ..DiffSummary::default()
+        };
+        for (index, ((base, cur), out)) in base
+            .chunks(block_len)
+            .zip(cur.chunks(block_len))
+            .zip(out.chunks_mut(block_len))
+            .enumerate()
+        {
+            if stop.load(Ordering::Relaxed) {
+                summary.stopped_early = true;
+                break;
+            }
+            let block_start = start + index * FAIL_FAST_BLOCK_PIXELS;
+            let block = diff_rgba_region(base, cur, out, block_start, params);
+            let block_diff = block.diff_pixels;
+            // 比較したピクセル数はチャンク全体で数えてあるので、ブロック分は足さない
+            summary = summary.merge(DiffSummary {
+                total_pixels: 0,
+                ignored_pixels: 0,
+                ..block
+            });
+            if seen_diff_pixels.fetch_add(block_diff, Ordering::Relaxed) + block_diff > limit {
+                stop.store(true, Ordering::Relaxed);
+            }
+        }
+        summary
+    };
+    let chunks = baseline
+        .chunks(chunk_len)
+        .zip(current.chunks(chunk_len))
+        .zip(output.chunks_mut(chunk_len))
+        .enumerate()
+        .map(|(index, ((base, cur), out))| (index * chunk_pixels, base, cur, out));
+
+    if workers <= 1 {
+        return chunks
+            .map(diff_chunk)
+            .fold(DiffSummary::default(), DiffSummary::merge);
+    }
+    thread::scope(|scope| {
+        let handles: Vec<_> = chunks
+            .map(|chunk| scope.spawn(move || diff_chunk(chunk)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("diff worker panicked"))
+            .fold(DiffSummary::default(), DiffSummary::merge)
+    })
+}
+
+/// アルファ乗算済みの RGBA8 バッファ2枚を比較し、差分画像を `output` に書き込んで差分ピクセル数を返す。
+///
+/// 変化のないピクセルは薄いグレー、変化したピクセルは赤で塗る。
+pub fn diff_rgba_pixels(baseline: &[u8], current: &[u8], output: &mut [u8]) -> usize {
+    let params = DiffParams {
+        width: baseline.len() / RGBA_CHANNELS,
+        ..DiffParams::default()
+    };
+    diff_rgba_region(baseline, current, output, 0, params).diff_pixels
+}
+
+// 画像の `start` ピクセル目から始まる連続した領域を比較する
+fn diff_rgba_region(
+    baseline: &[u8],
+    current: &[u8],
+    output: &mut [u8],
+    start: usize,
+    params: DiffParams,
+) -> DiffSummary {
+    debug_assert_eq!(baseline.len(), current.len());
+    debug_assert_eq!(baseline.len(), output.len());
+
+    let width = params.width.max(1);
+    let mut summary = DiffSummary::default();
+    for (offset, ((base, cur), out)) in baseline
+        .chunks_exact(RGBA_CHANNELS)
+        .zip(current.chunks_exact(RGBA_CHANNELS))
+        .zip(output.chunks_exact_mut(RGBA_CHANNELS))
+        .enumerate()
+    {
+        let index = start + offset;
+        if params.ignore_mask.is_some_and(|mask| mask[index]) {
+            summary.ignored_pixels += 1;
+            let blue = premultiplied(255.0, IGNORED_PIXEL_ALPHA);
+            out.copy_from_slice(&[0, 0, blue, alpha_byte(IGNORED_PIXEL_ALPHA)]);
+            continue;
+        }
+        summary.total_pixels += 1;
+        for (max, (b, c)) in summary
+            .max_channel_delta
+            .iter_mut()
+            .zip(base.iter().zip(cur))
+        {
+            *max = (*max).max(b.abs_diff(*c));
+        }
+        let (x, y) = (index % width, index / width);
+        let padded = params
+            .overlap
+            .is_some_and(|(overlap_width, overlap_height)| {
+                x >= overlap_width || y >= overlap_height
+            });
+        let same = !padded
+            && match params.metric {
+                DiffMetric::Channel => base
+                    .iter()
+                    .zip(cur)
+                    .all(|(b, c)| b.abs_diff(*c) <= PIXEL_CHANNEL_TOLERANCE),
+                DiffMetric::DeltaE(max_delta) => {
+                    base == cur || ciede2000(pixel_lab(base), pixel_lab(cur)) <= max_delta
+                }
+            };
+        if same {
+            let gray = (u16::from(cur[0]) + u16::from(cur[1]) + u16::from(cur[2])) as f64 / 3.0;
+            let value = premultiplied(gray, UNCHANGED_PIXEL_ALPHA);
+            out.copy_from_slice(&[value, value, value, alpha_byte(UNCHANGED_PIXEL_ALPHA)]);
+        } else {
+            summary.diff_pixels += 1;
+            let pixel = DiffBounds {
+                x,
+                y,
+                width: 1,
+                height: 1,
+            };
+            summary.bounds = Some(summary.bounds.map_or(pixel, |bounds| bounds.union(pixel)));
+            let delta = base[..3]
+                .iter()
+                .zip(&cur[..3])
+                .map(|(b, c)| b.abs_diff(*c))
+                .max()
+                .unwrap_or(0)
+                .max(CHANGED_PIXEL_MIN_INTENSITY);
+            let red = premultiplied(f64::from(delta), CHANGED_PIXEL_ALPHA);
+            out.copy_from_slice(&[red, 0, 0, alpha_byte(CHANGED_PIXEL_ALPHA)]);
+        }
+    }
+    summary
+}
+
+/// `image` クレートで読み込んだPNGを比較し、差分画像を書き出す（AppKitを使わない）。
+#[cfg(feature = "rust-diff-backend")]
+pub fn diff_png_files(
+    baseline_path: &str,
+    current_path: &str,
+    output_path: &str,
+    options: &DiffOptions,
+) -> Result<DiffSummary, String> {
+    let (baseline_width, baseline_height, baseline) = load_premultiplied_rgba(baseline_path)
+        .map_err(|error| format!("failed to load baseline PNG: {baseline_path} ({error})"))?;
+    let (current_width, current_height, current) = load_premultiplied_rgba(current_path)
+        .map_err(|error| format!("failed to load current PNG: {current_path} ({error})"))?;
+    let baseline_size = (baseline_width as usize, baseline_height as usize);
+    let current_size = (current_width as usize, current_height as usize);
+    let Some((width, height)) = options
+        .resize_policy
+        .canvas_size(baseline_size, current_size)
+    else {
+        return Err(format!(
+            "image size mismatch: baseline={}x{}, current={}x{}",
+            baseline_width, baseline_height, current_width, current_height
+        ));
+    };
+
+    let mask = match &options.mask_path {
+        Some(mask_path) => {
+            let (mask_width, mask_height, mask) = load_premultiplied_rgba(mask_path)
+                .map_err(|error| format!("failed to load mask PNG: {mask_path} ({error})"))?;
+            if mask_width != current_width || mask_height != current_height {
+                return Err(format!(
+                    "mask size mismatch: mask={}x{}, current={}x{}",
+                    mask_width, mask_height, current_width, current_height
+                ));
+            }
+            Some(fit_rgba(&mask, current_size, (width, height)))
+        }
+        None => None,
+    };
+    let ignore_mask = options.ignore_mask(width, height, mask.as_deref());
+    let baseline = fit_rgba(&baseline, baseline_size, (width, height));
+    let current = fit_rgba(&current, current_size, (width, height));
+
+    let mut output = vec![0u8; current.len()];
+    let params = DiffParams {
+        overlap: options.resize_policy.overlap(baseline_size, current_size),
+        ..options.params(width, height, ignore_mask.as_deref())
+    };
+    let summary = diff_rgba_image_with_params(&baseline, &current, &mut output, params);
+    if let Some(composite_path) = &options.composite_path {
+        let (composite, width, height) =
+            compose_side_by_side(&[&baseline, &current, &output], width, height);
+        save_rgba_png(composite_path, composite, width as u32, height as u32).map_err(|error| {
+            format!("failed to write composite PNG: {composite_path} ({error})")
+        })?;
+    }
+    save_rgba_png(output_path, output, width as u32, height as u32)
+        .map_err(|error| format!("failed to write diff PNG: {output_path} ({error})"))?;
+    Ok(summary)
+}
+
+#[cfg(feature = "rust-diff-backend")]
+fn save_rgba_png(
+    path: &str,
+    mut pixels: Vec<u8>,
+    width: u32,
+    height: u32,
+) -> Result<(), image::ImageError> {
+    unpremultiply_rgba(&mut pixels);
+    image::save_buffer_with_format(
+        path,
+        &pixels,
+        width,
+        height,
+        image::ExtendedColorType::Rgba8,
+        image::ImageFormat::Png,
+    )
+}
+
+#[cfg(feature = "rust-diff-backend")]
+fn load_premultiplied_rgba(path: &str) -> Result<(u32, u32, Vec<u8>), image::ImageError> {
+    let image = image::open(path)?.to_rgba8();
+    let (width, height) = image.dimensions();
+    let mut pixels = image.into_raw();
+    premultiply_rgba(&mut pixels);
+    Ok((width, height, pixels))
+}
+
+/// アルファ乗算済みの RGBA8 画像（すべて `width`x`height`）を白背景の上に横に並べ、
+/// 上部に [`COMPOSITE_LABELS`] のラベルを付けた不透明な画像を返す。
+///
+/// 戻り値は `(ピクセル, 幅, 高さ)`。
+pub fn compose_side_by_side(
+    panels: &[&[u8]],
+    width: usize,
+    height: usize,
+) -> (Vec<u8>, usize, usize) {
+    let label_height = LABEL_GLYPH_HEIGHT * LABEL_GLYPH_SCALE + COMPOSITE_PADDING * 2;
+    let composite_width = panels.len() * (width + COMPOSITE_GAP) + COMPOSITE_GAP;
+    let composite_height = label_height + height + COMPOSITE_GAP;
+    let mut composite = COMPOSITE_BACKGROUND.repeat(composite_width * composite_height);
+
+    for (index, panel) in panels.iter().enumerate() {
+        let left = COMPOSITE_GAP + index * (width + COMPOSITE_GAP);
+        if let Some(label) = COMPOSITE_LABELS.get(index) {
+            draw_label(
+                &mut composite,
+                composite_width,
+                left,
+                COMPOSITE_PADDING,
+                label,
+            );
+        }
+        for (row, pixels) in panel.chunks_exact(width * RGBA_CHANNELS).enumerate() {
+            let start = ((label_height + row) * composite_width + left) * RGBA_CHANNELS;
+            let dest = &mut composite[start..start + pixels.len()];
+            for (dest, src) in dest
+                .chunks_exact_mut(RGBA_CHANNELS)
+                .zip(pixels.chunks_exact(RGBA_CHANNELS))
+            {
+                // 乗算済みアルファなので、背景に (1 - alpha) を掛けて足せば重ねられる
+                let inverse_alpha = 255 - u16::from(src[3]);
+                for (channel, value) in dest[..3].iter_mut().zip(&src[..3]) {
+                    *channel = (u16::from(*value)
+                        + (u16::from(*channel) * inverse_alpha + 127) / 255)
+                        .min(255) as u8;
+                }
+            }
+        }
+    }
+    (composite, composite_width, composite_height)
+}
+
+fn draw_label(pixels: &mut [u8], stride: usize, left: usize, top: usize, label: &str) {
+    for (index, ch) in label.chars().enumerate() {
+        let glyph_left = left + index * (LABEL_GLYPH_WIDTH + 1) * LABEL_GLYPH_SCALE;
+        for (row, bits) in label_glyph(ch).iter().enumerate() {
+            for column in 0..LABEL_GLYPH_WIDTH {
+                if bits & (1 << (LABEL_GLYPH_WIDTH - 1 - column)) == 0 {
+                    continue;
+                }
+                for dy in 0..LABEL_GLYPH_SCALE {
+                    for dx in 0..LABEL_GLYPH_SCALE {
+                        let x = glyph_left + column * LABEL_GLYPH_SCALE + dx;
+                        let y = top + row * LABEL_GLYPH_SCALE + dy;
+                        if x >= stride {
+                            continue;
+                        }
+                        let start = (y * stride + x) * RGBA_CHANNELS;
+                        pixels[start..start + RGBA_CHANNELS].copy_from_slice(&LABEL_COLOR);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// フォントに依存せずどちらのバックエンドでも同じ見た目にするため、ラベルに使う文字だけ 5x7 のビットマップで持つ
+fn label_glyph(ch: char) -> [u8; LABEL_GLYPH_HEIGHT] {
+    match ch {
+        'A' => [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x1E],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'N' => [0x11, 0x19, 0x15, 0x13, 0x11, 0x11, 0x11],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        _ => [0; LABEL_GLYPH_HEIGHT],
+    }
+}
+
+/// PNGのストレートアルファを、AppKitのビットマップと同じアルファ乗算済みの値に変換する。
+pub fn premultiply_rgba(pixels: &mut [u8]) {
+    for pixel in pixels.chunks_exact_mut(RGBA_CHANNELS) {
+        let alpha = u16::from(pixel[3]);
+        for channel in &mut pixel[..3] {
+            *channel = ((u16::from(*channel) * alpha + 127) / 255) as u8;
+        }
+    }
+}
+
+/// [`premultiply_rgba`] の逆変換。PNGとして保存する前に使う。
+pub fn unpremultiply_rgba(pixels: &mut [u8]) {
+    for pixel in pixels.chunks_exact_mut(RGBA_CHANNELS) {
+        let alpha = u16::from(pixel[3]);
+        if alpha == 0 {
+            continue;
+        }
+        for channel in &mut pixel[..3] {
+            *channel = ((u16::from(*channel) * 255 + alpha / 2) / alpha).min(255) as u8;
+        }
+    }
+}
+
+// アルファ乗算済みのピクセルを白背景に合成し、sRGB (D65) として Lab に変換する
+fn pixel_lab(pixel: &[u8]) -> [f64; 3] {
+    let over_white = 255.0 - f64::from(pixel[3]);
+    let [r, g, b] = [0, 1, 2].map(|channel| {
+        let value = ((f64::from(pixel[channel]) + over_white) / 255.0).min(1.0);
+        if value <= 0.04045 {
+            value / 12.92
+        } else {
+            ((value + 0.055) / 1.055).powf(2.4)
+        }
+    });
+    let x = (0.4124564 * r + 0.3575761 * g + 0.1804375 * b) / 0.95047;
+    let y = 0.2126729 * r + 0.7151522 * g + 0.0721750 * b;
+    let z = (0.0193339 * r + 0.1191920 * g + 0.9503041 * b) / 1.08883;
+    let [fx, fy, fz] = [x, y, z].map(|t| {
+        const EPSILON: f64 = 216.0 / 24389.0;
+        const KAPPA: f64 = 24389.0 / 27.0;
+        if t > EPSILON {
+            t.cbrt()
+        } else {
+            (KAPPA * t + 16.0) / 116.0
+        }
+    });
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+// Sharma らの "The CIEDE2000 Color-Difference Formula" の式をそのまま実装している
+fn ciede2000(lab1: [f64; 3], lab2: [f64; 3]) -> f64 {
+    let [l1, a1, b1] = lab1;
+    let [l2, a2, b2] = lab2;
+    let pow25_7 = 25f64.powi(7);
+
+    let c_bar = (a1.hypot(b1) + a2.hypot(b2)) / 2.0;
+    let g = 0.5 * (1.0 - (c_bar.powi(7) / (c_bar.powi(7) + pow25_7)).sqrt());
+    let a1p = (1.0 + g) * a1;
+    let a2p = (1.0 + g) * a2;
+    let c1p = a1p.hypot(b1);
+    let c2p = a2p.hypot(b2);
+    let hue = |a: f64, b: f64| {
+        if a == 0.0 && b == 0.0 {
+            0.0
+        } else {
+            b.atan2(a).to_degrees().rem_euclid(360.0)
+        }
+    };
+    let h1p = hue(a1p, b1);
+    let h2p = hue(a2p, b2);
+
+    let delta_lp = l2 - l1;
+    let delta_cp = c2p - c1p;
+    let chroma_zero = c1p * c2p == 0.0;
+    let delta_hp = if chroma_zero {
+        0.0
+    } else if (h2p - h1p).abs() <= 180.0 {
+        h2p - h1p
+    } else if h2p - h1p > 180.0 {
+        h2p - h1p - 360.0
+    } else {
+        h2p - h1p + 360.0
+    };
+    let delta_big_hp = 2.0 * (c1p * c2p).sqrt() * (delta_hp / 2.0).to_radians().sin();
+
+    let l_bar_p = (l1 + l2) / 2.0;
+    let c_bar_p = (c1p + c2p) / 2.0;
+    let h_bar_p = if chroma_zero {
+        h1p + h2p
+    } else if (h1p - h2p).abs() <= 180.0 {
+        (h1p + h2p) / 2.0
+    } else if h1p + h2p < 360.0 {
+        (h1p + h2p + 360.0) / 2.0
+    } else {
+        (h1p + h2p - 360.0) / 2.0
+    };
+    let t = 1.0 - 0.17 * (h_bar_p - 30.0).to_radians().cos()
+        + 0.24 * (2.0 * h_bar_p).to_radians().cos()
+        + 0.32 * (3.0 * h_bar_p + 6.0).to_radians().cos()
+        - 0.20 * (4.0 * h_bar_p - 63.0).to_radians().cos();
+    let delta_theta = 30.0 * (-((h_bar_p - 275.0) / 25.0).powi(2)).exp();
+    let r_c = 2.0 * (c_bar_p.powi(7) / (c_bar_p.powi(7) + pow25_7)).sqrt();
+    let s_l = 1.0 + 0.015 * (l_bar_p - 50.0).powi(2) / (20.0 + (l_bar_p - 50.0).powi(2)).sqrt();
+    let s_c = 1.0 + 0.045 * c_bar_p;
+    let s_h = 1.0 + 0.015 * c_bar_p * t;
+    let r_t = -(2.0 * delta_theta).to_radians().sin() * r_c;
+
+    let l_term = delta_lp / s_l;
+    let c_term = delta_cp / s_c;
+    let h_term = delta_big_hp / s_h;
+    (l_term.powi(2) + c_term.powi(2) + h_term.powi(2) + r_t * c_term * h_term).sqrt()
+}
+
+fn premultiplied(value: f64, alpha: f64) -> u8 {
+    (value * alpha).round().clamp(0.0, 255.0) as u8
+}
+
+fn alpha_byte(alpha: f64) -> u8 {
+    (alpha * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ciede2000, parse_diff_metric, pixel_lab, DiffMetric, DEFAULT_MAX_DELTA_E};
+    use super::{
+        compose_side_by_side, diff_rgba_image_with_params, parse_diff_threshold,
+        parse_ignore_region, DiffOptions, DiffParams, DiffThreshold,
+    };
+    use super::{
+        diff_rgba_image, diff_rgba_image_with_workers, diff_rgba_pixels, premultiply_rgba,
+        unpremultiply_rgba, DiffBounds, DiffSummary, RGBA_CHANNELS,
+    };
+    use super::{fit_rgba, parse_resize_policy, ResizePolicy};
+
+    fn params(width: usize) -> DiffParams<'static> {
+        DiffParams {
+            width,
+            ..DiffParams::default()
+        }
+    }
+
+    #[test]
+    fn diff_rgba_pixels_counts_pixels_beyond_tolerance() {
+        let baseline = [
+            10, 10, 10, 255, // 同一
+            100, 100, 100, 255, // 許容範囲内
+            0, 0, 0, 255, // 変化
+        ];
+        let current = [
+            10, 10, 10, 255, //
+            102, 98, 100, 255, //
+            200, 0, 0, 255, //
+        ];
+        let mut output = [0u8; 3 * RGBA_CHANNELS];
+
+        let diff_pixels = diff_rgba_pixels(&baseline, &current, &mut output);
+
+        assert_eq!(diff_pixels, 1);
+        assert_eq!(&output[0..4], &[1, 1, 1, 20]);
+        assert_eq!(&output[4..8], &[8, 8, 8, 20]);
+        assert_eq!(&output[8..12], &[180, 0, 0, 230]);
+    }
+
+    #[test]
+    fn diff_rgba_image_merges_chunk_counts_across_workers() {
+        let width = 17;
+        let pixels = 1_003;
+        let baseline: Vec<u8> = (0..pixels * RGBA_CHANNELS)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let current: Vec<u8> = baseline
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                if i % 28 == 0 {
+                    value.wrapping_add(50)
+                } else {
+                    *value
+                }
+            })
+            .collect();
+
+        let mut sequential = vec![0u8; baseline.len()];
+        let expected_diff = diff_rgba_pixels(&baseline, &current, &mut sequential);
+        let mut output = vec![0u8; baseline.len()];
+        let expected =
+            diff_rgba_image_with_workers(&baseline, &current, &mut output, params(width), 1);
+        assert_eq!(expected.diff_pixels, expected_diff);
+        assert_eq!(expected.total_pixels, pixels);
+
+        for workers in [3, 8] {
+            let mut output = vec![0u8; baseline.len()];
+            let summary = diff_rgba_image_with_workers(
+                &baseline,
+                &current,
+                &mut output,
+                params(width),
+                workers,
+            );
+            assert_eq!(summary, expected);
+            assert_eq!(output, sequential);
+        }
+
+        let mut output = vec![0u8; baseline.len()];
+        assert_eq!(
+            diff_rgba_image(&baseline, &current, &mut output, width).diff_pixels,
+            expected_diff
+        );
+    }
+
+    #[test]
+    fn diff_summary_reports_changed_bounds_and_channel_deltas() {
+        // 4x3 の画像で (1,0) と (2,2) が変化し、(3,1) は許容範囲内の差だけ
+        let width = 4;
+        let baseline = [40u8, 40, 40, 255].repeat(12);
+        let mut current = baseline.clone();
+        current[4..8].copy_from_slice(&[140, 40, 40, 255]);
+        current[(2 * width + 2) * RGBA_CHANNELS..][..4].copy_from_slice(&[40, 40, 100, 200]);
+        current[(width + 3) * RGBA_CHANNELS..][..4].copy_from_slice(&[40, 42, 40, 255]);
+
+        for workers in [1, 5] {
+            let mut output = vec![0u8; baseline.len()];
+            let summary = diff_rgba_image_with_workers(
+                &baseline,
+                &current,
+                &mut output,
+                params(width),
+                workers,
+            );
+            assert_eq!(summary.diff_pixels, 2);
+            assert_eq!(
+                summary.bounds,
+                Some(DiffBounds {
+                    x: 1,
+                    y: 0,
+                    width: 2,
+                    height: 3,
+                })
+            );
+            assert_eq!(summary.max_channel_delta, [100, 2, 60, 55]);
+        }
+
+        let json = DiffSummary {
+            diff_pixels: 2,
+            total_pixels: 8,
+            ..DiffSummary::default()
+        }
+        .to_json();
+        assert_eq!(json["ratio"], 0.25);
+        assert!(json["bounds"].is_null());
+        assert_eq!(json["max_channel_delta"]["r"], 0);
+    }
+
+    #[test]
+    fn diff_with_limit_stops_once_threshold_is_exceeded() {
+        let pixels = 64 * 1024;
+        let baseline = vec![0u8; pixels * RGBA_CHANNELS];
+        let current = vec![255u8; pixels * RGBA_CHANNELS];
+        let threshold = DiffThreshold::Ratio(0.005);
+        let params = DiffParams {
+            limit: Some(threshold.pixel_limit(pixels)),
+            ..params(256)
+        };
+
+        for workers in [1, 4] {
+            let mut output = vec![0u8; baseline.len()];
+            let summary =
+                diff_rgba_image_with_workers(&baseline, &current, &mut output, params, workers);
+            assert!(summary.stopped_early);
+            assert!(summary.exceeds(threshold));
+            assert!(summary.diff_pixels < pixels);
+            assert_eq!(summary.total_pixels, pixels);
+        }
+
+        let mut output = vec![0u8; baseline.len()];
+        let summary = diff_rgba_image_with_params(&baseline, &baseline, &mut output, params);
+        assert!(!summary.stopped_early);
+        assert!(!summary.exceeds(threshold));
+    }
+
+    #[test]
+    fn ignore_mask_excludes_regions_and_opaque_mask_pixels() {
+        // 4x2 の画像。右上の 2x1 を領域で、左下をマスク画像で除外する
+        let width = 4;
+        let options = DiffOptions {
+            ignore_regions: vec![parse_ignore_region("2,0,5,1").expect("region")],
+            fail_fast_threshold: Some(DiffThreshold::Ratio(0.5)),
+            ..DiffOptions::default()
+        };
+        let mut mask_pixels = [0u8; 8 * RGBA_CHANNELS];
+        mask_pixels[4 * RGBA_CHANNELS..][..4].copy_from_slice(&[0, 0, 0, 255]);
+        mask_pixels[5 * RGBA_CHANNELS..][..4].copy_from_slice(&[0, 0, 0, 100]);
+        let mask = options
+            .ignore_mask(width, 2, Some(&mask_pixels))
+            .expect("mask");
+        assert_eq!(mask, [false, false, true, true, true, false, false, false]);
+        assert_eq!(DiffOptions::default().ignore_mask(width, 2, None), None);
+
+        let params = options.params(width, 2, Some(&mask));
+        assert_eq!(params.limit, Some(2));
+
+        let baseline = [0u8, 0, 0, 255].repeat(8);
+        let mut current = [255u8, 255, 255, 255].repeat(8);
+        current[..4].copy_from_slice(&[0, 0, 0, 255]);
+        let mut output = vec![0u8; baseline.len()];
+        let summary = diff_rgba_image_with_params(&baseline, &current, &mut output, params);
+        assert_eq!(summary.diff_pixels, 4);
+        assert_eq!(summary.total_pixels, 5);
+        assert_eq!(summary.ignored_pixels, 3);
+        assert_eq!(&output[8..12], &[0, 0, 64, 64]);
+
+        assert_eq!(parse_ignore_region("0,0,0,10"), None);
+        assert_eq!(parse_ignore_region("1,2,3"), None);
+        assert_eq!(parse_ignore_region("1,2,3,x"), None);
+    }
+
+    #[test]
+    fn ciede2000_matches_reference_pairs() {
+        // Sharma らの論文に載っているテストデータから抜粋
+        let pairs = [
+            ([50.0, 2.6772, -79.7751], [50.0, 0.0, -82.7485], 2.0425),
+            ([50.0, 0.0, 0.0], [50.0, -1.0, 2.0], 2.3669),
+            ([50.0, 2.5, 0.0], [73.0, 25.0, -18.0], 27.1492),
+            (
+                [60.2574, -34.0099, 36.2677],
+                [60.4626, -34.1751, 39.4387],
+                1.2644,
+            ),
+            (
+                [2.0776, 0.0795, -1.1350],
+                [0.9033, -0.0636, -0.5514],
+                0.9082,
+            ),
+        ];
+        for (lab1, lab2, expected) in pairs {
+            assert!((ciede2000(lab1, lab2) - expected).abs() < 1e-4);
+            assert!((ciede2000(lab2, lab1) - expected).abs() < 1e-4);
+        }
+
+        let white = pixel_lab(&[255, 255, 255, 255]);
+        assert!((white[0] - 100.0).abs() < 1e-3 && white[1].abs() < 1e-3);
+        // 透明なピクセルは白背景と同じ色になる
+        assert!(ciede2000(pixel_lab(&[0, 0, 0, 0]), white) < 1e-9);
+    }
+
+    #[test]
+    fn delta_e_metric_ignores_imperceptible_gradient_shifts() {
+        let baseline = [
+            120, 120, 120, 255, // わずかな明度の揺れ
+            0, 0, 200, 255, // 色相の変化
+        ];
+        let current = [
+            123, 123, 123, 255, //
+            0, 60, 200, 255, //
+        ];
+        let mut output = [0u8; 2 * RGBA_CHANNELS];
+        let channel = diff_rgba_image_with_params(&baseline, &current, &mut output, params(2));
+        assert_eq!(channel.diff_pixels, 2);
+
+        let params = DiffParams {
+            metric: DiffMetric::DeltaE(DEFAULT_MAX_DELTA_E),
+            ..params(2)
+        };
+        let delta_e = diff_rgba_image_with_params(&baseline, &current, &mut output, params);
+        assert_eq!(delta_e.diff_pixels, 1);
+        assert_eq!(&output[4..8], &[115, 0, 0, 230]);
+
+        assert_eq!(
+            parse_diff_metric("deltae", None),
+            Some(DiffMetric::DeltaE(2.0))
+        );
+        assert_eq!(
+            parse_diff_metric("deltae", Some(1.5)),
+            Some(DiffMetric::DeltaE(1.5))
+        );
+        assert_eq!(
+            parse_diff_metric("channel", None),
+            Some(DiffMetric::Channel)
+        );
+        assert_eq!(parse_diff_metric("channel", Some(1.0)), None);
+        assert_eq!(parse_diff_metric("deltae", Some(-1.0)), None);
+        assert_eq!(parse_diff_metric("ssim", None), None);
+    }
+
+    #[test]
+    fn pad_policy_counts_padded_area_as_diff() {
+        // 右に足された列は透明なので、埋めた領域と色は同じでも変化として数える
+        let baseline = [10u8, 10, 10, 255].repeat(4);
+        let current = [10, 10, 10, 255, 10, 10, 10, 255, 0, 0, 0, 0].repeat(2);
+        let (baseline_size, current_size) = ((2, 2), (3, 2));
+
+        assert_eq!(
+            ResizePolicy::Fail.canvas_size(baseline_size, current_size),
+            None
+        );
+        assert_eq!(
+            ResizePolicy::Crop.canvas_size(baseline_size, current_size),
+            Some((2, 2))
+        );
+        let canvas = ResizePolicy::Pad
+            .canvas_size(baseline_size, current_size)
+            .expect("canvas");
+        assert_eq!(canvas, (3, 2));
+
+        let padded = fit_rgba(&baseline, baseline_size, canvas);
+        assert_eq!(&padded[8..12], &[0, 0, 0, 0]);
+        assert_eq!(fit_rgba(&current, current_size, (2, 2)), baseline);
+
+        let params = DiffParams {
+            overlap: ResizePolicy::Pad.overlap(baseline_size, current_size),
+            ..params(3)
+        };
+        let mut output = vec![0u8; current.len()];
+        let summary = diff_rgba_image_with_params(&padded, &current, &mut output, params);
+        assert_eq!(summary.diff_pixels, 2);
+        assert_eq!(
+            summary.bounds,
+            Some(DiffBounds {
+                x: 2,
+                y: 0,
+                width: 1,
+                height: 2,
+            })
+        );
+        assert_eq!(
+            ResizePolicy::Crop.overlap(baseline_size, current_size),
+            None
+        );
+
+        assert_eq!(parse_resize_policy("pad"), Some(ResizePolicy::Pad));
+        assert_eq!(parse_resize_policy("stretch"), None);
+    }
+
+    #[test]
+    fn parse_diff_threshold_accepts_percent_and_pixels() {
+        assert_eq!(
+            parse_diff_threshold("0.5%"),
+            Some(DiffThreshold::Ratio(0.005))
+        );
+        assert_eq!(
+            parse_diff_threshold("120"),
+            Some(DiffThreshold::Pixels(120))
+        );
+        assert_eq!(
+            parse_diff_threshold("120px"),
+            Some(DiffThreshold::Pixels(120))
+        );
+        assert_eq!(parse_diff_threshold("150%"), None);
+        assert_eq!(parse_diff_threshold("0.02"), None);
+        assert_eq!(parse_diff_threshold("-1"), None);
+
+        assert_eq!(DiffThreshold::Ratio(0.005).pixel_limit(10_000), 50);
+        assert_eq!(DiffThreshold::Pixels(7).pixel_limit(10_000), 7);
+    }
+
+    #[test]
+    fn diff_rgba_pixels_uses_minimum_intensity_for_small_changes() {
+        let baseline = [50, 50, 50, 255];
+        let current = [60, 50, 50, 255];
+        let mut output = [0u8; RGBA_CHANNELS];
+
+        assert_eq!(diff_rgba_pixels(&baseline, &current, &mut output), 1);
+        assert_eq!(output, [115, 0, 0, 230]);
+    }
+
+    #[test]
+    fn compose_side_by_side_places_labeled_panels_on_white() {
+        let black = [0u8, 0, 0, 255].repeat(4);
+        let transparent = [0u8; 4 * RGBA_CHANNELS];
+        let half_red = [128u8, 0, 0, 128].repeat(4);
+
+        let (pixels, width, height) =
+            compose_side_by_side(&[&black, &transparent, &half_red], 2, 2);
+
+        // 余白 8px と 2px のパネルが3枚、上にラベル帯 22px
+        assert_eq!((width, height), (38, 32));
+        assert_eq!(pixels.len(), width * height * RGBA_CHANNELS);
+        let pixel = |x: usize, y: usize| {
+            let start = (y * width + x) * RGBA_CHANNELS;
+            &pixels[start..start + RGBA_CHANNELS]
+        };
+        assert_eq!(pixel(8, 22), &[0, 0, 0, 255]);
+        assert_eq!(pixel(18, 22), &[255, 255, 255, 255]);
+        assert_eq!(pixel(28, 23), &[255, 127, 127, 255]);
+        // "BASELINE" の B の左上
+        assert_eq!(pixel(8, 4), &[40, 40, 40, 255]);
+        assert_eq!(pixel(0, 0), &[255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn premultiply_round_trips_straight_alpha() {
+        let mut pixels = [200, 100, 0, 128, 255, 255, 255, 255, 80, 80, 80, 0];
+
+        premultiply_rgba(&mut pixels);
+        assert_eq!(pixels, [100, 50, 0, 128, 255, 255, 255, 255, 0, 0, 0, 0]);
+
+        unpremultiply_rgba(&mut pixels);
+        assert_eq!(pixels, [199, 100, 0, 128, 255, 255, 255, 255, 0, 0, 0, 0]);
+    }
+
+    #[cfg(feature = "rust-diff-backend")]
+    #[test]
+    fn diff_png_files_compares_pngs_without_appkit() {
+        let dir = std::env::temp_dir().join(format!("cliip-show-png-diff-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("create temp dir");
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+
+        let baseline = [0u8, 0, 0, 255].repeat(4);
+        let mut current = baseline.clone();
+        current[..4].copy_from_slice(&[255, 0, 0, 255]);
+        for (name, pixels) in [("baseline.png", &baseline), ("current.png", &current)] {
+            image::save_buffer(path(name), pixels, 2, 2, image::ExtendedColorType::Rgba8)
+                .expect("write png");
+        }
+
+        let options = super::DiffOptions {
+            composite_path: Some(path("composite.png")),
+            ..super::DiffOptions::default()
+        };
+        let summary = super::diff_png_files(
+            &path("baseline.png"),
+            &path("current.png"),
+            &path("diff.png"),
+            &options,
+        )
+        .expect("diff pngs");
+        assert_eq!(
+            summary,
+            DiffSummary {
+                diff_pixels: 1,
+                total_pixels: 4,
+                ignored_pixels: 0,
+                stopped_early: false,
+                bounds: Some(DiffBounds {
+                    x: 0,
+                    y: 0,
+                    width: 1,
+                    height: 1,
+                }),
+                max_channel_delta: [255, 0, 0, 0],
+            }
+        );
+        let diff = image::open(path("diff.png")).expect("read diff").to_rgba8();
+        assert_eq!(diff.get_pixel(0, 0).0, [255, 0, 0, 230]);
+        let composite = image::open(path("composite.png"))
+            .expect("read composite")
+            .to_rgba8();
+        assert_eq!(composite.dimensions(), (38, 32));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
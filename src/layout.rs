@@ -0,0 +1,647 @@
+use objc2_foundation::NSRect;
+
+use crate::config::{
+    parse_f64_value, DisplaySettings, HudPosition, DEFAULT_HUD_FONT_SIZE, DEFAULT_HUD_SCALE,
+    MAX_HUD_SCALE, MIN_HUD_SCALE,
+};
+use crate::truncation::{line_display_units, split_non_trailing_lines};
+
+const HUD_MIN_WIDTH: f64 = 200.0;
+const HUD_MAX_WIDTH: f64 = 820.0;
+const HUD_MIN_HEIGHT: f64 = 52.0;
+const HUD_MAX_HEIGHT: f64 = 280.0;
+const HUD_HORIZONTAL_PADDING: f64 = 16.0;
+const HUD_VERTICAL_PADDING: f64 = 10.0;
+/// 倍率を掛ける前の左右と上下の余白。テーマで変えられる
+pub const DEFAULT_HUD_PADDING: (f64, f64) = (HUD_HORIZONTAL_PADDING, HUD_VERTICAL_PADDING);
+const HUD_ICON_WIDTH: f64 = 22.0;
+const HUD_ICON_HEIGHT: f64 = 22.0;
+const HUD_THUMBNAIL_SIZE: f64 = 64.0;
+const HUD_GAP: f64 = 8.0;
+// 既定の文字の大きさ（DEFAULT_HUD_FONT_SIZE）での見積もり。文字の大きさに比例させる
+const HUD_CHAR_WIDTH_ESTIMATE: f64 = 9.6;
+const HUD_LINE_HEIGHT_ESTIMATE: f64 = 22.0;
+const HUD_BADGE_HEIGHT: f64 = 14.0;
+const HUD_FOOTER_HEIGHT: f64 = 16.0;
+const HUD_HEADER_HEIGHT: f64 = 16.0;
+
+/// 1行分のテキストの描画幅（ポイント）を返す。
+///
+/// 実行時は `rendering` がフォントの実測値を返し、テストでは文字数からの見積もりを使う。
+pub trait TextMeasurer {
+    fn line_width(&mut self, line: &str) -> f64;
+}
+
+/// フォントを使わず、[`line_display_units`] に1文字分の幅を掛けて見積もる計測器。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EstimatedTextMeasurer {
+    pub char_width: f64,
+}
+
+impl TextMeasurer for EstimatedTextMeasurer {
+    fn line_width(&mut self, line: &str) -> f64 {
+        line_display_units(line) * self.char_width
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HudLayoutMetrics {
+    pub width: f64,
+    pub text_width: f64,
+    pub height: f64,
+    pub text_height: f64,
+    pub label_y: f64,
+    pub icon_y: f64,
+    pub footer_y: f64,
+    /// 本文の上のヘッダー（コピー元のアプリなど）の下端。ヘッダーがなければ本文の上端
+    pub header_y: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HudDimensions {
+    pub min_width: f64,
+    pub max_width: f64,
+    pub min_height: f64,
+    pub max_height: f64,
+    pub horizontal_padding: f64,
+    pub vertical_padding: f64,
+    pub icon_width: f64,
+    pub icon_height: f64,
+    /// 画像のクリップで、アイコンの代わりに出すサムネイルの一辺
+    pub thumbnail_size: f64,
+    pub gap: f64,
+    pub line_height_estimate: f64,
+    pub char_width_estimate: f64,
+    /// 本文の行と行のあいだに足す余白
+    pub line_spacing: f64,
+    pub badge_height: f64,
+    pub footer_height: f64,
+    pub header_height: f64,
+}
+
+/// HUD の寸法を決める設定。`f64` からは、その倍率と既定の余白・文字の大きさで作る。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HudSizing {
+    pub scale: f64,
+    /// 倍率を掛ける前の左右と上下の余白
+    pub padding: (f64, f64),
+    /// 倍率を掛ける前の本文の文字の大きさ
+    pub font_size: f64,
+    /// 倍率を掛ける前の本文の行と行のあいだの余白
+    pub line_spacing: f64,
+}
+
+impl From<f64> for HudSizing {
+    fn from(scale: f64) -> Self {
+        Self {
+            scale,
+            padding: DEFAULT_HUD_PADDING,
+            font_size: DEFAULT_HUD_FONT_SIZE,
+            line_spacing: 0.0,
+        }
+    }
+}
+
+impl From<DisplaySettings> for HudSizing {
+    fn from(settings: DisplaySettings) -> Self {
+        Self {
+            scale: settings.hud_scale,
+            padding: settings.hud_padding,
+            font_size: settings.hud_font_size,
+            line_spacing: settings.hud_line_spacing,
+        }
+    }
+}
+
+pub fn hud_dimensions(sizing: impl Into<HudSizing>) -> HudDimensions {
+    let HudSizing {
+        scale,
+        padding: (horizontal_padding, vertical_padding),
+        font_size,
+        line_spacing,
+    } = sizing.into();
+    let clamped_scale = parse_f64_value(scale, DEFAULT_HUD_SCALE, MIN_HUD_SCALE, MAX_HUD_SCALE);
+    let text_scale = clamped_scale * font_size / DEFAULT_HUD_FONT_SIZE;
+    HudDimensions {
+        min_width: HUD_MIN_WIDTH * clamped_scale,
+        max_width: HUD_MAX_WIDTH * clamped_scale,
+        min_height: HUD_MIN_HEIGHT * clamped_scale,
+        max_height: HUD_MAX_HEIGHT * clamped_scale,
+        horizontal_padding: horizontal_padding * clamped_scale,
+        vertical_padding: vertical_padding * clamped_scale,
+        icon_width: HUD_ICON_WIDTH * clamped_scale,
+        icon_height: HUD_ICON_HEIGHT * clamped_scale,
+        thumbnail_size: HUD_THUMBNAIL_SIZE * clamped_scale,
+        gap: HUD_GAP * clamped_scale,
+        line_height_estimate: HUD_LINE_HEIGHT_ESTIMATE * text_scale,
+        char_width_estimate: HUD_CHAR_WIDTH_ESTIMATE * text_scale,
+        line_spacing: line_spacing * clamped_scale,
+        badge_height: HUD_BADGE_HEIGHT * clamped_scale,
+        footer_height: HUD_FOOTER_HEIGHT * clamped_scale,
+        header_height: HUD_HEADER_HEIGHT * clamped_scale,
+    }
+}
+
+pub fn hud_origin_for_frame(
+    frame: NSRect,
+    width: f64,
+    height: f64,
+    position: HudPosition,
+) -> (f64, f64) {
+    let min_x = frame.origin.x;
+    let max_x = frame.origin.x + (frame.size.width - width).max(0.0);
+    let min_y = frame.origin.y;
+    let max_y = frame.origin.y + (frame.size.height - height).max(0.0);
+
+    let x = frame.origin.x + (frame.size.width - width) / 2.0;
+    let available_height = max_y - min_y;
+    let center_y = frame.origin.y + available_height / 2.0;
+    let vertical_quarter = available_height / 4.0;
+    // AppKit screen coordinates increase upward. "Top" means a larger y value.
+    let upper_half_mid_y = center_y + vertical_quarter;
+    let lower_half_mid_y = center_y - vertical_quarter;
+    let y = match position {
+        HudPosition::Top => upper_half_mid_y,
+        HudPosition::Center => center_y,
+        HudPosition::Bottom => lower_half_mid_y,
+    };
+    let x = x.clamp(min_x, max_x);
+    let y = y.clamp(min_y, max_y);
+    (x, y)
+}
+
+#[cfg(test)]
+fn compute_hud_layout_metrics(width: f64, measured_text_height: f64) -> HudLayoutMetrics {
+    compute_hud_layout_metrics_with_scale(width, measured_text_height, 0.0, DEFAULT_HUD_SCALE)
+}
+
+/// HUD の枠・本文・アイコン・フッターの寸法と位置を求める。
+///
+/// `width` と高さは `sizing` に応じた [`HudDimensions`] の範囲に収め、収まらない本文の高さは
+/// 切り詰める。`footer_height` が 0 ならフッターなしとして扱う。
+pub fn compute_hud_layout_metrics_with_scale(
+    width: f64,
+    measured_text_height: f64,
+    footer_height: f64,
+    sizing: impl Into<HudSizing>,
+) -> HudLayoutMetrics {
+    let sizing = sizing.into();
+    let dims = hud_dimensions(sizing);
+    compute_hud_layout_metrics_with_leading(
+        width,
+        measured_text_height,
+        footer_height,
+        (dims.icon_width, dims.icon_height),
+        sizing,
+    )
+}
+
+/// [`compute_hud_layout_metrics_with_scale`] と同じだが、本文の左にアイコンの代わりに
+/// `leading_size`（幅, 高さ）の要素（画像のサムネイルなど）を置く。
+///
+/// 返す `icon_y` はその要素の下端になる。
+pub fn compute_hud_layout_metrics_with_leading(
+    width: f64,
+    measured_text_height: f64,
+    footer_height: f64,
+    leading_size: (f64, f64),
+    sizing: impl Into<HudSizing>,
+) -> HudLayoutMetrics {
+    compute_hud_layout_metrics_with_header(
+        width,
+        measured_text_height,
+        (0.0, footer_height),
+        leading_size,
+        sizing,
+    )
+}
+
+/// [`compute_hud_layout_metrics_with_leading`] と同じだが、本文の上に `header_height` の
+/// ヘッダーの行を空ける。`(header_height, footer_height)` のどちらも 0 ならその行はない。
+pub fn compute_hud_layout_metrics_with_header(
+    width: f64,
+    measured_text_height: f64,
+    (header_height, footer_height): (f64, f64),
+    leading_size: (f64, f64),
+    sizing: impl Into<HudSizing>,
+) -> HudLayoutMetrics {
+    let dims = hud_dimensions(sizing);
+    let (leading_width, leading_height) = leading_size;
+    let width = width.clamp(dims.min_width, dims.max_width);
+    let text_width = width - (dims.horizontal_padding * 2.0 + leading_width + dims.gap);
+    let header_height = header_height.max(0.0);
+    let footer_height = footer_height.max(0.0);
+    let reserved_height = header_height + footer_height;
+    let measured_text_height = measured_text_height.min(
+        (dims.max_height - dims.vertical_padding * 2.0 - reserved_height)
+            .max(dims.line_height_estimate),
+    );
+    let height = (measured_text_height + reserved_height + dims.vertical_padding * 2.0)
+        .clamp(dims.min_height, dims.max_height);
+    let text_height = (height - dims.vertical_padding * 2.0 - reserved_height)
+        .min(measured_text_height)
+        .max(dims.line_height_estimate);
+    // フッターはテキストの下（AppKit座標では y の小さい側）、ヘッダーは上に置く
+    let label_y = footer_height + (height - reserved_height - text_height) / 2.0;
+    let footer_y = dims.vertical_padding;
+    let header_y = height - dims.vertical_padding - header_height;
+    let icon_y = (label_y + text_height - leading_height)
+        .max(dims.vertical_padding)
+        .min(height - leading_height - dims.vertical_padding - header_height);
+
+    HudLayoutMetrics {
+        width,
+        text_width,
+        height,
+        text_height,
+        label_y,
+        icon_y,
+        footer_y,
+        header_y,
+    }
+}
+
+/// AppKit を使わずに、行数と行間から本文の高さを見積もる。折り返しは数えない。
+pub fn estimated_text_height(text: &str, sizing: impl Into<HudSizing>) -> f64 {
+    let dims = hud_dimensions(sizing);
+    let lines = split_non_trailing_lines(text).len().max(1) as f64;
+    lines * dims.line_height_estimate + (lines - 1.0) * dims.line_spacing
+}
+
+#[cfg(test)]
+fn hud_width_for_text(text: &str) -> f64 {
+    hud_width_for_text_with_scale(text, DEFAULT_HUD_SCALE)
+}
+
+/// AppKit を使わずに、文字幅の見積もり（[`EstimatedTextMeasurer`]）から HUD の幅を求める。
+pub fn hud_width_for_text_with_scale(text: &str, sizing: impl Into<HudSizing>) -> f64 {
+    let sizing = sizing.into();
+    let mut measurer = EstimatedTextMeasurer {
+        char_width: hud_dimensions(sizing).char_width_estimate,
+    };
+    hud_width_for_text_with_measurer(text, &mut measurer, sizing)
+}
+
+pub fn hud_width_for_text_with_measurer(
+    text: &str,
+    measurer: &mut impl TextMeasurer,
+    sizing: impl Into<HudSizing>,
+) -> f64 {
+    let dims = hud_dimensions(sizing);
+    let max_line_width = split_non_trailing_lines(text)
+        .iter()
+        .map(|line| measurer.line_width(line))
+        .fold(0.0f64, f64::max);
+
+    (max_line_width + dims.horizontal_padding * 2.0 + dims.icon_width + dims.gap)
+        .clamp(dims.min_width, dims.max_width)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        compute_hud_layout_metrics, compute_hud_layout_metrics_with_header,
+        compute_hud_layout_metrics_with_leading, compute_hud_layout_metrics_with_scale,
+        estimated_text_height, hud_dimensions, hud_origin_for_frame, hud_width_for_text,
+        hud_width_for_text_with_measurer, hud_width_for_text_with_scale, HudSizing, TextMeasurer,
+        HUD_FOOTER_HEIGHT,
+    };
+    use crate::config::{HudPosition, DEFAULT_HUD_SCALE, MAX_HUD_SCALE, MIN_HUD_SCALE};
+    use objc2_foundation::{NSPoint, NSRect, NSSize};
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn layout_metrics_stay_within_clamps(
+            width in -1000.0f64..2000.0,
+            measured_text_height in 0.0f64..2000.0,
+            has_footer in any::<bool>(),
+            scale in MIN_HUD_SCALE..=MAX_HUD_SCALE,
+        ) {
+            let dims = hud_dimensions(scale);
+            let footer_height = if has_footer { dims.footer_height } else { 0.0 };
+            let metrics = compute_hud_layout_metrics_with_scale(
+                width,
+                measured_text_height,
+                footer_height,
+                scale,
+            );
+
+            prop_assert!((dims.min_width..=dims.max_width).contains(&metrics.width));
+            prop_assert!((dims.min_height..=dims.max_height).contains(&metrics.height));
+            prop_assert!(metrics.text_width > 0.0);
+            prop_assert!(metrics.text_height >= dims.line_height_estimate);
+            // 本文・アイコン・フッターはどれも枠の内側に収まる
+            prop_assert!(metrics.label_y >= footer_height);
+            prop_assert!(metrics.label_y + metrics.text_height <= metrics.height + 1e-9);
+            prop_assert!(metrics.icon_y >= dims.vertical_padding);
+            prop_assert!(
+                metrics.icon_y + dims.icon_height
+                    <= metrics.height - dims.vertical_padding + 1e-9
+            );
+            prop_assert!(metrics.footer_y >= 0.0);
+        }
+
+        #[test]
+        fn estimated_width_is_clamped_and_grows_with_wider_lines(
+            first in "[a-zあ-ん📋 ]{0,120}",
+            second in "[a-zあ-ん📋 ]{0,120}",
+            scale in MIN_HUD_SCALE..=MAX_HUD_SCALE,
+        ) {
+            let dims = hud_dimensions(scale);
+            let width = hud_width_for_text_with_scale(&first, scale);
+            let combined = hud_width_for_text_with_scale(&format!("{first}\n{second}"), scale);
+
+            prop_assert!((dims.min_width..=dims.max_width).contains(&width));
+            prop_assert!(combined >= width);
+        }
+    }
+
+    #[test]
+    fn layout_metrics_leave_room_for_header_above_text() {
+        let dims = hud_dimensions(DEFAULT_HUD_SCALE);
+        let icon = (dims.icon_width, dims.icon_height);
+        let without = compute_hud_layout_metrics_with_leading(
+            400.0,
+            dims.line_height_estimate * 2.0,
+            dims.footer_height,
+            icon,
+            DEFAULT_HUD_SCALE,
+        );
+        let with = compute_hud_layout_metrics_with_header(
+            400.0,
+            dims.line_height_estimate * 2.0,
+            (dims.header_height, dims.footer_height),
+            icon,
+            DEFAULT_HUD_SCALE,
+        );
+
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        assert!(close(with.height, without.height + dims.header_height));
+        assert!(close(with.text_height, without.text_height));
+        assert!(with.label_y + with.text_height <= with.header_y + 1e-9);
+        assert!(with.icon_y + dims.icon_height <= with.header_y + 1e-9);
+        assert!(close(
+            with.header_y + dims.header_height,
+            with.height - dims.vertical_padding
+        ));
+    }
+
+    #[test]
+    fn layout_metrics_leave_room_for_thumbnail() {
+        let dims = hud_dimensions(DEFAULT_HUD_SCALE);
+        let thumbnail = (dims.thumbnail_size, dims.thumbnail_size);
+        // 本文が1行でも、サムネイルが収まる高さを呼び出し側で確保する
+        let metrics = compute_hud_layout_metrics_with_leading(
+            400.0,
+            dims.thumbnail_size,
+            dims.footer_height,
+            thumbnail,
+            DEFAULT_HUD_SCALE,
+        );
+        let with_icon = compute_hud_layout_metrics_with_scale(
+            400.0,
+            dims.thumbnail_size,
+            dims.footer_height,
+            DEFAULT_HUD_SCALE,
+        );
+
+        assert_eq!(
+            metrics.text_width,
+            with_icon.text_width - (dims.thumbnail_size - dims.icon_width)
+        );
+        assert!(metrics.icon_y >= dims.vertical_padding);
+        assert!(metrics.icon_y + dims.thumbnail_size <= metrics.height - dims.vertical_padding);
+    }
+
+    #[test]
+    fn theme_padding_scales_with_hud_scale_and_narrows_text() {
+        let roomy = HudSizing {
+            padding: (30.0, 20.0),
+            ..HudSizing::from(2.0)
+        };
+        let dims = hud_dimensions(roomy);
+        assert_eq!(
+            (dims.horizontal_padding, dims.vertical_padding),
+            (60.0, 40.0)
+        );
+
+        let default = compute_hud_layout_metrics_with_scale(800.0, 200.0, 0.0, 2.0);
+        let metrics = compute_hud_layout_metrics_with_scale(800.0, 200.0, 0.0, roomy);
+        assert_eq!(metrics.text_width, default.text_width - (60.0 - 32.0) * 2.0);
+        assert_eq!(metrics.height, default.height + (40.0 - 20.0) * 2.0);
+        assert_eq!(hud_width_for_text_with_scale("a", roomy), dims.min_width);
+    }
+
+    #[test]
+    fn font_size_scales_width_and_line_height_estimates() {
+        let default = hud_dimensions(DEFAULT_HUD_SCALE);
+        let large = HudSizing {
+            font_size: 27.0,
+            ..HudSizing::from(DEFAULT_HUD_SCALE)
+        };
+        let dims = hud_dimensions(large);
+        assert!((dims.char_width_estimate - default.char_width_estimate * 1.5).abs() < 1e-9);
+        assert!((dims.line_height_estimate - default.line_height_estimate * 1.5).abs() < 1e-9);
+        // 余白やアイコンは文字の大きさでは変えない
+        assert_eq!(dims.horizontal_padding, default.horizontal_padding);
+        assert_eq!(dims.icon_width, default.icon_width);
+
+        let text = "0123456789".repeat(3);
+        assert!(
+            hud_width_for_text_with_scale(&text, large)
+                > hud_width_for_text_with_scale(&text, DEFAULT_HUD_SCALE)
+        );
+    }
+
+    #[test]
+    fn hud_width_regression_snapshot() {
+        let cases = vec![
+            ("ascii_short", "hello".to_string()),
+            ("ascii_40", "a".repeat(40)),
+            ("wide_20", "あ".repeat(20)),
+            ("cyrillic_40", "д".repeat(40)),
+            ("emoji_20", "📋".repeat(20)),
+            ("ascii_very_long", "a".repeat(300)),
+        ];
+
+        let snapshot = cases
+            .iter()
+            .map(|(name, text)| format!("{name}: {:.1}", hud_width_for_text(text)))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let expected = "\
+ascii_short: 220.0
+ascii_40: 490.6
+wide_20: 490.6
+cyrillic_40: 490.6
+emoji_20: 490.6
+ascii_very_long: 902.0";
+
+        assert_eq!(snapshot, expected);
+    }
+
+    #[test]
+    fn hud_width_follows_widest_measured_line() {
+        // プロポーショナルフォントを模して、文字ごとに幅が異なる計測器を使う
+        struct ProportionalMeasurer {
+            calls: usize,
+        }
+        impl TextMeasurer for ProportionalMeasurer {
+            fn line_width(&mut self, line: &str) -> f64 {
+                self.calls += 1;
+                line.chars()
+                    .map(|ch| if ch == 'i' { 4.0 } else { 14.0 })
+                    .sum()
+            }
+        }
+
+        let mut measurer = ProportionalMeasurer { calls: 0 };
+        let text = format!("{}\n{}\n\n", "i".repeat(60), "W".repeat(30));
+        let width = hud_width_for_text_with_measurer(&text, &mut measurer, 1.0);
+
+        // 文字数の多い "i" の行ではなく、実測で広い "W" の行が幅を決める
+        assert_eq!(width, 30.0 * 14.0 + 16.0 * 2.0 + 22.0 + 8.0);
+        // 末尾の空行は計測しない
+        assert_eq!(measurer.calls, 2);
+
+        let mut measurer = ProportionalMeasurer { calls: 0 };
+        let clamped = hud_width_for_text_with_measurer(&"W".repeat(100), &mut measurer, 1.0);
+        assert_eq!(clamped, 820.0);
+    }
+
+    #[test]
+    fn hud_layout_regression_snapshot() {
+        let cases = [
+            ("one_line", 600.0, 22.0),
+            ("three_lines", 600.0, 88.0),
+            ("overflow", 600.0, 400.0),
+            ("narrow_clamped", 100.0, 22.0),
+        ];
+
+        let snapshot = cases
+            .iter()
+            .map(|(name, width, measured)| {
+                let metrics = compute_hud_layout_metrics(*width, *measured);
+                format!(
+                    "{name}: w={:.1} text_w={:.1} h={:.1} text_h={:.1} label_y={:.1} icon_y={:.1}",
+                    metrics.width,
+                    metrics.text_width,
+                    metrics.height,
+                    metrics.text_height,
+                    metrics.label_y,
+                    metrics.icon_y
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let expected = "\
+one_line: w=600.0 text_w=531.8 h=57.2 text_h=24.2 label_y=16.5 icon_y=16.5
+three_lines: w=600.0 text_w=531.8 h=110.0 text_h=88.0 label_y=11.0 icon_y=74.8
+overflow: w=600.0 text_w=531.8 h=308.0 text_h=286.0 label_y=11.0 icon_y=272.8
+narrow_clamped: w=220.0 text_w=151.8 h=57.2 text_h=24.2 label_y=16.5 icon_y=16.5";
+
+        assert_eq!(snapshot, expected);
+    }
+
+    #[test]
+    fn hud_layout_with_line_spacing_regression_snapshot() {
+        let cases = [
+            ("one_line", "single", 0.0),
+            ("three_lines", "a\nb\nc", 0.0),
+            ("three_lines_spaced", "a\nb\nc", 6.0),
+            ("overflow_spaced", &"line\n".repeat(12), 6.0),
+        ];
+
+        let snapshot = cases
+            .iter()
+            .map(|(name, text, line_spacing)| {
+                let sizing = HudSizing {
+                    line_spacing: *line_spacing,
+                    ..HudSizing::from(DEFAULT_HUD_SCALE)
+                };
+                let measured = estimated_text_height(text, sizing);
+                let metrics = compute_hud_layout_metrics_with_scale(600.0, measured, 0.0, sizing);
+                format!(
+                    "{name}: measured={measured:.1} h={:.1} text_h={:.1} label_y={:.1} icon_y={:.1}",
+                    metrics.height, metrics.text_height, metrics.label_y, metrics.icon_y
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let expected = "\
+one_line: measured=24.2 h=57.2 text_h=24.2 label_y=16.5 icon_y=16.5
+three_lines: measured=72.6 h=94.6 text_h=72.6 label_y=11.0 icon_y=59.4
+three_lines_spaced: measured=85.8 h=107.8 text_h=85.8 label_y=11.0 icon_y=72.6
+overflow_spaced: measured=363.0 h=308.0 text_h=286.0 label_y=11.0 icon_y=272.8";
+
+        assert_eq!(snapshot, expected);
+    }
+
+    #[test]
+    fn hud_layout_with_footer_regression_snapshot() {
+        let footer_height = HUD_FOOTER_HEIGHT * DEFAULT_HUD_SCALE;
+        let cases = [
+            ("one_line", 600.0, 22.0),
+            ("three_lines", 600.0, 88.0),
+            ("overflow", 600.0, 400.0),
+        ];
+
+        let snapshot = cases
+            .iter()
+            .map(|(name, width, measured)| {
+                let metrics = compute_hud_layout_metrics_with_scale(
+                    *width,
+                    *measured,
+                    footer_height,
+                    DEFAULT_HUD_SCALE,
+                );
+                format!(
+                    "{name}: h={:.1} text_h={:.1} label_y={:.1} icon_y={:.1} footer_y={:.1}",
+                    metrics.height,
+                    metrics.text_height,
+                    metrics.label_y,
+                    metrics.icon_y,
+                    metrics.footer_y
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let expected = "\
+one_line: h=61.6 text_h=24.2 label_y=27.5 icon_y=26.4 footer_y=11.0
+three_lines: h=127.6 text_h=88.0 label_y=28.6 icon_y=92.4 footer_y=11.0
+overflow: h=308.0 text_h=268.4 label_y=28.6 icon_y=272.8 footer_y=11.0";
+
+        assert_eq!(snapshot, expected);
+    }
+
+    #[test]
+    fn hud_origin_for_frame_positions_by_setting() {
+        let frame = NSRect {
+            origin: NSPoint { x: 0.0, y: 0.0 },
+            size: NSSize {
+                width: 1000.0,
+                height: 800.0,
+            },
+        };
+        let hud_width = 600.0;
+        let hud_height = 100.0;
+
+        let (top_x, top_y) = hud_origin_for_frame(frame, hud_width, hud_height, HudPosition::Top);
+        let (center_x, center_y) =
+            hud_origin_for_frame(frame, hud_width, hud_height, HudPosition::Center);
+        let (bottom_x, bottom_y) =
+            hud_origin_for_frame(frame, hud_width, hud_height, HudPosition::Bottom);
+
+        assert_eq!(top_x, 200.0);
+        assert_eq!(center_x, 200.0);
+        assert_eq!(bottom_x, 200.0);
+        assert_eq!(top_y, 525.0);
+        assert_eq!(center_y, 350.0);
+        assert_eq!(bottom_y, 175.0);
+    }
+}
@@ -0,0 +1,39 @@
+#[cfg(target_os = "macos")]
+pub mod config;
+#[cfg(target_os = "macos")]
+pub mod content;
+#[cfg(target_os = "macos")]
+pub mod control;
+#[cfg(target_os = "macos")]
+pub mod encryption;
+pub mod files;
+#[cfg(target_os = "macos")]
+pub mod history;
+#[cfg(target_os = "macos")]
+pub mod history_db;
+#[cfg(target_os = "macos")]
+pub mod history_log;
+#[cfg(target_os = "macos")]
+pub mod hotkey;
+pub mod image_diff;
+#[cfg(target_os = "macos")]
+pub mod layout;
+pub mod onboarding;
+#[cfg(target_os = "macos")]
+pub mod owned;
+#[cfg(target_os = "macos")]
+pub mod pasteboard;
+#[cfg(target_os = "macos")]
+pub mod plugin;
+#[cfg(target_os = "macos")]
+pub mod rendering;
+pub mod schedule;
+#[cfg(target_os = "macos")]
+pub mod snapshot;
+#[cfg(target_os = "macos")]
+pub mod speech;
+#[cfg(target_os = "macos")]
+pub mod sync;
+#[cfg(target_os = "macos")]
+pub mod theme;
+pub mod truncation;
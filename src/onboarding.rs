@@ -0,0 +1,81 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// 設定ファイルを作らないまま使い続ける人にも、案内は一度だけ出す
+const ONBOARDING_MARKER_FILE_NAME: &str = ".onboarded";
+pub const ONBOARDING_HUD_DURATION_SECS: f64 = 6.0;
+pub const ONBOARDING_FOOTER: &str = "shown once · skip with --no-onboarding";
+
+fn onboarding_marker_path(config_path: &Path) -> Option<PathBuf> {
+    Some(config_path.parent()?.join(ONBOARDING_MARKER_FILE_NAME))
+}
+
+/// 初回起動か。設定ファイルも案内済みの目印もなければ `true`。
+pub fn needs_onboarding(config_path: &Path) -> bool {
+    !config_path.exists()
+        && onboarding_marker_path(config_path).is_some_and(|marker| !marker.exists())
+}
+
+/// 案内を出したことを設定ファイルと同じディレクトリに記録する。
+pub fn mark_onboarded(config_path: &Path) -> Result<(), String> {
+    let marker = onboarding_marker_path(config_path).ok_or_else(|| {
+        format!(
+            "failed to determine parent directory for config file {}",
+            config_path.display()
+        )
+    })?;
+    if let Some(parent) = marker.parent() {
+        fs::create_dir_all(parent).map_err(|err| {
+            format!(
+                "failed to create config directory {}: {err}",
+                parent.display()
+            )
+        })?;
+    }
+    fs::write(&marker, "").map_err(|err| format!("failed to write {}: {err}", marker.display()))
+}
+
+/// 初回起動の HUD に出す本文。
+///
+/// 今の機能はクリップボードの読み書きだけで、入力監視や画面収録の許可は要らない。
+pub fn onboarding_text(config_path: &Path) -> String {
+    [
+        "cliip-show is running".to_string(),
+        format!("config: {}", config_path.display()),
+        "customize: cliip-show --config init".to_string(),
+        "permissions: none required".to_string(),
+    ]
+    .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{mark_onboarded, needs_onboarding, onboarding_text};
+
+    #[test]
+    fn needs_onboarding_only_before_config_or_marker_exists() {
+        let dir =
+            std::env::temp_dir().join(format!("cliip-show-onboarding-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let config_path = dir.join("config.toml");
+        assert!(needs_onboarding(&config_path));
+
+        mark_onboarded(&config_path).expect("mark onboarded");
+        assert!(!needs_onboarding(&config_path));
+
+        fs::remove_dir_all(&dir).expect("cleanup");
+        fs::create_dir_all(&dir).expect("create dir");
+        fs::write(&config_path, "").expect("write config");
+        assert!(!needs_onboarding(&config_path));
+        fs::remove_dir_all(&dir).expect("cleanup");
+    }
+
+    #[test]
+    fn onboarding_text_points_at_config_path() {
+        let text = onboarding_text("/tmp/cliip-show/config.toml".as_ref());
+        assert!(text.starts_with("cliip-show is running\n"));
+        assert!(text.contains("config: /tmp/cliip-show/config.toml"));
+    }
+}
@@ -0,0 +1,201 @@
+use std::ffi::c_void;
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use objc2::runtime::{AnyObject, Sel};
+use objc2::{class, msg_send};
+
+// `Owned` と `DispatchTimer` で保持していて、まだ解放していないオブジェクトの数
+static LIVE_OBJECTS: AtomicUsize = AtomicUsize::new(0);
+
+/// このラッパーで保持しているオブジェクトの数。作るか `retain` した数から、解放した数を引いたもの。
+///
+/// `stats --resources` の `objc_objects` に使う。
+pub fn live_objects() -> usize {
+    LIVE_OBJECTS.load(Ordering::Relaxed)
+}
+
+/// `alloc`/`new`/`copy` などで得た所有権付き(+1)のオブジェクト。Drop で `release` する。
+#[derive(Debug)]
+pub struct Owned(NonNull<AnyObject>);
+
+impl Owned {
+    /// null なら `None` を返す。
+    ///
+    /// # Safety
+    ///
+    /// `object` は null か、呼び出し側が所有権を持つ(+1 の)オブジェクトであること。
+    pub unsafe fn from_raw(object: *mut AnyObject) -> Option<Self> {
+        let object = NonNull::new(object)?;
+        LIVE_OBJECTS.fetch_add(1, Ordering::Relaxed);
+        Some(Self(object))
+    }
+
+    /// 自動解放されるオブジェクトなど、所有権を持たない参照を `retain` して保持する。
+    ///
+    /// # Safety
+    ///
+    /// `object` は null か有効なオブジェクトであること。
+    pub unsafe fn retain(object: *mut AnyObject) -> Option<Self> {
+        let object = NonNull::new(object)?;
+        let _: *mut AnyObject = msg_send![object.as_ptr(), retain];
+        LIVE_OBJECTS.fetch_add(1, Ordering::Relaxed);
+        Some(Self(object))
+    }
+
+    pub fn as_ptr(&self) -> *mut AnyObject {
+        self.0.as_ptr()
+    }
+}
+
+impl Drop for Owned {
+    fn drop(&mut self) {
+        unsafe {
+            let () = msg_send![self.0.as_ptr(), release];
+        }
+        LIVE_OBJECTS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// ランループに登録した `NSTimer`。Drop で `invalidate` するので、止め忘れや
+/// 無効化済みタイマーへのダングリング参照が残らない。
+#[derive(Debug)]
+pub struct ScheduledTimer(Owned);
+
+impl ScheduledTimer {
+    /// # Safety
+    ///
+    /// `target` は `selector` を実装していて、タイマーが動いている間は解放されないこと。
+    /// メインスレッドから呼び出すこと。
+    pub unsafe fn schedule(
+        interval_secs: f64,
+        target: &AnyObject,
+        selector: Sel,
+        repeats: bool,
+    ) -> Option<Self> {
+        let timer: *mut AnyObject = msg_send![
+            class!(NSTimer),
+            scheduledTimerWithTimeInterval: interval_secs
+            target: target
+            selector: selector
+            userInfo: std::ptr::null_mut::<AnyObject>()
+            repeats: repeats
+        ];
+        Owned::retain(timer).map(Self)
+    }
+
+    pub fn as_ptr(&self) -> *mut AnyObject {
+        self.0.as_ptr()
+    }
+}
+
+impl Drop for ScheduledTimer {
+    fn drop(&mut self) {
+        unsafe {
+            let () = msg_send![self.0.as_ptr(), invalidate];
+        }
+    }
+}
+
+// dispatch_get_main_queue() はマクロなので、実体のシンボルを直接参照する
+extern "C" {
+    static _dispatch_main_q: c_void;
+    static _dispatch_source_type_timer: c_void;
+
+    fn dispatch_source_create(
+        source_type: *const c_void,
+        handle: usize,
+        mask: usize,
+        queue: *const c_void,
+    ) -> *mut c_void;
+    fn dispatch_source_set_timer(source: *mut c_void, start: u64, interval: u64, leeway: u64);
+    fn dispatch_source_set_event_handler_f(
+        source: *mut c_void,
+        handler: extern "C" fn(*mut c_void),
+    );
+    fn dispatch_source_cancel(source: *mut c_void);
+    fn dispatch_set_context(object: *mut c_void, context: *mut c_void);
+    fn dispatch_resume(object: *mut c_void);
+    fn dispatch_release(object: *mut c_void);
+    fn dispatch_time(when: u64, delta: i64) -> u64;
+}
+
+const DISPATCH_TIME_NOW: u64 = 0;
+const NANOS_PER_SEC: f64 = 1_000_000_000.0;
+
+#[derive(Debug, Clone, Copy)]
+struct DispatchTarget {
+    target: *const AnyObject,
+    selector: Sel,
+}
+
+extern "C" fn fire_dispatch_target(context: *mut c_void) {
+    unsafe {
+        let DispatchTarget { target, selector } = *(context as *const DispatchTarget);
+        let _: *mut AnyObject = msg_send![
+            target,
+            performSelector: selector
+            withObject: std::ptr::null_mut::<AnyObject>()
+        ];
+    }
+}
+
+/// メインキューで動く GCD のタイマーソース。ランループのモードに左右されず、
+/// leeway を付けて登録できる。Drop で cancel して解放する。
+#[derive(Debug)]
+pub struct DispatchTimer {
+    source: NonNull<c_void>,
+    context: *mut DispatchTarget,
+}
+
+impl DispatchTimer {
+    /// `interval_secs` ごとに `target` へ `selector` を送る。
+    ///
+    /// # Safety
+    ///
+    /// `target` は `selector` を実装していて、タイマーが動いている間は解放されないこと。
+    /// メインスレッドから呼び出すこと。
+    pub unsafe fn schedule(
+        interval_secs: f64,
+        leeway_secs: f64,
+        target: &AnyObject,
+        selector: Sel,
+    ) -> Option<Self> {
+        let source = NonNull::new(dispatch_source_create(
+            &_dispatch_source_type_timer,
+            0,
+            0,
+            &_dispatch_main_q,
+        ))?;
+        let context = Box::into_raw(Box::new(DispatchTarget { target, selector }));
+        let interval = (interval_secs * NANOS_PER_SEC) as u64;
+        let leeway = (leeway_secs.max(0.0) * NANOS_PER_SEC) as u64;
+        dispatch_set_context(source.as_ptr(), context.cast());
+        dispatch_source_set_event_handler_f(source.as_ptr(), fire_dispatch_target);
+        dispatch_source_set_timer(
+            source.as_ptr(),
+            dispatch_time(DISPATCH_TIME_NOW, interval as i64),
+            interval,
+            leeway,
+        );
+        dispatch_resume(source.as_ptr());
+        LIVE_OBJECTS.fetch_add(1, Ordering::Relaxed);
+        Some(Self { source, context })
+    }
+
+    pub fn as_ptr(&self) -> *mut AnyObject {
+        self.source.as_ptr().cast()
+    }
+}
+
+impl Drop for DispatchTimer {
+    fn drop(&mut self) {
+        unsafe {
+            // メインキュー上で cancel するので、以降ハンドラが context を読むことはない
+            dispatch_source_cancel(self.source.as_ptr());
+            dispatch_release(self.source.as_ptr());
+            drop(Box::from_raw(self.context));
+        }
+        LIVE_OBJECTS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,644 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::{c_char, c_void, CStr};
+use std::path::PathBuf;
+use std::ptr;
+
+use objc2::runtime::AnyObject;
+use objc2::{class, msg_send};
+use objc2_foundation::NSRange;
+
+const UTF8_ENCODING: usize = 4;
+pub const PLAIN_TEXT_PASTEBOARD_TYPE: &str = "public.utf8-plain-text";
+// cliip-show 自身の書き込みに付ける目印。別プロセスの cliip-show が書いた場合も見分けられる
+pub const SELF_WRITE_PASTEBOARD_TYPE: &str = "io.github.somei-san.cliip-show.self-write";
+// nspasteboard.org の取り決めで、パスワードマネージャーが秘密の値に付ける型
+const CONCEALED_PASTEBOARD_TYPE: &str = "org.nspasteboard.ConcealedType";
+// 自動入力などが一時的に書き込み、すぐに元へ戻す内容に付ける型
+const TRANSIENT_PASTEBOARD_TYPE: &str = "org.nspasteboard.TransientType";
+// Universal Clipboard で別のデバイスから届いた内容に macOS が付ける型
+const REMOTE_CLIPBOARD_PASTEBOARD_TYPE: &str = "com.apple.is-remote-clipboard";
+// Finder のコピーは名前のテキストも載せるので、ファイルの型をテキストより先に見る
+const FILE_URL_PASTEBOARD_TYPE: &str = "public.file-url";
+const LEGACY_FILENAMES_PASTEBOARD_TYPE: &str = "NSFilenamesPboardType";
+// 画像は劣化のない PNG を優先し、なければ macOS が標準で載せる TIFF を読む
+const IMAGE_PASTEBOARD_TYPES: [&str; 2] = ["public.png", "public.tiff"];
+// NSDocumentTypeDocumentOption / NSCharacterEncodingDocumentOption の値
+const DOCUMENT_TYPE_OPTION: &str = "DocumentType";
+const CHARACTER_ENCODING_OPTION: &str = "CharacterEncoding";
+const RICH_TEXT_PASTEBOARD_TYPES: [&str; 4] = [
+    "public.rtf",
+    "public.html",
+    "com.apple.flat-rtfd",
+    "com.apple.webarchive",
+];
+
+/// # Safety
+///
+/// Objective-C ランタイムが利用可能であること。戻り値は呼び出し側で `release` する。
+pub unsafe fn nsstring_from_str(value: &str) -> *mut AnyObject {
+    let ns_string: *mut AnyObject = msg_send![class!(NSString), alloc];
+    msg_send![
+        ns_string,
+        initWithBytes: value.as_ptr() as *const c_void
+        length: value.len()
+        encoding: UTF8_ENCODING
+    ]
+}
+
+thread_local! {
+    // 型名や属性キーなど固定の文字列は、ポーリングのたびに作り直さずスレッドごとに使い回す
+    static CACHED_NSSTRINGS: RefCell<HashMap<&'static str, usize>> = RefCell::new(HashMap::new());
+}
+
+/// 固定の文字列に対応する `NSString` を返す。初回だけ作成し、以降は同じオブジェクトを返す。
+///
+/// # Safety
+///
+/// Objective-C ランタイムが利用可能であること。戻り値はキャッシュが所有するので `release` しない。
+pub unsafe fn cached_nsstring(value: &'static str) -> *mut AnyObject {
+    CACHED_NSSTRINGS.with(|cache| {
+        *cache
+            .borrow_mut()
+            .entry(value)
+            .or_insert_with(|| nsstring_from_str(value) as usize) as *mut AnyObject
+    })
+}
+
+/// # Safety
+///
+/// `pasteboard` は有効な `NSPasteboard` であること。
+pub unsafe fn pasteboard_types(pasteboard: *mut AnyObject) -> Vec<String> {
+    let types: *mut AnyObject = msg_send![pasteboard, types];
+    nsarray_strings(types, |value| value)
+}
+
+/// 1回の `types` 読み出しの結果から、内容を取り出す表現を選ぶ。テキストがなければ `None`。
+pub fn preferred_text_type(types: &[String]) -> Option<&'static str> {
+    types
+        .iter()
+        .any(|value| value == PLAIN_TEXT_PASTEBOARD_TYPE)
+        .then_some(PLAIN_TEXT_PASTEBOARD_TYPE)
+}
+
+/// テキストのない画像のクリップで、内容を取り出す表現を選ぶ。画像がなければ `None`。
+pub fn preferred_image_type(types: &[String]) -> Option<&'static str> {
+    IMAGE_PASTEBOARD_TYPES
+        .into_iter()
+        .find(|image_type| types.iter().any(|value| value == image_type))
+}
+
+/// テキスト・画像・ファイルのどれでもないクリップで、種類として見せる表現を選ぶ。
+/// 自動生成の `dyn.` 型より、`com.adobe.pdf` のような UTI を優先する。
+pub fn preferred_binary_type(types: &[String]) -> Option<&str> {
+    types
+        .iter()
+        .find(|value| value.contains('.') && !value.starts_with("dyn."))
+        .or_else(|| types.first())
+        .map(String::as_str)
+}
+
+/// `type_name` の表現のバイト数。取り出せなければ `None`。
+///
+/// # Safety
+///
+/// `pasteboard` は有効な `NSPasteboard` であること。
+pub unsafe fn pasteboard_data_len(pasteboard: *mut AnyObject, type_name: &str) -> Option<usize> {
+    let type_name = nsstring_from_str(type_name);
+    let data: *mut AnyObject = msg_send![pasteboard, dataForType: type_name];
+    let () = msg_send![type_name, release];
+    if data.is_null() {
+        return None;
+    }
+    Some(msg_send![data, length])
+}
+
+/// `types` を `text`・`html`・`rtf` のような短い名前にする。古い形式の型や目印の型は省き、
+/// 同じ名前は最初の1つだけ残す。
+pub fn type_badges(types: &[String]) -> Vec<String> {
+    let mut badges: Vec<String> = Vec::new();
+    for value in types {
+        let Some(badge) = type_badge(value) else {
+            continue;
+        };
+        if !badges.iter().any(|existing| existing == badge) {
+            badges.push(badge.to_string());
+        }
+    }
+    badges
+}
+
+fn type_badge(value: &str) -> Option<&str> {
+    let badge = match value {
+        PLAIN_TEXT_PASTEBOARD_TYPE | "public.plain-text" | "public.utf16-plain-text" => "text",
+        "com.apple.flat-rtfd" => "rtfd",
+        FILE_URL_PASTEBOARD_TYPE => "file",
+        // NSStringPboardType のような古い型や、nspasteboard.org・cliip-show の目印は種類ではない
+        _ if !value.contains('.')
+            || value.starts_with("dyn.")
+            || value.starts_with("org.nspasteboard.")
+            || value == SELF_WRITE_PASTEBOARD_TYPE =>
+        {
+            return None
+        }
+        _ => value.rsplit('.').next()?,
+    };
+    Some(badge)
+}
+
+pub fn has_file_type(types: &[String]) -> bool {
+    types
+        .iter()
+        .any(|value| value == FILE_URL_PASTEBOARD_TYPE || value == LEGACY_FILENAMES_PASTEBOARD_TYPE)
+}
+
+/// コピーされたファイルのパス。ファイル URL を優先し、なければ古い `NSFilenamesPboardType` を読む。
+///
+/// # Safety
+///
+/// `pasteboard` は有効な `NSPasteboard` であること。
+pub unsafe fn pasteboard_file_paths(pasteboard: *mut AnyObject) -> Vec<PathBuf> {
+    let url_class = class!(NSURL) as *const _ as *mut AnyObject;
+    let classes: *mut AnyObject = msg_send![class!(NSArray), arrayWithObject: url_class];
+    let file_urls_only: *mut AnyObject = msg_send![class!(NSNumber), numberWithBool: true];
+    let options: *mut AnyObject = msg_send![
+        class!(NSDictionary),
+        dictionaryWithObject: file_urls_only
+        forKey: cached_nsstring("NSPasteboardURLReadingFileURLsOnlyKey")
+    ];
+    let urls: *mut AnyObject =
+        msg_send![pasteboard, readObjectsForClasses: classes options: options];
+    let paths = nsarray_strings(urls, |url| msg_send![url, path]);
+    if !paths.is_empty() {
+        return paths.into_iter().map(PathBuf::from).collect();
+    }
+
+    let filenames: *mut AnyObject = msg_send![
+        pasteboard,
+        propertyListForType: cached_nsstring(LEGACY_FILENAMES_PASTEBOARD_TYPE)
+    ];
+    nsarray_strings(filenames, |name| name)
+        .into_iter()
+        .map(PathBuf::from)
+        .collect()
+}
+
+// `array` の各要素を `to_nsstring` で NSString にして読む。null や NSArray 以外なら空
+unsafe fn nsarray_strings(
+    array: *mut AnyObject,
+    to_nsstring: impl Fn(*mut AnyObject) -> *mut AnyObject,
+) -> Vec<String> {
+    if array.is_null() {
+        return Vec::new();
+    }
+    let is_array: bool = msg_send![array, isKindOfClass: class!(NSArray)];
+    if !is_array {
+        return Vec::new();
+    }
+    let count: usize = msg_send![array, count];
+    (0..count)
+        .filter_map(|index| {
+            let value: *mut AnyObject = msg_send![array, objectAtIndex: index];
+            nsstring_to_string(to_nsstring(value))
+        })
+        .collect()
+}
+
+/// プレーンテキストのないコピーから本文を取り出せる書式。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RichTextFormat {
+    Rtf,
+    Rtfd,
+    Html,
+}
+
+impl RichTextFormat {
+    // WebKit を使う HTML より、軽い RTF の変換を優先する
+    const PREFERRED_ORDER: [Self; 3] = [Self::Rtf, Self::Rtfd, Self::Html];
+
+    pub fn pasteboard_type(self) -> &'static str {
+        match self {
+            Self::Rtf => "public.rtf",
+            Self::Rtfd => "com.apple.flat-rtfd",
+            Self::Html => "public.html",
+        }
+    }
+
+    /// HUD のアイコンの下に出すバッジ。
+    pub fn badge(self) -> &'static str {
+        match self {
+            Self::Rtf => "RTF",
+            Self::Rtfd => "RTFD",
+            Self::Html => "HTML",
+        }
+    }
+
+    // NSRTFTextDocumentType などの値
+    fn document_type(self) -> &'static str {
+        match self {
+            Self::Rtf => "NSRTF",
+            Self::Rtfd => "NSRTFD",
+            Self::Html => "NSHTML",
+        }
+    }
+}
+
+/// プレーンテキストがないときに、本文を取り出す書式を選ぶ。
+pub fn preferred_rich_text_format(types: &[String]) -> Option<RichTextFormat> {
+    RichTextFormat::PREFERRED_ORDER
+        .into_iter()
+        .find(|format| types.iter().any(|value| value == format.pasteboard_type()))
+}
+
+/// `format` の内容を `NSAttributedString` で読み、書式を除いた本文を
+/// [`nsstring_to_string_capped`] と同じく先頭 `max_bytes` バイトまで返す。
+///
+/// # Safety
+///
+/// `pasteboard` は有効な `NSPasteboard` で、メインスレッドから呼び出すこと。
+pub unsafe fn rich_text_to_plain_capped(
+    pasteboard: *mut AnyObject,
+    format: RichTextFormat,
+    max_bytes: usize,
+) -> Option<(String, bool)> {
+    let data: *mut AnyObject = msg_send![
+        pasteboard,
+        dataForType: cached_nsstring(format.pasteboard_type())
+    ];
+    if data.is_null() {
+        return None;
+    }
+    let options: *mut AnyObject = msg_send![class!(NSMutableDictionary), dictionary];
+    let () = msg_send![
+        options,
+        setObject: cached_nsstring(format.document_type())
+        forKey: cached_nsstring(DOCUMENT_TYPE_OPTION)
+    ];
+    // meta タグで文字コードを指定していない HTML も UTF-8 として読む
+    let encoding: *mut AnyObject =
+        msg_send![class!(NSNumber), numberWithUnsignedInteger: UTF8_ENCODING];
+    let () = msg_send![
+        options,
+        setObject: encoding
+        forKey: cached_nsstring(CHARACTER_ENCODING_OPTION)
+    ];
+    let attributed: *mut AnyObject = msg_send![class!(NSAttributedString), alloc];
+    let attributed: *mut AnyObject = msg_send![
+        attributed,
+        initWithData: data
+        options: options
+        documentAttributes: ptr::null_mut::<*mut AnyObject>()
+        error: ptr::null_mut::<*mut AnyObject>()
+    ];
+    if attributed.is_null() {
+        return None;
+    }
+    let string: *mut AnyObject = msg_send![attributed, string];
+    let text = nsstring_to_string_capped(string, max_bytes);
+    let () = msg_send![attributed, release];
+    text
+}
+
+/// 複数の `NSPasteboardItem` があるとき、プレーンテキストを持つアイテムの本文を順に返す。
+/// アイテムが1つ以下なら空。
+///
+/// 合計で `max_bytes` バイトを超えたら残りのアイテムは読まず、切り詰めたかどうかを合わせて返す。
+///
+/// # Safety
+///
+/// `pasteboard` は有効な `NSPasteboard` であること。
+pub unsafe fn pasteboard_item_texts(
+    pasteboard: *mut AnyObject,
+    max_bytes: usize,
+) -> (Vec<String>, bool) {
+    let items: *mut AnyObject = msg_send![pasteboard, pasteboardItems];
+    if items.is_null() {
+        return (Vec::new(), false);
+    }
+    let count: usize = msg_send![items, count];
+    if count <= 1 {
+        return (Vec::new(), false);
+    }
+    let text_type = cached_nsstring(PLAIN_TEXT_PASTEBOARD_TYPE);
+    let mut texts = Vec::new();
+    let mut remaining = max_bytes;
+    for index in 0..count {
+        if remaining == 0 {
+            return (texts, true);
+        }
+        let item: *mut AnyObject = msg_send![items, objectAtIndex: index];
+        let value: *mut AnyObject = msg_send![item, stringForType: text_type];
+        let Some((text, cut)) = nsstring_to_string_capped(value, remaining) else {
+            continue;
+        };
+        remaining -= text.len();
+        texts.push(text);
+        if cut {
+            return (texts, true);
+        }
+    }
+    (texts, false)
+}
+
+pub fn has_rich_text_type(types: &[String]) -> bool {
+    types
+        .iter()
+        .any(|value| RICH_TEXT_PASTEBOARD_TYPES.contains(&value.as_str()))
+}
+
+/// cliip-show 自身が書き込んだ内容か。HUD を出し直さないよう `poll_pasteboard` で読み飛ばす。
+pub fn is_self_write(types: &[String]) -> bool {
+    types
+        .iter()
+        .any(|value| value == SELF_WRITE_PASTEBOARD_TYPE)
+}
+
+/// パスワードなど、画面に出すべきでないと示されたコピーか。
+pub fn is_concealed(types: &[String]) -> bool {
+    types.iter().any(|value| value == CONCEALED_PASTEBOARD_TYPE)
+}
+
+/// 他のツールによる一時的な書き込みか。
+pub fn is_transient(types: &[String]) -> bool {
+    types.iter().any(|value| value == TRANSIENT_PASTEBOARD_TYPE)
+}
+
+/// iPhone など別のデバイスから Universal Clipboard（Handoff）で届いたコピーか。
+pub fn is_remote_clipboard(types: &[String]) -> bool {
+    types
+        .iter()
+        .any(|value| value == REMOTE_CLIPBOARD_PASTEBOARD_TYPE)
+}
+
+/// 内容をプレーンテキストだけに置き換える。目印は付けないので、常駐中の cliip-show には
+/// 通常のコピーとして届き、HUD が出る。
+///
+/// # Safety
+///
+/// `pasteboard` は有効な `NSPasteboard` で、メインスレッドから呼び出すこと。
+pub unsafe fn set_plain_text(pasteboard: *mut AnyObject, text: &str) -> bool {
+    let _: isize = msg_send![pasteboard, clearContents];
+    let plain = nsstring_from_str(text);
+    let written: bool = msg_send![
+        pasteboard,
+        setString: plain
+        forType: cached_nsstring(PLAIN_TEXT_PASTEBOARD_TYPE)
+    ];
+    let () = msg_send![plain, release];
+    written
+}
+
+/// 内容をプレーンテキストだけに置き換え、[`SELF_WRITE_PASTEBOARD_TYPE`] の目印を付ける。
+///
+/// # Safety
+///
+/// `pasteboard` は有効な `NSPasteboard` で、メインスレッドから呼び出すこと。
+pub unsafe fn write_plain_text(pasteboard: *mut AnyObject, text: &str) -> bool {
+    let written = set_plain_text(pasteboard, text);
+    let marker = cached_nsstring("");
+    let _: bool = msg_send![
+        pasteboard,
+        setString: marker
+        forType: cached_nsstring(SELF_WRITE_PASTEBOARD_TYPE)
+    ];
+    written
+}
+
+/// `NSData` の中身をコピーする。null なら空。
+///
+/// # Safety
+///
+/// `data` は null か有効な `NSData` であること。
+pub unsafe fn nsdata_to_vec(data: *mut AnyObject) -> Vec<u8> {
+    if data.is_null() {
+        return Vec::new();
+    }
+    let length: usize = msg_send![data, length];
+    let bytes: *const c_void = msg_send![data, bytes];
+    if bytes.is_null() || length == 0 {
+        return Vec::new();
+    }
+    std::slice::from_raw_parts(bytes as *const u8, length).to_vec()
+}
+
+/// # Safety
+///
+/// `value` は null か有効な `NSString` であること。
+pub unsafe fn nsstring_to_string(value: *mut AnyObject) -> Option<String> {
+    if value.is_null() {
+        return None;
+    }
+
+    let utf8_ptr: *const c_char = msg_send![value, UTF8String];
+    if utf8_ptr.is_null() {
+        return Some(String::new());
+    }
+
+    Some(CStr::from_ptr(utf8_ptr).to_string_lossy().into_owned())
+}
+
+/// `value` の先頭 `max_bytes` バイト分だけを変換し、切り詰めたかどうかを合わせて返す。
+///
+/// 巨大なコピーでメインスレッドが止まらないよう、`length` を見て必要な分の部分文字列だけを
+/// UTF-8 に変換する。
+///
+/// # Safety
+///
+/// `value` は null か有効な `NSString` であること。
+pub unsafe fn nsstring_to_string_capped(
+    value: *mut AnyObject,
+    max_bytes: usize,
+) -> Option<(String, bool)> {
+    if value.is_null() {
+        return None;
+    }
+
+    // UTF-16 の1単位は UTF-8 で1〜3バイトになる
+    let length: usize = msg_send![value, length];
+    if length.saturating_mul(3) <= max_bytes {
+        return nsstring_to_string(value).map(|text| (text, false));
+    }
+    if length <= max_bytes {
+        let mut text = nsstring_to_string(value)?;
+        let cut = cap_utf8_len(&mut text, max_bytes);
+        return Some((text, cut));
+    }
+
+    // サロゲートペアや結合文字の途中で切らないよう、文字の境界まで戻す
+    let boundary: NSRange = msg_send![value, rangeOfComposedCharacterSequenceAtIndex: max_bytes];
+    let head: *mut AnyObject = msg_send![value, substringToIndex: boundary.location];
+    let mut text = nsstring_to_string(head)?;
+    cap_utf8_len(&mut text, max_bytes);
+    Some((text, true))
+}
+
+fn cap_utf8_len(text: &mut String, max_bytes: usize) -> bool {
+    if text.len() <= max_bytes {
+        return false;
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    text.truncate(end);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        cap_utf8_len, has_file_type, has_rich_text_type, is_concealed, is_remote_clipboard,
+        is_self_write, is_transient, preferred_binary_type, preferred_image_type,
+        preferred_rich_text_format, preferred_text_type, type_badges, RichTextFormat,
+        SELF_WRITE_PASTEBOARD_TYPE,
+    };
+
+    fn types(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn preferred_text_type_requires_plain_text() {
+        assert_eq!(
+            preferred_text_type(&types(&["public.rtf", "public.utf8-plain-text"])),
+            Some("public.utf8-plain-text")
+        );
+        assert_eq!(preferred_text_type(&types(&["public.png"])), None);
+        assert_eq!(preferred_text_type(&[]), None);
+    }
+
+    #[test]
+    fn preferred_image_type_prefers_png_over_tiff() {
+        assert_eq!(
+            preferred_image_type(&types(&["public.tiff", "public.png"])),
+            Some("public.png")
+        );
+        assert_eq!(
+            preferred_image_type(&types(&["public.tiff"])),
+            Some("public.tiff")
+        );
+        assert_eq!(
+            preferred_image_type(&types(&["public.utf8-plain-text"])),
+            None
+        );
+    }
+
+    #[test]
+    fn has_file_type_detects_file_urls_and_legacy_filenames() {
+        assert!(has_file_type(&types(&[
+            "public.file-url",
+            "public.utf8-plain-text"
+        ])));
+        assert!(has_file_type(&types(&["NSFilenamesPboardType"])));
+        assert!(!has_file_type(&types(&["public.url"])));
+    }
+
+    #[test]
+    fn preferred_rich_text_format_prefers_rtf_over_html() {
+        assert_eq!(
+            preferred_rich_text_format(&types(&["public.html", "public.rtf"])),
+            Some(RichTextFormat::Rtf)
+        );
+        assert_eq!(
+            preferred_rich_text_format(&types(&["public.html"])),
+            Some(RichTextFormat::Html)
+        );
+        assert_eq!(
+            preferred_rich_text_format(&types(&["com.apple.webarchive"])),
+            None
+        );
+    }
+
+    #[test]
+    fn cap_utf8_len_cuts_on_char_boundary() {
+        let mut text = "abcあいう".to_string();
+        assert!(cap_utf8_len(&mut text, 7));
+        assert_eq!(text, "abcあ");
+
+        let mut text = "abcあいう".to_string();
+        assert!(!cap_utf8_len(&mut text, 12));
+        assert_eq!(text, "abcあいう");
+    }
+
+    #[test]
+    fn has_rich_text_type_detects_rtf_and_html() {
+        assert!(has_rich_text_type(&types(&[
+            "public.rtf",
+            "public.utf8-plain-text"
+        ])));
+        assert!(has_rich_text_type(&types(&["public.html"])));
+        assert!(!has_rich_text_type(&types(&["public.utf8-plain-text"])));
+        assert!(!has_rich_text_type(&[]));
+    }
+
+    #[test]
+    fn preferred_binary_type_prefers_uti_over_dynamic_types() {
+        assert_eq!(
+            preferred_binary_type(&types(&[
+                "dyn.ah62d4rv4gu8y",
+                "Apple PDF pasteboard type",
+                "com.adobe.pdf"
+            ])),
+            Some("com.adobe.pdf")
+        );
+        assert_eq!(
+            preferred_binary_type(&types(&["dyn.ah62d4rv4gu8y"])),
+            Some("dyn.ah62d4rv4gu8y")
+        );
+        assert_eq!(preferred_binary_type(&[]), None);
+    }
+
+    #[test]
+    fn is_concealed_detects_password_manager_type() {
+        assert!(is_concealed(&types(&[
+            "public.utf8-plain-text",
+            "org.nspasteboard.ConcealedType"
+        ])));
+        assert!(!is_concealed(&types(&["public.utf8-plain-text"])));
+    }
+
+    #[test]
+    fn is_transient_detects_temporary_writes() {
+        assert!(is_transient(&types(&[
+            "public.utf8-plain-text",
+            "org.nspasteboard.TransientType"
+        ])));
+        assert!(!is_transient(&types(&["org.nspasteboard.ConcealedType"])));
+    }
+
+    #[test]
+    fn is_remote_clipboard_detects_handoff_type() {
+        assert!(is_remote_clipboard(&types(&[
+            "public.utf8-plain-text",
+            "com.apple.is-remote-clipboard"
+        ])));
+        assert!(!is_remote_clipboard(&types(&["public.utf8-plain-text"])));
+    }
+
+    #[test]
+    fn type_badges_shortens_and_dedupes_types() {
+        assert_eq!(
+            type_badges(&types(&[
+                "public.utf8-plain-text",
+                "NSStringPboardType",
+                "public.html",
+                "public.rtf",
+                "public.utf16-plain-text",
+                "dyn.ah62d4rv4gu8y",
+                "org.nspasteboard.TransientType",
+                "com.adobe.pdf",
+            ])),
+            vec!["text", "html", "rtf", "pdf"]
+        );
+        assert!(type_badges(&[]).is_empty());
+    }
+
+    #[test]
+    fn is_self_write_detects_marker_type() {
+        assert!(is_self_write(&types(&[
+            "public.utf8-plain-text",
+            SELF_WRITE_PASTEBOARD_TYPE
+        ])));
+        assert!(!is_self_write(&types(&["public.utf8-plain-text"])));
+    }
+}
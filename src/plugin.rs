@@ -0,0 +1,430 @@
+use std::fs;
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::config_file_path;
+
+const PLUGIN_TIMEOUT: Duration = Duration::from_millis(500);
+const PLUGIN_WAIT_TICK: Duration = Duration::from_millis(5);
+const PLUGINS_DIR_NAME: &str = "plugins";
+const SIGKILL: i32 = 9;
+
+extern "C" {
+    fn kill(pid: i32, signal: i32) -> i32;
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransformStyle {
+    pub badge: Option<String>,
+    pub footer: Option<String>,
+}
+
+pub trait ContentTransformer: Send {
+    fn name(&self) -> &str;
+
+    fn classify(&self, text: &str) -> bool;
+
+    fn transform(&self, text: &str) -> Option<String>;
+
+    fn style(&self, _text: &str) -> TransformStyle {
+        TransformStyle::default()
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct PluginRequest<'a> {
+    text: &'a str,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+struct PluginResponse {
+    matched: bool,
+    text: Option<String>,
+    badge: Option<String>,
+    footer: Option<String>,
+}
+
+fn parse_plugin_response(raw: &[u8]) -> Option<PluginResponse> {
+    let response: PluginResponse = serde_json::from_slice(raw).ok()?;
+    response.matched.then_some(response)
+}
+
+// 1つのクリップに対して classify/transform/style が続けて呼ばれるので、直近の応答を使い回す
+#[derive(Default)]
+struct ResponseCache {
+    last: Mutex<Option<(String, Option<PluginResponse>)>>,
+}
+
+impl ResponseCache {
+    fn get_or_run(
+        &self,
+        text: &str,
+        run: impl FnOnce() -> Option<PluginResponse>,
+    ) -> Option<PluginResponse> {
+        let mut last = self.last.lock().expect("plugin cache lock poisoned");
+        if let Some((cached_text, response)) = last.as_ref() {
+            if cached_text == text {
+                return response.clone();
+            }
+        }
+        let response = run();
+        *last = Some((text.to_string(), response.clone()));
+        response
+    }
+}
+
+pub struct ScriptTransformer {
+    name: String,
+    path: PathBuf,
+    cache: ResponseCache,
+}
+
+impl ScriptTransformer {
+    pub fn new(path: PathBuf) -> Self {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Self {
+            name,
+            path,
+            cache: ResponseCache::default(),
+        }
+    }
+
+    fn response(&self, text: &str) -> Option<PluginResponse> {
+        self.cache
+            .get_or_run(text, || run_script_plugin(&self.path, text))
+    }
+}
+
+impl ContentTransformer for ScriptTransformer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn classify(&self, text: &str) -> bool {
+        self.response(text).is_some()
+    }
+
+    fn transform(&self, text: &str) -> Option<String> {
+        self.response(text)?.text
+    }
+
+    fn style(&self, text: &str) -> TransformStyle {
+        self.response(text)
+            .map(|response| TransformStyle {
+                badge: response.badge,
+                footer: response.footer,
+            })
+            .unwrap_or_default()
+    }
+}
+
+fn run_script_plugin(path: &Path, text: &str) -> Option<PluginResponse> {
+    let request = serde_json::to_vec(&PluginRequest { text }).ok()?;
+    let mut command = Command::new(path);
+    command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        // スクリプトが起動したプロセスもまとめて終了できるよう、別のプロセスグループにする
+        .process_group(0);
+    let started = Instant::now();
+    let mut child = loop {
+        match command.spawn() {
+            Ok(child) => break child,
+            // 書いた直後のスクリプトは、書き込み用のファイルを引き継いだほかのスレッドの子プロセスが
+            // exec するまで実行できない（ETXTBSY）。少し待って試し直す
+            Err(err)
+                if err.kind() == ErrorKind::ExecutableFileBusy
+                    && started.elapsed() < PLUGIN_TIMEOUT =>
+            {
+                thread::sleep(PLUGIN_WAIT_TICK)
+            }
+            Err(_) => return None,
+        }
+    };
+
+    // 大きな入出力でパイプが詰まらないよう、読み書きは別スレッドで行う
+    let mut stdin = child.stdin.take()?;
+    let writer = thread::spawn(move || {
+        let _ = stdin.write_all(&request);
+    });
+    let mut stdout = child.stdout.take()?;
+    let reader = thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = stdout.read_to_end(&mut output);
+        output
+    });
+
+    let started = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break Some(status),
+            Ok(None) if started.elapsed() < PLUGIN_TIMEOUT => thread::sleep(PLUGIN_WAIT_TICK),
+            _ => {
+                // 子プロセスが標準出力を持ったまま残ると読み取りのスレッドが終わらないので、
+                // プロセスグループごと終了させる
+                if let Ok(pid) = i32::try_from(child.id()) {
+                    unsafe { kill(-pid, SIGKILL) };
+                }
+                let _ = child.kill();
+                let _ = child.wait();
+                break None;
+            }
+        }
+    };
+    // 終了させたあとはパイプが閉じるので、読み書きのスレッドも終わるのを待ってから返す
+    let _ = writer.join();
+    let output = reader.join().ok()?;
+    if !status?.success() {
+        return None;
+    }
+    parse_plugin_response(&output)
+}
+
+#[cfg(feature = "dylib-plugins")]
+pub struct DylibTransformer {
+    name: String,
+    library: libloading::Library,
+    cache: ResponseCache,
+}
+
+#[cfg(feature = "dylib-plugins")]
+impl DylibTransformer {
+    /// # Safety
+    ///
+    /// `path` は `cliip_show_plugin_transform` と `cliip_show_plugin_free` を
+    /// C ABI で公開している信頼できるライブラリであること。
+    pub unsafe fn load(path: &Path) -> Result<Self, String> {
+        let library = libloading::Library::new(path)
+            .map_err(|error| format!("failed to load plugin {}: {error}", path.display()))?;
+        for symbol in [
+            &b"cliip_show_plugin_transform\0"[..],
+            &b"cliip_show_plugin_free\0"[..],
+        ] {
+            library
+                .get::<*const ()>(symbol)
+                .map_err(|error| format!("invalid plugin {}: {error}", path.display()))?;
+        }
+        let name = path
+            .file_stem()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Ok(Self {
+            name,
+            library,
+            cache: ResponseCache::default(),
+        })
+    }
+
+    fn response(&self, text: &str) -> Option<PluginResponse> {
+        use std::ffi::{c_char, CStr, CString};
+
+        type TransformFn = unsafe extern "C" fn(*const c_char) -> *mut c_char;
+        type FreeFn = unsafe extern "C" fn(*mut c_char);
+
+        self.cache.get_or_run(text, || unsafe {
+            let request = serde_json::to_string(&PluginRequest { text }).ok()?;
+            let request = CString::new(request).ok()?;
+            let transform = self
+                .library
+                .get::<TransformFn>(b"cliip_show_plugin_transform\0")
+                .ok()?;
+            let free = self
+                .library
+                .get::<FreeFn>(b"cliip_show_plugin_free\0")
+                .ok()?;
+            let output = transform(request.as_ptr());
+            if output.is_null() {
+                return None;
+            }
+            let response = parse_plugin_response(CStr::from_ptr(output).to_bytes());
+            free(output);
+            response
+        })
+    }
+}
+
+#[cfg(feature = "dylib-plugins")]
+impl ContentTransformer for DylibTransformer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn classify(&self, text: &str) -> bool {
+        self.response(text).is_some()
+    }
+
+    fn transform(&self, text: &str) -> Option<String> {
+        self.response(text)?.text
+    }
+
+    fn style(&self, text: &str) -> TransformStyle {
+        self.response(text)
+            .map(|response| TransformStyle {
+                badge: response.badge,
+                footer: response.footer,
+            })
+            .unwrap_or_default()
+    }
+}
+
+pub fn plugins_dir() -> Result<PathBuf, String> {
+    if let Ok(path) = std::env::var("CLIIP_SHOW_PLUGINS_DIR") {
+        let trimmed = path.trim();
+        if !trimmed.is_empty() {
+            return Ok(PathBuf::from(trimmed));
+        }
+    }
+    let config_path = config_file_path()?;
+    let parent = config_path
+        .parent()
+        .ok_or_else(|| format!("invalid config path: {}", config_path.display()))?;
+    Ok(parent.join(PLUGINS_DIR_NAME))
+}
+
+pub fn load_plugins(dir: &Path) -> Vec<Box<dyn ContentTransformer>> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file())
+        .collect();
+    paths.sort();
+
+    let mut plugins: Vec<Box<dyn ContentTransformer>> = Vec::new();
+    for path in paths {
+        if path.extension().is_some_and(|ext| ext == "dylib") {
+            #[cfg(feature = "dylib-plugins")]
+            match unsafe { DylibTransformer::load(&path) } {
+                Ok(plugin) => plugins.push(Box::new(plugin)),
+                Err(error) => eprintln!("warning: {error}"),
+            }
+            continue;
+        }
+        if is_executable(&path) {
+            plugins.push(Box::new(ScriptTransformer::new(path)));
+        }
+    }
+    plugins
+}
+
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::metadata(path).is_ok_and(|metadata| metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        load_plugins, parse_plugin_response, ContentTransformer, PluginResponse, ScriptTransformer,
+        TransformStyle,
+    };
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, Instant};
+
+    fn write_script(dir: &Path, name: &str, body: &str) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, body).expect("write plugin script");
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).expect("chmod");
+        path
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cliip-show-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("create temp dir");
+        dir
+    }
+
+    #[test]
+    fn parse_plugin_response_requires_matched() {
+        assert_eq!(
+            parse_plugin_response(br#"{"matched":true,"text":"JIRA-1","badge":"JIRA"}"#),
+            Some(PluginResponse {
+                matched: true,
+                text: Some("JIRA-1".to_string()),
+                badge: Some("JIRA".to_string()),
+                footer: None,
+            })
+        );
+        assert_eq!(parse_plugin_response(br#"{"matched":false}"#), None);
+        assert_eq!(parse_plugin_response(b"not json"), None);
+    }
+
+    #[test]
+    fn script_transformer_exchanges_json_over_stdio() {
+        let dir = temp_dir("script-plugin");
+        let path = write_script(
+            &dir,
+            "ticket",
+            "#!/bin/sh\n\
+             if grep -q 'ABC-[0-9]'; then\n\
+             echo '{\"matched\":true,\"text\":\"https://tracker.example.com/ABC-1\",\"footer\":\"ticket\"}'\n\
+             else\n\
+             echo '{\"matched\":false}'\n\
+             fi\n",
+        );
+        let plugin = ScriptTransformer::new(path);
+
+        assert_eq!(plugin.name(), "ticket");
+        assert!(plugin.classify("see ABC-1"));
+        assert_eq!(
+            plugin.transform("see ABC-1").as_deref(),
+            Some("https://tracker.example.com/ABC-1")
+        );
+        assert_eq!(
+            plugin.style("see ABC-1"),
+            TransformStyle {
+                badge: None,
+                footer: Some("ticket".to_string()),
+            }
+        );
+        assert!(!plugin.classify("nothing here"));
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn script_transformer_kills_plugins_that_time_out() {
+        let dir = temp_dir("slow-plugin");
+        // `sleep` は sh の子プロセスとして標準出力を持ったまま残る
+        let path = write_script(
+            &dir,
+            "slow",
+            "#!/bin/sh\nsleep 5\necho '{\"matched\":true}'\n",
+        );
+        let plugin = ScriptTransformer::new(path);
+
+        let started = Instant::now();
+        assert!(!plugin.classify("anything"));
+        assert!(started.elapsed() < Duration::from_secs(3));
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn load_plugins_skips_non_executable_files() {
+        let dir = temp_dir("load-plugins");
+        write_script(&dir, "b-plugin", "#!/bin/sh\necho '{}'\n");
+        write_script(&dir, "a-plugin", "#!/bin/sh\necho '{}'\n");
+        fs::write(dir.join("README.txt"), "notes").expect("write readme");
+
+        let plugins = load_plugins(&dir);
+        let names: Vec<&str> = plugins.iter().map(|plugin| plugin.name()).collect();
+        assert_eq!(names, vec!["a-plugin", "b-plugin"]);
+        let _ = fs::remove_dir_all(dir);
+    }
+}
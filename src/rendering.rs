@@ -0,0 +1,2258 @@
+use std::cell::{Cell, RefCell};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::path::Path;
+use std::ptr;
+
+use objc2::runtime::AnyObject;
+use objc2::{class, msg_send, sel};
+use objc2_foundation::{NSPoint, NSRange, NSRect, NSSize};
+use serde_json::json;
+
+use crate::config::{
+    apply_appearance, is_sf_symbol_name, parse_f64_value, DisplaySettings, HudAppearance,
+    HudBackgroundColor, HudMaterial, HudPosition, HudTextColor, DEFAULT_HUD_FONT_NAME,
+    DEFAULT_HUD_FONT_SIZE, DEFAULT_HUD_ICON, DEFAULT_HUD_SCALE, MAX_HUD_SCALE, MIN_HUD_SCALE,
+};
+use crate::content::{
+    build_hud_content, ClipContext, ClipImage, ColorSwatch, DiffKind, DiffSpan, HudContent,
+    MarkdownSpan, MarkdownStyle, SourceApp,
+};
+use crate::image_diff::{
+    compose_side_by_side, diff_rgba_image_with_params, fit_rgba, DiffOptions, DiffParams,
+    DiffSummary, RGBA_CHANNELS,
+};
+use crate::layout::{
+    compute_hud_layout_metrics_with_header, estimated_text_height, hud_dimensions,
+    hud_origin_for_frame, hud_width_for_text_with_measurer, HudDimensions, HudLayoutMetrics,
+    TextMeasurer,
+};
+use crate::owned::Owned;
+use crate::pasteboard::{cached_nsstring, nsdata_to_vec, nsstring_from_str, nsstring_to_string};
+
+const BORDERLESS_MASK: usize = 0;
+const BACKING_BUFFERED: isize = 2;
+const FLOATING_WINDOW_LEVEL: isize = 3;
+const HUD_TEXT_MEASURE_HEIGHT: f64 = 10_000.0;
+// NSTextFieldCell が左右に確保する余白。実測幅に足さないと最長行が折り返される
+const HUD_TEXT_CELL_HORIZONTAL_INSET: f64 = 2.0;
+const LINE_WIDTH_CACHE_CAPACITY: usize = 512;
+const HUD_CORNER_RADIUS: f64 = 14.0;
+const HUD_BORDER_WIDTH: f64 = 1.0;
+const HUD_ICON_FONT_SIZE: f64 = 18.0;
+const HUD_BADGE_FONT_SIZE: f64 = 10.0;
+const HUD_FOOTER_FONT_SIZE: f64 = 11.0;
+const HUD_BADGE_TEXT_ALPHA: f64 = 0.7;
+const HUD_FOOTER_TEXT_ALPHA: f64 = 0.6;
+const BITMAP_IMAGE_FILE_TYPE_PNG: usize = 4;
+// NSViewWidthSizable / NSViewHeightSizable
+const VIEW_WIDTH_SIZABLE: usize = 1 << 1;
+const VIEW_HEIGHT_SIZABLE: usize = 1 << 4;
+// NSVisualEffectBlendingModeBehindWindow / NSVisualEffectStateActive
+const VISUAL_EFFECT_BLENDING_BEHIND_WINDOW: isize = 0;
+const VISUAL_EFFECT_STATE_ACTIVE: isize = 1;
+// NSImageScaleProportionallyUpOrDown / NSImageInterpolationHigh
+const IMAGE_SCALE_PROPORTIONALLY_UP_OR_DOWN: usize = 3;
+const IMAGE_INTERPOLATION_HIGH: usize = 3;
+// NSImageInterpolationNone。QR コードのセルをぼかさずに拡大する
+const IMAGE_INTERPOLATION_NONE: usize = 1;
+// 画像のクリップを縮小するときの長辺。Retina でもサムネイル枠を埋められる大きさにする
+const CLIP_THUMBNAIL_MAX_PIXELS: f64 = 256.0;
+// コピー元のアプリのアイコンの大きさ。ヘッダーの行に縮小して表示する
+const SOURCE_APP_ICON_PIXELS: f64 = 64.0;
+// 色見本を描く大きさ。アイコン枠に縮小して表示するので、Retina でもぼやけない大きさにする
+const COLOR_SWATCH_PIXELS: f64 = 64.0;
+const COLOR_SWATCH_CORNER_RADIUS: f64 = 12.0;
+const COLOR_SWATCH_BORDER_WIDTH: f64 = 2.0;
+pub const MIN_RENDER_SCALE_FACTOR: f64 = 1.0;
+pub const MAX_RENDER_SCALE_FACTOR: f64 = 3.0;
+// --deterministic ではシステムフォントの代わりに、OS のバージョンで字形が変わりにくいフォントを使う
+const DETERMINISTIC_FONT_NAME: &str = "Helvetica";
+const DETERMINISTIC_BOLD_FONT_NAME: &str = "Helvetica-Bold";
+// NSAccessibilityPriorityHigh。読み上げ中の内容を遮ってでも伝える
+const ACCESSIBILITY_PRIORITY_HIGH: isize = 90;
+// URL の強調しないスキームやパスの文字の不透明度
+const DIMMED_TEXT_ALPHA: f64 = 0.6;
+// NSBoldFontMask
+const BOLD_FONT_MASK: usize = 2;
+// Markdown のコードの背景。HUD の背景より少し明るくする
+const MARKDOWN_CODE_BACKGROUND_ALPHA: f64 = 0.15;
+
+extern "C" {
+    fn NSAccessibilityPostNotificationWithUserInfo(
+        element: *mut AnyObject,
+        notification: *mut AnyObject,
+        user_info: *mut AnyObject,
+    );
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct HudViews {
+    pub window: *mut AnyObject,
+    pub icon_label: *mut AnyObject,
+    /// 画像のクリップで、アイコンの代わりに出す `NSImageView`
+    pub thumbnail_view: *mut AnyObject,
+    /// アイコンと同じ大きさで、アイコンの代わりに出す `NSImageView`（URL のファビコンなど）
+    pub icon_image_view: *mut AnyObject,
+    pub badge_label: *mut AnyObject,
+    pub label: *mut AnyObject,
+    pub footer_label: *mut AnyObject,
+    /// 本文の上のヘッダー（コピー元のアプリ名）の左に出すアイコンの `NSImageView`
+    pub header_icon_view: *mut AnyObject,
+    pub header_label: *mut AnyObject,
+    /// `hud_material` で背景に敷く `NSVisualEffectView`。オフスクリーンでは null
+    pub effect_view: *mut AnyObject,
+}
+
+/// [`create_hud_window`] が作ったウィンドウの所有者。Drop でウィンドウを閉じて解放する。
+///
+/// ラベルはウィンドウの contentView が保持しているので、[`HudViews`] の各ポインタは
+/// この値が生きている間だけ有効。
+#[derive(Debug)]
+pub struct HudWindow {
+    views: HudViews,
+    _window: Owned,
+}
+
+impl HudWindow {
+    pub fn views(&self) -> HudViews {
+        self.views
+    }
+}
+
+impl Drop for HudWindow {
+    fn drop(&mut self) {
+        // 解放後に同じアドレスで作られたウィンドウを、配置済みと取り違えないようにする
+        LAST_LAYOUT.with(|last| last.set(None));
+        unsafe {
+            let () = msg_send![self.views.window, close];
+        }
+    }
+}
+
+/// ウィンドウを作らずに組み立てた HUD のビュー階層。`views.window` は null で、
+/// ラベルは `root` が保持する。
+struct OffscreenHud {
+    views: HudViews,
+    root: Owned,
+}
+
+impl OffscreenHud {
+    unsafe fn new(settings: DisplaySettings) -> Result<Self, String> {
+        let dims = hud_dimensions(settings);
+        let width = dims.min_width;
+        let height = dims.min_height;
+        let root: *mut AnyObject = msg_send![class!(NSView), alloc];
+        let root: *mut AnyObject = msg_send![
+            root,
+            initWithFrame: NSRect {
+                origin: NSPoint { x: 0.0, y: 0.0 },
+                size: NSSize { width, height },
+            }
+        ];
+        let root = Owned::from_raw(root).ok_or_else(|| "failed to create HUD view".to_string())?;
+        let views = add_hud_labels(ptr::null_mut(), root.as_ptr(), &dims, width, height);
+        apply_view_settings(&views, settings);
+        Ok(Self { views, root })
+    }
+}
+
+impl Drop for OffscreenHud {
+    fn drop(&mut self) {
+        // どのオフスクリーン描画も window が null なので、次の描画で配置を使い回さない
+        LAST_LAYOUT.with(|last| last.set(None));
+    }
+}
+
+/// `--layout-json` で出力する、実際のフォントで計測した HUD の寸法と表示位置。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HudPlacement {
+    pub metrics: HudLayoutMetrics,
+    pub hud_position: HudPosition,
+    pub has_badge: bool,
+    pub has_footer: bool,
+    /// メインスクリーンの `visibleFrame`。画面がない環境では `None`。
+    pub screen_frame: Option<NSRect>,
+    /// ウィンドウの左下の座標。画面がない環境では `None`。
+    pub origin: Option<(f64, f64)>,
+}
+
+impl HudPlacement {
+    pub fn to_json(&self) -> serde_json::Value {
+        let metrics = self.metrics;
+        json!({
+            "metrics": {
+                "width": metrics.width,
+                "text_width": metrics.text_width,
+                "height": metrics.height,
+                "text_height": metrics.text_height,
+                "label_y": metrics.label_y,
+                "icon_y": metrics.icon_y,
+                "footer_y": metrics.footer_y,
+            },
+            "hud_position": self.hud_position.as_str(),
+            "has_badge": self.has_badge,
+            "has_footer": self.has_footer,
+            "screen_frame": self.screen_frame.map(|frame| json!({
+                "x": frame.origin.x,
+                "y": frame.origin.y,
+                "width": frame.size.width,
+                "height": frame.size.height,
+            })),
+            "origin": self.origin.map(|(x, y)| json!({ "x": x, "y": y })),
+        })
+    }
+}
+
+/// ウィンドウを作らず、描画もせずに、`text` を表示したときの HUD の寸法と位置を求める。
+pub fn measure_hud_placement(
+    text: &str,
+    settings: DisplaySettings,
+) -> Result<HudPlacement, String> {
+    unsafe {
+        let hud = OffscreenHud::new(settings)?;
+        let content = build_hud_content(text, ClipContext::default(), settings);
+        apply_hud_content(&hud.views, &content, settings);
+        let has_badge = content.badge.is_some();
+        let has_footer = content.footer.is_some();
+        let metrics = measure_hud_layout(
+            &hud.views,
+            settings,
+            has_badge,
+            has_footer,
+            content.thumbnail.is_some(),
+            content.header.is_some(),
+        );
+        // 常駐時と同じ画面の情報を得られるよう、NSScreen を引く前にアプリケーションを初期化する
+        let _: *mut AnyObject = msg_send![class!(NSApplication), sharedApplication];
+        let screen_frame = main_screen_visible_frame();
+        Ok(HudPlacement {
+            metrics,
+            hud_position: settings.hud_position,
+            has_badge,
+            has_footer,
+            screen_frame,
+            origin: screen_frame.map(|frame| {
+                hud_origin_for_frame(frame, metrics.width, metrics.height, settings.hud_position)
+            }),
+        })
+    }
+}
+
+/// `--render-hud-png` の描画オプション。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SnapshotOptions {
+    /// 2.0 にすると Retina 表示と同じ 2x のピクセル数で書き出す。
+    pub scale_factor: f64,
+    /// フォントの固定・フォントスムージングの無効化・sRGB・外観の固定を行い、
+    /// macOS のバージョンやランナーの設定によらず同じ画像になるようにする。
+    pub deterministic: bool,
+    /// ウィンドウを作らずにビュー階層をビットマップへ直接描く。WindowServer の
+    /// セッションがない環境（SSH 越しやヘッドレスの CI）でも動く。ウィンドウの影は描かない。
+    pub offscreen: bool,
+}
+
+impl Default for SnapshotOptions {
+    fn default() -> Self {
+        Self {
+            scale_factor: MIN_RENDER_SCALE_FACTOR,
+            deterministic: false,
+            offscreen: false,
+        }
+    }
+}
+
+pub fn render_hud_png(
+    text: &str,
+    output_path: &str,
+    settings: DisplaySettings,
+    options: SnapshotOptions,
+) -> Result<(), String> {
+    unsafe {
+        if options.deterministic {
+            prepare_deterministic_rendering();
+        }
+        let bitmap = if options.offscreen {
+            render_offscreen_bitmap(text, settings, options)?
+        } else {
+            render_window_bitmap(text, settings, options)?
+        };
+
+        let properties: *mut AnyObject = msg_send![class!(NSDictionary), dictionary];
+        let data: *mut AnyObject = msg_send![
+            bitmap.as_ptr(),
+            representationUsingType: BITMAP_IMAGE_FILE_TYPE_PNG
+            properties: properties
+        ];
+        if data.is_null() {
+            return Err("failed to encode PNG data".to_string());
+        }
+
+        let output_path_ns = nsstring_from_str(output_path);
+        let success: bool = msg_send![data, writeToFile: output_path_ns atomically: true];
+        let () = msg_send![output_path_ns, release];
+
+        if !success {
+            return Err(format!("failed to write PNG: {output_path}"));
+        }
+    }
+
+    Ok(())
+}
+
+unsafe fn render_window_bitmap(
+    text: &str,
+    settings: DisplaySettings,
+    options: SnapshotOptions,
+) -> Result<Owned, String> {
+    let app: *mut AnyObject = msg_send![class!(NSApplication), sharedApplication];
+    if options.deterministic {
+        set_aqua_appearance(app);
+    }
+    let hud_window = create_hud_window(settings);
+    let views = hud_window.views();
+    update_hud_content(&views, text, settings);
+
+    let content_view: *mut AnyObject = msg_send![views.window, contentView];
+    if content_view.is_null() {
+        return Err("failed to get contentView".to_string());
+    }
+
+    let bounds: NSRect = msg_send![content_view, bounds];
+    let bitmap = create_snapshot_bitmap(bounds, options)?;
+    let () = msg_send![
+        content_view,
+        cacheDisplayInRect: bounds
+        toBitmapImageRep: bitmap.as_ptr()
+    ];
+    Ok(bitmap)
+}
+
+unsafe fn render_offscreen_bitmap(
+    text: &str,
+    settings: DisplaySettings,
+    options: SnapshotOptions,
+) -> Result<Owned, String> {
+    let hud = OffscreenHud::new(settings)?;
+    if options.deterministic {
+        set_aqua_appearance(hud.root.as_ptr());
+    }
+    update_hud_content(&hud.views, text, settings);
+
+    let bounds: NSRect = msg_send![hud.root.as_ptr(), bounds];
+    let bitmap = create_snapshot_bitmap(bounds, options)?;
+    let context: *mut AnyObject = msg_send![
+        class!(NSGraphicsContext),
+        graphicsContextWithBitmapImageRep: bitmap.as_ptr()
+    ];
+    if context.is_null() {
+        return Err("failed to create bitmap graphics context".to_string());
+    }
+
+    let () = msg_send![class!(NSGraphicsContext), saveGraphicsState];
+    let () = msg_send![class!(NSGraphicsContext), setCurrentContext: context];
+    // ウィンドウではレイヤーが描く角丸の背景と枠を、同じ寸法で塗る。
+    // レイヤーの枠線は内側に描かれるので、線の太さの半分だけ内側をなぞる
+    let style = HudFrameStyle::new(settings);
+    let background: *mut AnyObject = msg_send![
+        class!(NSBezierPath),
+        bezierPathWithRoundedRect: bounds
+        xRadius: style.corner_radius
+        yRadius: style.corner_radius
+    ];
+    let () = msg_send![style.background_color(), setFill];
+    let () = msg_send![background, fill];
+    let inset = style.border_width / 2.0;
+    let border_rect = NSRect {
+        origin: NSPoint {
+            x: bounds.origin.x + inset,
+            y: bounds.origin.y + inset,
+        },
+        size: NSSize {
+            width: bounds.size.width - style.border_width,
+            height: bounds.size.height - style.border_width,
+        },
+    };
+    let border_radius = (style.corner_radius - inset).max(0.0);
+    let border: *mut AnyObject = msg_send![
+        class!(NSBezierPath),
+        bezierPathWithRoundedRect: border_rect
+        xRadius: border_radius
+        yRadius: border_radius
+    ];
+    // 太さ 0 の線は AppKit では最も細い線になるので、テーマで枠を消したときは描かない
+    if style.border_width > 0.0 {
+        let () = msg_send![border, setLineWidth: style.border_width];
+        let () = msg_send![style.border_color(), setStroke];
+        let () = msg_send![border, stroke];
+    }
+    // 背景の上にラベルを重ねる。ウィンドウに載っていないビューでも描ける
+    let () = msg_send![
+        hud.root.as_ptr(),
+        displayRectIgnoringOpacity: bounds
+        inContext: context
+    ];
+    let () = msg_send![class!(NSGraphicsContext), restoreGraphicsState];
+    Ok(bitmap)
+}
+
+// 描画前に sRGB として扱わせ、ディスプレイや既定のカラースペースによる変換を挟まない
+unsafe fn create_snapshot_bitmap(
+    bounds: NSRect,
+    options: SnapshotOptions,
+) -> Result<Owned, String> {
+    let scale_factor = options
+        .scale_factor
+        .clamp(MIN_RENDER_SCALE_FACTOR, MAX_RENDER_SCALE_FACTOR);
+    let bitmap = create_bitmap_rep_for_bounds(bounds, scale_factor)?;
+    if !options.deterministic {
+        return Ok(bitmap);
+    }
+    let srgb: *mut AnyObject = msg_send![class!(NSColorSpace), sRGBColorSpace];
+    let retagged: *mut AnyObject =
+        msg_send![bitmap.as_ptr(), bitmapImageRepByRetaggingWithColorSpace: srgb];
+    let bitmap =
+        Owned::retain(retagged).ok_or_else(|| "failed to retag bitmap as sRGB".to_string())?;
+    let () = msg_send![bitmap.as_ptr(), setSize: bounds.size];
+    Ok(bitmap)
+}
+
+pub fn generate_diff_png(
+    baseline_path: &str,
+    current_path: &str,
+    output_path: &str,
+    options: &DiffOptions,
+) -> Result<DiffSummary, String> {
+    unsafe {
+        let baseline_path_ns = nsstring_from_str(baseline_path);
+        let baseline_rep: *mut AnyObject =
+            msg_send![class!(NSBitmapImageRep), imageRepWithContentsOfFile: baseline_path_ns];
+        let () = msg_send![baseline_path_ns, release];
+        if baseline_rep.is_null() {
+            return Err(format!("failed to load baseline PNG: {baseline_path}"));
+        }
+
+        let current_path_ns = nsstring_from_str(current_path);
+        let current_rep: *mut AnyObject =
+            msg_send![class!(NSBitmapImageRep), imageRepWithContentsOfFile: current_path_ns];
+        let () = msg_send![current_path_ns, release];
+        if current_rep.is_null() {
+            return Err(format!("failed to load current PNG: {current_path}"));
+        }
+
+        let baseline_width: isize = msg_send![baseline_rep, pixelsWide];
+        let baseline_height: isize = msg_send![baseline_rep, pixelsHigh];
+        let current_width: isize = msg_send![current_rep, pixelsWide];
+        let current_height: isize = msg_send![current_rep, pixelsHigh];
+        let baseline_size = (baseline_width as usize, baseline_height as usize);
+        let current_size = (current_width as usize, current_height as usize);
+        let Some((width, height)) = options
+            .resize_policy
+            .canvas_size(baseline_size, current_size)
+        else {
+            return Err(format!(
+                "image size mismatch: baseline={}x{}@{}x, current={}x{}@{}x",
+                baseline_width,
+                baseline_height,
+                format_scale_factor(rep_scale_factor(baseline_rep)),
+                current_width,
+                current_height,
+                format_scale_factor(rep_scale_factor(current_rep))
+            ));
+        };
+
+        let canvas = (width, height);
+        let baseline_pixels = fit_rgba(
+            &rgba_pixels_of_rep(baseline_rep, pixel_bounds(baseline_size))?,
+            baseline_size,
+            canvas,
+        );
+        let current_pixels = fit_rgba(
+            &rgba_pixels_of_rep(current_rep, pixel_bounds(current_size))?,
+            current_size,
+            canvas,
+        );
+        let mask_pixels = match &options.mask_path {
+            Some(mask_path) => {
+                let mask_path_ns = nsstring_from_str(mask_path);
+                let mask_rep: *mut AnyObject =
+                    msg_send![class!(NSBitmapImageRep), imageRepWithContentsOfFile: mask_path_ns];
+                let () = msg_send![mask_path_ns, release];
+                if mask_rep.is_null() {
+                    return Err(format!("failed to load mask PNG: {mask_path}"));
+                }
+                let mask_width: isize = msg_send![mask_rep, pixelsWide];
+                let mask_height: isize = msg_send![mask_rep, pixelsHigh];
+                if mask_width != current_width || mask_height != current_height {
+                    return Err(format!(
+                        "mask size mismatch: mask={}x{}, current={}x{}",
+                        mask_width, mask_height, current_width, current_height
+                    ));
+                }
+                let mask = rgba_pixels_of_rep(mask_rep, pixel_bounds(current_size))?;
+                Some(fit_rgba(&mask, current_size, canvas))
+            }
+            None => None,
+        };
+        let ignore_mask = options.ignore_mask(width, height, mask_pixels.as_deref());
+
+        let mut diff_buffer = vec![0u8; current_pixels.len()];
+        let params = DiffParams {
+            overlap: options.resize_policy.overlap(baseline_size, current_size),
+            ..options.params(width, height, ignore_mask.as_deref())
+        };
+        let summary = diff_rgba_image_with_params(
+            &baseline_pixels,
+            &current_pixels,
+            &mut diff_buffer,
+            params,
+        );
+
+        if let Some(composite_path) = &options.composite_path {
+            let (composite, width, height) = compose_side_by_side(
+                &[&baseline_pixels, &current_pixels, &diff_buffer],
+                width,
+                height,
+            );
+            let composite_rep = create_bitmap_rep_for_bounds(pixel_bounds((width, height)), 1.0)?;
+            write_rgba_pixels(composite_rep.as_ptr(), &composite);
+            write_bitmap_png(composite_rep.as_ptr(), composite_path, "composite")?;
+        }
+
+        let diff_rep = create_bitmap_rep_for_bounds(pixel_bounds(canvas), 1.0)?;
+        write_rgba_pixels(diff_rep.as_ptr(), &diff_buffer);
+        // 差分画像にも現在の画像と同じ DPI（ポイントサイズ）を引き継ぐ
+        let current_points: NSSize = msg_send![current_rep, size];
+        let points_per_pixel = current_points.width / current_width.max(1) as f64;
+        let diff_points = NSSize {
+            width: width as f64 * points_per_pixel,
+            height: height as f64 * points_per_pixel,
+        };
+        let () = msg_send![diff_rep.as_ptr(), setSize: diff_points];
+        write_bitmap_png(diff_rep.as_ptr(), output_path, "diff")?;
+
+        Ok(summary)
+    }
+}
+
+fn pixel_bounds((width, height): (usize, usize)) -> NSRect {
+    NSRect {
+        origin: NSPoint { x: 0.0, y: 0.0 },
+        size: NSSize {
+            width: width as f64,
+            height: height as f64,
+        },
+    }
+}
+
+unsafe fn write_bitmap_png(
+    rep: *mut AnyObject,
+    output_path: &str,
+    kind: &str,
+) -> Result<(), String> {
+    let properties: *mut AnyObject = msg_send![class!(NSDictionary), dictionary];
+    let data: *mut AnyObject = msg_send![
+        rep,
+        representationUsingType: BITMAP_IMAGE_FILE_TYPE_PNG
+        properties: properties
+    ];
+    if data.is_null() {
+        return Err(format!("failed to encode {kind} PNG"));
+    }
+
+    let output_path_ns = nsstring_from_str(output_path);
+    let success: bool = msg_send![data, writeToFile: output_path_ns atomically: true];
+    let () = msg_send![output_path_ns, release];
+
+    if !success {
+        return Err(format!("failed to write {kind} PNG: {output_path}"));
+    }
+    Ok(())
+}
+
+// PNGごとにビット深度やアルファの扱いが異なるため、RGBA8 のビットマップに描き直してから読み出す
+unsafe fn rgba_pixels_of_rep(rep: *mut AnyObject, bounds: NSRect) -> Result<Vec<u8>, String> {
+    let normalized = create_bitmap_rep_for_bounds(bounds, 1.0)?;
+    let context: *mut AnyObject = msg_send![
+        class!(NSGraphicsContext),
+        graphicsContextWithBitmapImageRep: normalized.as_ptr()
+    ];
+    if context.is_null() {
+        return Err("failed to create bitmap graphics context".to_string());
+    }
+    let () = msg_send![class!(NSGraphicsContext), saveGraphicsState];
+    let () = msg_send![class!(NSGraphicsContext), setCurrentContext: context];
+    let _: bool = msg_send![rep, drawInRect: bounds];
+    let () = msg_send![class!(NSGraphicsContext), restoreGraphicsState];
+
+    read_rgba_pixels(normalized.as_ptr()).ok_or_else(|| "failed to read bitmap data".to_string())
+}
+
+unsafe fn read_rgba_pixels(rep: *mut AnyObject) -> Option<Vec<u8>> {
+    let (data, width, height, bytes_per_row) = bitmap_layout(rep)?;
+    let row_len = width * RGBA_CHANNELS;
+    let mut pixels = Vec::with_capacity(row_len * height);
+    for row in 0..height {
+        let start = data.add(row * bytes_per_row);
+        pixels.extend_from_slice(std::slice::from_raw_parts(start, row_len));
+    }
+    Some(pixels)
+}
+
+unsafe fn write_rgba_pixels(rep: *mut AnyObject, pixels: &[u8]) {
+    let Some((data, width, height, bytes_per_row)) = bitmap_layout(rep) else {
+        return;
+    };
+    let row_len = width * RGBA_CHANNELS;
+    for (row, chunk) in pixels.chunks_exact(row_len).take(height).enumerate() {
+        let start = data.add(row * bytes_per_row);
+        std::slice::from_raw_parts_mut(start, row_len).copy_from_slice(chunk);
+    }
+}
+
+unsafe fn bitmap_layout(rep: *mut AnyObject) -> Option<(*mut u8, usize, usize, usize)> {
+    let data: *mut u8 = msg_send![rep, bitmapData];
+    if data.is_null() {
+        return None;
+    }
+    let width: isize = msg_send![rep, pixelsWide];
+    let height: isize = msg_send![rep, pixelsHigh];
+    let bytes_per_row: isize = msg_send![rep, bytesPerRow];
+    Some((
+        data,
+        width as usize,
+        height as usize,
+        bytes_per_row as usize,
+    ))
+}
+
+// PNGのピクセル数とポイントサイズの比（72dpi を 1x とした DPI）
+unsafe fn rep_scale_factor(rep: *mut AnyObject) -> f64 {
+    let pixels_wide: isize = msg_send![rep, pixelsWide];
+    let size: NSSize = msg_send![rep, size];
+    if size.width <= 0.0 {
+        return 1.0;
+    }
+    pixels_wide as f64 / size.width
+}
+
+fn format_scale_factor(scale_factor: f64) -> String {
+    format!("{}", (scale_factor * 100.0).round() / 100.0)
+}
+
+unsafe fn prepare_deterministic_rendering() {
+    PINNED_FONTS.with(|pinned| pinned.set(true));
+
+    // 引数ドメインは揮発性なので、ユーザーの defaults を書き換えずにこのプロセスだけで効く
+    let zero: *mut AnyObject = msg_send![class!(NSNumber), numberWithInteger: 0isize];
+    let yes: *mut AnyObject = msg_send![class!(NSNumber), numberWithBool: true];
+    let dictionary: *mut AnyObject = msg_send![class!(NSMutableDictionary), dictionary];
+    for (key, value) in [
+        ("AppleFontSmoothing", zero),
+        ("CGFontRenderingFontSmoothingDisabled", yes),
+    ] {
+        let () = msg_send![dictionary, setObject: value forKey: cached_nsstring(key)];
+    }
+    let defaults: *mut AnyObject = msg_send![class!(NSUserDefaults), standardUserDefaults];
+    let () = msg_send![
+        defaults,
+        setVolatileDomain: dictionary
+        forName: cached_nsstring("NSArgumentDomain")
+    ];
+}
+
+// `target` は NSApplication か NSView
+unsafe fn set_aqua_appearance(target: *mut AnyObject) {
+    let aqua: *mut AnyObject = msg_send![
+        class!(NSAppearance),
+        appearanceNamed: cached_nsstring("NSAppearanceNameAqua")
+    ];
+    let () = msg_send![target, setAppearance: aqua];
+}
+
+/// `appearance = "auto"` のために、アプリの今の外観がダークかを調べる。
+///
+/// # Safety
+///
+/// AppKit のメインスレッドから呼び出すこと。
+pub unsafe fn system_appearance_is_dark() -> bool {
+    let app: *mut AnyObject = msg_send![class!(NSApplication), sharedApplication];
+    let appearance: *mut AnyObject = msg_send![app, effectiveAppearance];
+    if appearance.is_null() {
+        return true;
+    }
+    // ハイコントラストなどの派生もあるので、名前ではなく近いほうを選ばせる
+    let dark_name = cached_nsstring("NSAppearanceNameDarkAqua");
+    let names: *mut AnyObject = msg_send![
+        class!(NSArray),
+        arrayWithObject: cached_nsstring("NSAppearanceNameAqua")
+    ];
+    let names: *mut AnyObject = msg_send![names, arrayByAddingObject: dark_name];
+    let best: *mut AnyObject = msg_send![appearance, bestMatchFromAppearancesWithNames: names];
+    !best.is_null() && msg_send![best, isEqualToString: dark_name]
+}
+
+// 配色を決めたあとの設定。ライトなら既定の色を明るいものに入れ替える
+unsafe fn appearance_settings(settings: DisplaySettings) -> DisplaySettings {
+    let system_dark = settings.appearance != HudAppearance::Auto || system_appearance_is_dark();
+    apply_appearance(settings, system_dark)
+}
+
+fn create_bitmap_rep_for_bounds(bounds: NSRect, scale_factor: f64) -> Result<Owned, String> {
+    let width = (bounds.size.width * scale_factor).ceil().max(1.0) as isize;
+    let height = (bounds.size.height * scale_factor).ceil().max(1.0) as isize;
+    unsafe {
+        let bitmap: *mut AnyObject = msg_send![class!(NSBitmapImageRep), alloc];
+        let color_space = cached_nsstring("NSCalibratedRGBColorSpace");
+        let bitmap: *mut AnyObject = msg_send![
+            bitmap,
+            initWithBitmapDataPlanes: ptr::null_mut::<*mut u8>()
+            pixelsWide: width
+            pixelsHigh: height
+            bitsPerSample: 8isize
+            samplesPerPixel: 4isize
+            hasAlpha: true
+            isPlanar: false
+            colorSpaceName: color_space
+            bytesPerRow: 0isize
+            bitsPerPixel: 0isize
+        ];
+
+        let bitmap = Owned::from_raw(bitmap)
+            .ok_or_else(|| "failed to allocate fixed-size bitmap image rep".to_string())?;
+        if scale_factor != 1.0 {
+            // ポイントサイズを元の大きさにしておくと cacheDisplayInRect が高解像度で描画する
+            let () = msg_send![bitmap.as_ptr(), setSize: bounds.size];
+        }
+
+        Ok(bitmap)
+    }
+}
+
+fn hud_background_rgba(color: HudBackgroundColor) -> (f64, f64, f64, f64) {
+    match color {
+        HudBackgroundColor::Default => (0.0, 0.0, 0.0, 0.78),
+        HudBackgroundColor::Yellow => (0.43, 0.34, 0.04, 0.9),
+        HudBackgroundColor::Blue => (0.08, 0.22, 0.53, 0.9),
+        HudBackgroundColor::Green => (0.08, 0.35, 0.22, 0.9),
+        HudBackgroundColor::Red => (0.47, 0.14, 0.14, 0.9),
+        HudBackgroundColor::Purple => (0.36, 0.16, 0.47, 0.9),
+        HudBackgroundColor::Rgba(rgba) => rgba_components(rgba),
+    }
+}
+
+fn rgba_components(rgba: [u8; 4]) -> (f64, f64, f64, f64) {
+    let [red, green, blue, alpha] = rgba.map(|channel| f64::from(channel) / 255.0);
+    (red, green, blue, alpha)
+}
+
+// NSVisualEffectMaterial の値
+fn visual_effect_material(material: HudMaterial) -> Option<isize> {
+    match material {
+        HudMaterial::None => None,
+        HudMaterial::Hud => Some(13),
+        HudMaterial::Popover => Some(6),
+        HudMaterial::Sidebar => Some(7),
+    }
+}
+
+// `alpha` は色そのもののアルファに掛ける
+unsafe fn hud_text_color(color: HudTextColor, alpha: f64) -> *mut AnyObject {
+    let [red, green, blue, color_alpha] = match color {
+        HudTextColor::White => [1.0; 4],
+        HudTextColor::Black => [0.0, 0.0, 0.0, 1.0],
+        HudTextColor::Rgba(rgba) => rgba.map(|channel| f64::from(channel) / 255.0),
+    };
+    msg_send![
+        class!(NSColor),
+        colorWithCalibratedRed: red
+        green: green
+        blue: blue
+        alpha: color_alpha * alpha
+    ]
+}
+
+/// # Safety
+///
+/// AppKit のメインスレッドから呼び出すこと。
+pub unsafe fn create_hud_window(settings: DisplaySettings) -> HudWindow {
+    let clamped_scale = parse_f64_value(
+        settings.hud_scale,
+        DEFAULT_HUD_SCALE,
+        MIN_HUD_SCALE,
+        MAX_HUD_SCALE,
+    );
+    let dims = hud_dimensions(settings);
+    let default_width = (600.0 * clamped_scale).clamp(dims.min_width, dims.max_width);
+    let default_height = dims.min_height;
+    let mut rect = NSRect {
+        origin: NSPoint { x: 0.0, y: 0.0 },
+        size: NSSize {
+            width: default_width,
+            height: default_height,
+        },
+    };
+
+    if let Some((x, y)) = hud_origin(default_width, default_height, settings.hud_position) {
+        rect.origin = NSPoint { x, y };
+    }
+
+    let window: *mut AnyObject = msg_send![class!(NSWindow), alloc];
+    let window: *mut AnyObject = msg_send![
+        window,
+        initWithContentRect: rect
+        styleMask: BORDERLESS_MASK
+        backing: BACKING_BUFFERED
+        defer: false
+    ];
+    // 解放は HudWindow の Drop に任せるので、close で二重に解放されないようにする
+    let () = msg_send![window, setReleasedWhenClosed: false];
+
+    let () = msg_send![window, setOpaque: false];
+    let () = msg_send![window, setHasShadow: true];
+    let () = msg_send![window, setIgnoresMouseEvents: true];
+    let () = msg_send![window, setLevel: FLOATING_WINDOW_LEVEL];
+
+    let clear: *mut AnyObject = msg_send![class!(NSColor), clearColor];
+    let () = msg_send![window, setBackgroundColor: clear];
+
+    let content_view: *mut AnyObject = msg_send![window, contentView];
+    let () = msg_send![content_view, setWantsLayer: true];
+    let layer: *mut AnyObject = msg_send![content_view, layer];
+    let () = msg_send![layer, setMasksToBounds: true];
+
+    // ラベルより先に足して一番奥に置き、ウィンドウの大きさの変化に合わせて伸ばす
+    let bounds: NSRect = msg_send![content_view, bounds];
+    let effect_view: *mut AnyObject = msg_send![class!(NSVisualEffectView), alloc];
+    let effect_view: *mut AnyObject = msg_send![effect_view, initWithFrame: bounds];
+    let () = msg_send![
+        effect_view,
+        setAutoresizingMask: VIEW_WIDTH_SIZABLE | VIEW_HEIGHT_SIZABLE
+    ];
+    // ウィンドウがキーにならなくても、常に有効な見た目で描く
+    let () = msg_send![effect_view, setBlendingMode: VISUAL_EFFECT_BLENDING_BEHIND_WINDOW];
+    let () = msg_send![effect_view, setState: VISUAL_EFFECT_STATE_ACTIVE];
+    let () = msg_send![effect_view, setHidden: true];
+    let () = msg_send![content_view, addSubview: effect_view];
+    let () = msg_send![effect_view, release];
+
+    let mut views = add_hud_labels(window, content_view, &dims, default_width, default_height);
+    views.effect_view = effect_view;
+    let () = msg_send![window, orderOut: ptr::null_mut::<AnyObject>()];
+    apply_view_settings(&views, settings);
+    HudWindow {
+        views,
+        _window: Owned::from_raw(window).expect("failed to create HUD window"),
+    }
+}
+
+// `content_view` にアイコン・バッジ・本文・フッターのラベルを並べる。`window` は null でもよい
+unsafe fn add_hud_labels(
+    window: *mut AnyObject,
+    content_view: *mut AnyObject,
+    dims: &HudDimensions,
+    default_width: f64,
+    default_height: f64,
+) -> HudViews {
+    let icon_rect = NSRect {
+        origin: NSPoint {
+            x: dims.horizontal_padding,
+            y: ((default_height - dims.line_height_estimate) / 2.0 + dims.line_height_estimate
+                - dims.icon_height)
+                .max(dims.vertical_padding),
+        },
+        size: NSSize {
+            width: dims.icon_width,
+            height: dims.icon_height,
+        },
+    };
+
+    let icon_label: *mut AnyObject = msg_send![class!(NSTextField), alloc];
+    let icon_label: *mut AnyObject = msg_send![icon_label, initWithFrame: icon_rect];
+    let () = msg_send![icon_label, setBezeled: false];
+    let () = msg_send![icon_label, setBordered: false];
+    let () = msg_send![icon_label, setEditable: false];
+    let () = msg_send![icon_label, setSelectable: false];
+    let () = msg_send![icon_label, setDrawsBackground: false];
+    let () = msg_send![icon_label, setAlignment: 1isize];
+    let () = msg_send![icon_label, setLineBreakMode: 0isize];
+    let () = msg_send![icon_label, setUsesSingleLineMode: true];
+    let icon_text = nsstring_from_str(DEFAULT_HUD_ICON);
+    let () = msg_send![icon_label, setStringValue: icon_text];
+    let () = msg_send![icon_text, release];
+
+    let thumbnail_view: *mut AnyObject = msg_send![class!(NSImageView), alloc];
+    let thumbnail_view: *mut AnyObject = msg_send![thumbnail_view, initWithFrame: icon_rect];
+    let () = msg_send![
+        thumbnail_view,
+        setImageScaling: IMAGE_SCALE_PROPORTIONALLY_UP_OR_DOWN
+    ];
+    let () = msg_send![thumbnail_view, setHidden: true];
+
+    let icon_image_view: *mut AnyObject = msg_send![class!(NSImageView), alloc];
+    let icon_image_view: *mut AnyObject = msg_send![icon_image_view, initWithFrame: icon_rect];
+    let () = msg_send![
+        icon_image_view,
+        setImageScaling: IMAGE_SCALE_PROPORTIONALLY_UP_OR_DOWN
+    ];
+    let () = msg_send![icon_image_view, setHidden: true];
+
+    let badge_rect = NSRect {
+        origin: NSPoint {
+            x: dims.horizontal_padding - dims.gap / 2.0,
+            y: icon_rect.origin.y - dims.badge_height,
+        },
+        size: NSSize {
+            width: dims.icon_width + dims.gap,
+            height: dims.badge_height,
+        },
+    };
+    let badge_label: *mut AnyObject = msg_send![class!(NSTextField), alloc];
+    let badge_label: *mut AnyObject = msg_send![badge_label, initWithFrame: badge_rect];
+    let () = msg_send![badge_label, setBezeled: false];
+    let () = msg_send![badge_label, setBordered: false];
+    let () = msg_send![badge_label, setEditable: false];
+    let () = msg_send![badge_label, setSelectable: false];
+    let () = msg_send![badge_label, setDrawsBackground: false];
+    let () = msg_send![badge_label, setAlignment: 1isize];
+    let () = msg_send![badge_label, setLineBreakMode: 2isize];
+    let () = msg_send![badge_label, setUsesSingleLineMode: true];
+    let () = msg_send![badge_label, setHidden: true];
+
+    let label_rect = NSRect {
+        origin: NSPoint {
+            x: dims.horizontal_padding + dims.icon_width + dims.gap,
+            y: (default_height - dims.line_height_estimate) / 2.0,
+        },
+        size: NSSize {
+            width: default_width - (dims.horizontal_padding * 2.0 + dims.icon_width + dims.gap),
+            height: dims.line_height_estimate,
+        },
+    };
+
+    let label: *mut AnyObject = msg_send![class!(NSTextField), alloc];
+    let label: *mut AnyObject = msg_send![label, initWithFrame: label_rect];
+
+    let () = msg_send![label, setBezeled: false];
+    let () = msg_send![label, setBordered: false];
+    let () = msg_send![label, setEditable: false];
+    let () = msg_send![label, setSelectable: false];
+    let () = msg_send![label, setDrawsBackground: false];
+    let () = msg_send![label, setLineBreakMode: 1isize];
+    let () = msg_send![label, setUsesSingleLineMode: false];
+    let () = msg_send![label, setMaximumNumberOfLines: 0isize];
+    let () = msg_send![label, setAlignment: 0isize];
+
+    let cell: *mut AnyObject = msg_send![label, cell];
+    if !cell.is_null() {
+        let () = msg_send![cell, setWraps: true];
+        let () = msg_send![cell, setScrollable: false];
+        let () = msg_send![cell, setLineBreakMode: 1isize];
+    }
+
+    let default_text = nsstring_from_str("Clipboard text");
+    let () = msg_send![label, setStringValue: default_text];
+    let () = msg_send![default_text, release];
+
+    let footer_rect = NSRect {
+        origin: NSPoint {
+            x: label_rect.origin.x,
+            y: dims.vertical_padding,
+        },
+        size: NSSize {
+            width: label_rect.size.width,
+            height: dims.footer_height,
+        },
+    };
+    let footer_label: *mut AnyObject = msg_send![class!(NSTextField), alloc];
+    let footer_label: *mut AnyObject = msg_send![footer_label, initWithFrame: footer_rect];
+    let () = msg_send![footer_label, setBezeled: false];
+    let () = msg_send![footer_label, setBordered: false];
+    let () = msg_send![footer_label, setEditable: false];
+    let () = msg_send![footer_label, setSelectable: false];
+    let () = msg_send![footer_label, setDrawsBackground: false];
+    let () = msg_send![footer_label, setAlignment: 0isize];
+    let () = msg_send![footer_label, setLineBreakMode: 4isize];
+    let () = msg_send![footer_label, setUsesSingleLineMode: true];
+    let () = msg_send![footer_label, setHidden: true];
+
+    // ヘッダーは本文の上に、本文と左端をそろえて置く。位置は layout_hud で決める
+    let header_icon_rect = NSRect {
+        origin: NSPoint {
+            x: label_rect.origin.x,
+            y: default_height - dims.vertical_padding - dims.header_height,
+        },
+        size: NSSize {
+            width: dims.header_height,
+            height: dims.header_height,
+        },
+    };
+    let header_icon_view: *mut AnyObject = msg_send![class!(NSImageView), alloc];
+    let header_icon_view: *mut AnyObject =
+        msg_send![header_icon_view, initWithFrame: header_icon_rect];
+    let () = msg_send![
+        header_icon_view,
+        setImageScaling: IMAGE_SCALE_PROPORTIONALLY_UP_OR_DOWN
+    ];
+    let () = msg_send![header_icon_view, setHidden: true];
+
+    let header_label: *mut AnyObject = msg_send![class!(NSTextField), alloc];
+    let header_label: *mut AnyObject = msg_send![header_label, initWithFrame: footer_rect];
+    let () = msg_send![header_label, setBezeled: false];
+    let () = msg_send![header_label, setBordered: false];
+    let () = msg_send![header_label, setEditable: false];
+    let () = msg_send![header_label, setSelectable: false];
+    let () = msg_send![header_label, setDrawsBackground: false];
+    let () = msg_send![header_label, setAlignment: 0isize];
+    let () = msg_send![header_label, setLineBreakMode: 4isize];
+    let () = msg_send![header_label, setUsesSingleLineMode: true];
+    let () = msg_send![header_label, setHidden: true];
+
+    // addSubview: で contentView が保持するので、alloc した分はここで手放す
+    for subview in [
+        icon_label,
+        thumbnail_view,
+        icon_image_view,
+        badge_label,
+        label,
+        footer_label,
+        header_icon_view,
+        header_label,
+    ] {
+        let () = msg_send![content_view, addSubview: subview];
+        let () = msg_send![subview, release];
+    }
+
+    HudViews {
+        window,
+        icon_label,
+        thumbnail_view,
+        icon_image_view,
+        badge_label,
+        label,
+        footer_label,
+        header_icon_view,
+        header_label,
+        effect_view: ptr::null_mut(),
+    }
+}
+
+/// 既存のウィンドウとラベルを作り直さずに、色・フォント・サイズを `settings` に合わせて更新する。
+///
+/// # Safety
+///
+/// `views` は [`create_hud_window`] で作成したもので、メインスレッドから呼び出すこと。
+pub unsafe fn apply_view_settings(views: &HudViews, settings: DisplaySettings) {
+    let settings = appearance_settings(settings);
+    let clamped_scale = parse_f64_value(
+        settings.hud_scale,
+        DEFAULT_HUD_SCALE,
+        MIN_HUD_SCALE,
+        MAX_HUD_SCALE,
+    );
+
+    // オフスクリーンのビューにはレイヤーがないので、背景と枠は描画時に塗る
+    if !views.window.is_null() {
+        let style = HudFrameStyle::new(settings);
+        let content_view: *mut AnyObject = msg_send![views.window, contentView];
+        let layer: *mut AnyObject = msg_send![content_view, layer];
+        let () = msg_send![layer, setCornerRadius: style.corner_radius];
+        // 素材を敷くときは、その上に色を重ねないよう背景を透明にする
+        let material = visual_effect_material(settings.hud_material);
+        let background: *mut AnyObject = match material {
+            Some(_) => msg_send![class!(NSColor), clearColor],
+            None => style.background_color(),
+        };
+        let cg_color: *mut c_void = msg_send![background, CGColor];
+        let () = msg_send![layer, setBackgroundColor: cg_color];
+        if !views.effect_view.is_null() {
+            if let Some(material) = material {
+                let () = msg_send![views.effect_view, setMaterial: material];
+            }
+            let () = msg_send![views.effect_view, setHidden: material.is_none()];
+        }
+        let border_color: *mut c_void = msg_send![style.border_color(), CGColor];
+        let () = msg_send![layer, setBorderColor: border_color];
+        let () = msg_send![layer, setBorderWidth: style.border_width];
+    }
+
+    // 文字色は設定で変わるので、ラベルを作るときではなくここで塗る
+    for (label, alpha) in [
+        (views.icon_label, 1.0),
+        (views.label, 1.0),
+        (views.badge_label, HUD_BADGE_TEXT_ALPHA),
+        (views.footer_label, HUD_FOOTER_TEXT_ALPHA),
+        (views.header_label, HUD_FOOTER_TEXT_ALPHA),
+    ] {
+        let () = msg_send![label, setTextColor: hud_text_color(settings.hud_text_color, alpha)];
+    }
+    // SF Symbols はテンプレート画像なので文字色で塗る。ファビコンや色見本には効かない
+    let () = msg_send![
+        views.icon_image_view,
+        setContentTintColor: hud_text_color(settings.hud_text_color, 1.0)
+    ];
+
+    let icon_font_size = (HUD_ICON_FONT_SIZE * clamped_scale).clamp(10.0, 44.0);
+    let icon_font = cached_font(HudFont::System, icon_font_size);
+    let () = msg_send![views.icon_label, setFont: icon_font];
+
+    let badge_font_size = (HUD_BADGE_FONT_SIZE * clamped_scale).clamp(8.0, 24.0);
+    let badge_font = cached_font(HudFont::BoldSystem, badge_font_size);
+    let () = msg_send![views.badge_label, setFont: badge_font];
+
+    let text_font_size = (settings.hud_font_size * clamped_scale).clamp(6.0, 80.0);
+    // 設定やテーマのフォントが見つからなければ Menlo に戻す
+    let mut font = cached_font(
+        HudFont::Named(settings.hud_font.unwrap_or(DEFAULT_HUD_FONT_NAME)),
+        text_font_size,
+    );
+    if font.is_null() {
+        font = cached_font(HudFont::Named(DEFAULT_HUD_FONT_NAME), text_font_size);
+    }
+    if !font.is_null() {
+        let () = msg_send![views.label, setFont: font];
+    }
+    set_label_line_spacing(views.label, settings);
+
+    let footer_font_size = (HUD_FOOTER_FONT_SIZE * clamped_scale).clamp(8.0, 28.0);
+    let footer_font = cached_font(HudFont::System, footer_font_size);
+    let () = msg_send![views.footer_label, setFont: footer_font];
+    let () = msg_send![views.header_label, setFont: footer_font];
+
+    // 表示中の内容はそのままに、新しい寸法と位置で並べ直す
+    let badge_hidden: bool = msg_send![views.badge_label, isHidden];
+    let footer_hidden: bool = msg_send![views.footer_label, isHidden];
+    let thumbnail_hidden: bool = msg_send![views.thumbnail_view, isHidden];
+    let header_hidden: bool = msg_send![views.header_label, isHidden];
+    layout_hud(
+        views,
+        settings,
+        !badge_hidden,
+        !footer_hidden,
+        !thumbnail_hidden,
+        !header_hidden,
+    );
+}
+
+// 角丸・背景・枠線。ウィンドウのレイヤーとオフスクリーン描画で同じ見た目にする
+#[derive(Debug, Clone, Copy)]
+struct HudFrameStyle {
+    corner_radius: f64,
+    border_width: f64,
+    border: (f64, f64, f64, f64),
+    background: (f64, f64, f64, f64),
+}
+
+impl HudFrameStyle {
+    unsafe fn new(settings: DisplaySettings) -> Self {
+        let settings = appearance_settings(settings);
+        let clamped_scale = parse_f64_value(
+            settings.hud_scale,
+            DEFAULT_HUD_SCALE,
+            MIN_HUD_SCALE,
+            MAX_HUD_SCALE,
+        );
+        // テーマの寸法は倍率だけを掛け、既定の見た目のような上下限はかけない
+        let corner_radius = match settings.hud_corner_radius {
+            Some(radius) => radius * clamped_scale,
+            None => (HUD_CORNER_RADIUS * clamped_scale).clamp(8.0, 30.0),
+        };
+        let border_width = match settings.hud_border_width {
+            Some(width) => width * clamped_scale,
+            None => (HUD_BORDER_WIDTH * clamped_scale).clamp(1.0, 2.5),
+        };
+        let border = match settings.hud_border_color {
+            Some(rgba) => rgba_components(rgba),
+            None if settings.hud_background_color == HudBackgroundColor::Default => {
+                (1.0, 1.0, 1.0, 0.14)
+            }
+            None => (1.0, 1.0, 1.0, 0.2),
+        };
+        Self {
+            corner_radius,
+            border_width,
+            border,
+            background: hud_background_rgba(settings.hud_background_color),
+        }
+    }
+
+    unsafe fn background_color(self) -> *mut AnyObject {
+        let (r, g, b, a) = self.background;
+        msg_send![
+            class!(NSColor),
+            colorWithCalibratedRed: r
+            green: g
+            blue: b
+            alpha: a
+        ]
+    }
+
+    unsafe fn border_color(self) -> *mut AnyObject {
+        let (r, g, b, a) = self.border;
+        msg_send![
+            class!(NSColor),
+            colorWithCalibratedRed: r
+            green: g
+            blue: b
+            alpha: a
+        ]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum HudFont {
+    System,
+    BoldSystem,
+    Named(&'static str),
+}
+
+thread_local! {
+    // 設定の反映やスナップショットのたびにフォントを引き直さないよう、種類と寸法ごとに保持する
+    static FONT_CACHE: RefCell<HashMap<(HudFont, u64), usize>> = RefCell::new(HashMap::new());
+    static PINNED_FONTS: Cell<bool> = const { Cell::new(false) };
+}
+
+/// 見つからないフォント名では null を返す。戻り値はキャッシュが保持するので `release` しない。
+unsafe fn cached_font(kind: HudFont, size: f64) -> *mut AnyObject {
+    let kind = match kind {
+        HudFont::System if PINNED_FONTS.with(Cell::get) => HudFont::Named(DETERMINISTIC_FONT_NAME),
+        HudFont::BoldSystem if PINNED_FONTS.with(Cell::get) => {
+            HudFont::Named(DETERMINISTIC_BOLD_FONT_NAME)
+        }
+        kind => kind,
+    };
+    FONT_CACHE.with(|cache| {
+        *cache
+            .borrow_mut()
+            .entry((kind, size.to_bits()))
+            .or_insert_with(|| {
+                let font: *mut AnyObject = match kind {
+                    HudFont::System => msg_send![class!(NSFont), systemFontOfSize: size],
+                    HudFont::BoldSystem => msg_send![class!(NSFont), boldSystemFontOfSize: size],
+                    HudFont::Named(name) => {
+                        msg_send![class!(NSFont), fontWithName: cached_nsstring(name) size: size]
+                    }
+                };
+                if !font.is_null() {
+                    let _: *mut AnyObject = msg_send![font, retain];
+                }
+                font as usize
+            }) as *mut AnyObject
+    })
+}
+
+/// `hud_font` の名前で NSFont を作れるか。見つからないフォントは表示時に Menlo に戻す。
+///
+/// # Safety
+///
+/// AppKit を使うので、メインスレッドから呼び出すこと。
+pub unsafe fn hud_font_is_available(name: &str) -> bool {
+    let name_ns = nsstring_from_str(name);
+    let font: *mut AnyObject = msg_send![
+        class!(NSFont),
+        fontWithName: name_ns
+        size: DEFAULT_HUD_FONT_SIZE
+    ];
+    let () = msg_send![name_ns, release];
+    !font.is_null()
+}
+
+/// `hud_icon` の名前で SF Symbols の画像を作れるか。見つからなければ表示時に 📋 に戻す。
+///
+/// # Safety
+///
+/// AppKit を使うので、メインスレッドから呼び出すこと。
+pub unsafe fn hud_symbol_is_available(name: &str) -> bool {
+    !symbol_image(name).is_null()
+}
+
+// SF Symbols の画像（autorelease 済み）。macOS 11 より前や見つからない名前なら null
+unsafe fn symbol_image(name: &str) -> *mut AnyObject {
+    let supported: bool = msg_send![
+        class!(NSImage),
+        respondsToSelector: sel!(imageWithSystemSymbolName:accessibilityDescription:)
+    ];
+    if !supported {
+        return ptr::null_mut();
+    }
+    let name_ns = nsstring_from_str(name);
+    let image: *mut AnyObject = msg_send![
+        class!(NSImage),
+        imageWithSystemSymbolName: name_ns
+        accessibilityDescription: ptr::null_mut::<AnyObject>()
+    ];
+    let () = msg_send![name_ns, release];
+    image
+}
+
+unsafe fn main_screen_visible_frame() -> Option<NSRect> {
+    let screen: *mut AnyObject = msg_send![class!(NSScreen), mainScreen];
+    if screen.is_null() {
+        return None;
+    }
+
+    let frame: NSRect = msg_send![screen, visibleFrame];
+    Some(frame)
+}
+
+unsafe fn hud_origin(width: f64, height: f64, position: HudPosition) -> Option<(f64, f64)> {
+    let frame = main_screen_visible_frame()?;
+    Some(hud_origin_for_frame(frame, width, height, position))
+}
+
+unsafe fn position_window(window: *mut AnyObject, width: f64, height: f64, position: HudPosition) {
+    let (x, y) = hud_origin(width, height, position).unwrap_or((0.0, 0.0));
+
+    let rect = NSRect {
+        origin: NSPoint { x, y },
+        size: NSSize { width, height },
+    };
+    let () = msg_send![window, setFrame: rect display: true];
+}
+
+unsafe fn update_hud_content(views: &HudViews, text: &str, settings: DisplaySettings) {
+    let content = build_hud_content(text, ClipContext::default(), settings);
+    apply_hud_content(views, &content, settings);
+}
+
+/// # Safety
+///
+/// `views` は [`create_hud_window`] で作成したもので、メインスレッドから呼び出すこと。
+pub unsafe fn apply_hud_content(views: &HudViews, content: &HudContent, settings: DisplaySettings) {
+    match (&content.diff, &content.markdown, &content.emphasis) {
+        (Some(spans), _, _) => set_label_diff(views.label, spans),
+        (None, Some(spans), _) => set_label_markdown(views.label, spans),
+        (None, None, Some(emphasis)) => {
+            set_label_emphasis(views.label, &content.text, emphasis.clone())
+        }
+        (None, None, None) => set_label_text(views.label, &content.text),
+    }
+    set_label_line_spacing(views.label, settings);
+    // 内容に合わせたアイコン（🔗 など）がなければ設定のアイコンを使う
+    let icon = content
+        .icon
+        .unwrap_or(settings.hud_icon.unwrap_or(DEFAULT_HUD_ICON));
+    // SF Symbols は画像で出す。見つからない名前なら画像の代わりに文字の 📋 を出す
+    let (icon_text, symbol) = if is_sf_symbol_name(icon) {
+        (DEFAULT_HUD_ICON, symbol_image(icon))
+    } else {
+        (icon, ptr::null_mut())
+    };
+    set_label_text(views.icon_label, icon_text);
+    set_optional_label_text(views.badge_label, content.badge.as_deref());
+    set_optional_label_text(views.footer_label, content.footer.as_deref());
+    set_image_view_data(views.thumbnail_view, content.thumbnail.as_deref());
+    let header = content.header.as_ref();
+    set_optional_label_text(
+        views.header_label,
+        header.map(|header| header.text.as_str()),
+    );
+    set_image_view_data(
+        views.header_icon_view,
+        header.and_then(|header| header.icon_png.as_deref()),
+    );
+    // 前のクリップのファビコンは引き継がない。色の値なら色見本を、SF Symbols なら記号をアイコンの位置に出す
+    let swatch = content.swatch.and_then(|swatch| color_swatch_image(swatch));
+    set_image_view_image(
+        views.icon_image_view,
+        swatch.as_ref().map_or(symbol, Owned::as_ptr),
+    );
+    update_icon_visibility(views);
+    layout_hud(
+        views,
+        settings,
+        content.badge.is_some(),
+        content.footer.is_some(),
+        content.thumbnail.is_some(),
+        content.header.is_some(),
+    );
+}
+
+/// 表示中の HUD のアイコンを、`data`（NSImage で読める PNG や ICO）の画像に差し替える。
+/// 画像として読めなければ何もせず `false` を返す。
+///
+/// # Safety
+///
+/// `views` は [`create_hud_window`] で作成したもので、メインスレッドから呼び出すこと。
+pub unsafe fn set_icon_image(views: &HudViews, data: &[u8]) -> bool {
+    if !set_image_view_data(views.icon_image_view, Some(data)) {
+        return false;
+    }
+    update_icon_visibility(views);
+    true
+}
+
+// `view`（NSImageView）に画像を入れて表示する。`None` や読めないデータなら隠して `false` を返す
+unsafe fn set_image_view_data(view: *mut AnyObject, data: Option<&[u8]>) -> bool {
+    let image = data.and_then(|data| {
+        let data: *mut AnyObject = msg_send![
+            class!(NSData),
+            dataWithBytes: data.as_ptr() as *const c_void
+            length: data.len()
+        ];
+        let image: *mut AnyObject = msg_send![class!(NSImage), alloc];
+        Owned::from_raw(msg_send![image, initWithData: data])
+    });
+    set_image_view_image(view, image.as_ref().map_or(ptr::null_mut(), Owned::as_ptr))
+}
+
+// `view`（NSImageView）に `image`（NSImage）を入れて表示する。null なら隠して `false` を返す
+unsafe fn set_image_view_image(view: *mut AnyObject, image: *mut AnyObject) -> bool {
+    let () = msg_send![view, setImage: image];
+    let () = msg_send![view, setHidden: image.is_null()];
+    !image.is_null()
+}
+
+// 角丸の四角を `swatch` の色で塗った NSImage。暗い色でも HUD の背景と見分けられるよう薄い枠を付ける
+unsafe fn color_swatch_image(swatch: ColorSwatch) -> Option<Owned> {
+    let bounds = NSRect {
+        origin: NSPoint { x: 0.0, y: 0.0 },
+        size: NSSize {
+            width: COLOR_SWATCH_PIXELS,
+            height: COLOR_SWATCH_PIXELS,
+        },
+    };
+    let bitmap = create_bitmap_rep_for_bounds(bounds, 1.0).ok()?;
+    let context: *mut AnyObject = msg_send![
+        class!(NSGraphicsContext),
+        graphicsContextWithBitmapImageRep: bitmap.as_ptr()
+    ];
+    if context.is_null() {
+        return None;
+    }
+    let () = msg_send![class!(NSGraphicsContext), saveGraphicsState];
+    let () = msg_send![class!(NSGraphicsContext), setCurrentContext: context];
+    let inset = COLOR_SWATCH_BORDER_WIDTH / 2.0;
+    let rect = NSRect {
+        origin: NSPoint { x: inset, y: inset },
+        size: NSSize {
+            width: COLOR_SWATCH_PIXELS - COLOR_SWATCH_BORDER_WIDTH,
+            height: COLOR_SWATCH_PIXELS - COLOR_SWATCH_BORDER_WIDTH,
+        },
+    };
+    let path: *mut AnyObject = msg_send![
+        class!(NSBezierPath),
+        bezierPathWithRoundedRect: rect
+        xRadius: COLOR_SWATCH_CORNER_RADIUS
+        yRadius: COLOR_SWATCH_CORNER_RADIUS
+    ];
+    let channel = |value: u8| f64::from(value) / 255.0;
+    let fill: *mut AnyObject = msg_send![
+        class!(NSColor),
+        colorWithSRGBRed: channel(swatch.red)
+        green: channel(swatch.green)
+        blue: channel(swatch.blue)
+        alpha: channel(swatch.alpha)
+    ];
+    let () = msg_send![fill, setFill];
+    let () = msg_send![path, fill];
+    let border: *mut AnyObject =
+        msg_send![class!(NSColor), colorWithCalibratedWhite: 1.0f64 alpha: 0.6f64];
+    let () = msg_send![path, setLineWidth: COLOR_SWATCH_BORDER_WIDTH];
+    let () = msg_send![border, setStroke];
+    let () = msg_send![path, stroke];
+    let () = msg_send![class!(NSGraphicsContext), restoreGraphicsState];
+
+    let image: *mut AnyObject = msg_send![class!(NSImage), alloc];
+    let image = Owned::from_raw(msg_send![image, initWithSize: bounds.size])?;
+    let () = msg_send![image.as_ptr(), addRepresentation: bitmap.as_ptr()];
+    Some(image)
+}
+
+// 画像を出している間は、同じ位置の絵文字のアイコンを隠す
+unsafe fn update_icon_visibility(views: &HudViews) {
+    let thumbnail_hidden: bool = msg_send![views.thumbnail_view, isHidden];
+    let icon_image_hidden: bool = msg_send![views.icon_image_view, isHidden];
+    let () = msg_send![
+        views.icon_label,
+        setHidden: !thumbnail_hidden || !icon_image_hidden
+    ];
+}
+
+/// 画像のクリップ（PNG や TIFF の `NSData`）から、元の寸法と HUD 用に縮小した PNG を作る。
+/// 画像として読めなければ `None`。
+///
+/// # Safety
+///
+/// `data` は null か有効な `NSData` で、メインスレッドから呼び出すこと。
+pub unsafe fn clip_image_from_data(data: *mut AnyObject) -> Option<ClipImage> {
+    if data.is_null() {
+        return None;
+    }
+    let source: *mut AnyObject = msg_send![class!(NSBitmapImageRep), imageRepWithData: data];
+    if source.is_null() {
+        return None;
+    }
+    let pixel_width: isize = msg_send![source, pixelsWide];
+    let pixel_height: isize = msg_send![source, pixelsHigh];
+    if pixel_width <= 0 || pixel_height <= 0 {
+        return None;
+    }
+    // NSImage に包んで、ファイルのアイコンと同じ経路で縮小する
+    let size: NSSize = msg_send![source, size];
+    let image: *mut AnyObject = msg_send![class!(NSImage), alloc];
+    let image = Owned::from_raw(msg_send![image, initWithSize: size])?;
+    let () = msg_send![image.as_ptr(), addRepresentation: source];
+    let thumbnail_png = thumbnail_png(image.as_ptr(), pixel_width as f64, pixel_height as f64)?;
+    Some(ClipImage {
+        pixel_width: pixel_width as usize,
+        pixel_height: pixel_height as usize,
+        thumbnail_png,
+    })
+}
+
+/// 最前面のアプリの名前と、HUD 用に縮小したアイコン。cliip-show 自身なら `None`。
+///
+/// # Safety
+///
+/// メインスレッドから呼び出すこと。
+pub unsafe fn frontmost_source_app() -> Option<SourceApp> {
+    let workspace: *mut AnyObject = msg_send![class!(NSWorkspace), sharedWorkspace];
+    let app: *mut AnyObject = msg_send![workspace, frontmostApplication];
+    if app.is_null() {
+        return None;
+    }
+    let pid: i32 = msg_send![app, processIdentifier];
+    if pid == std::process::id() as i32 {
+        return None;
+    }
+    let name: *mut AnyObject = msg_send![app, localizedName];
+    let name = nsstring_to_string(name).filter(|name| !name.is_empty())?;
+    let icon: *mut AnyObject = msg_send![app, icon];
+    let icon_png = (!icon.is_null())
+        .then(|| {
+            draw_image_png(
+                icon,
+                SOURCE_APP_ICON_PIXELS,
+                SOURCE_APP_ICON_PIXELS,
+                IMAGE_INTERPOLATION_HIGH,
+            )
+        })
+        .flatten();
+    Some(SourceApp { name, icon_png })
+}
+
+/// Finder と同じファイルのアイコン（複数なら重ねたアイコン）を PNG にする。
+///
+/// # Safety
+///
+/// メインスレッドから呼び出すこと。
+pub unsafe fn file_icon_png(paths: &[impl AsRef<Path>]) -> Option<Vec<u8>> {
+    let filenames: *mut AnyObject = msg_send![class!(NSMutableArray), array];
+    for path in paths {
+        let filename = nsstring_from_str(&path.as_ref().to_string_lossy());
+        let () = msg_send![filenames, addObject: filename];
+        let () = msg_send![filename, release];
+    }
+    let workspace: *mut AnyObject = msg_send![class!(NSWorkspace), sharedWorkspace];
+    let icon: *mut AnyObject = msg_send![workspace, iconForFiles: filenames];
+    if icon.is_null() {
+        return None;
+    }
+    thumbnail_png(icon, CLIP_THUMBNAIL_MAX_PIXELS, CLIP_THUMBNAIL_MAX_PIXELS)
+}
+
+/// `text` を CoreImage の `CIQRCodeGenerator` で QR コードにし、サムネイル枠に収まる PNG にする。
+///
+/// # Safety
+///
+/// メインスレッドから呼び出すこと。
+pub unsafe fn qr_code_png(text: &str) -> Option<Vec<u8>> {
+    let filter: *mut AnyObject = msg_send![
+        class!(CIFilter),
+        filterWithName: cached_nsstring("CIQRCodeGenerator")
+    ];
+    if filter.is_null() {
+        return None;
+    }
+    let message: *mut AnyObject = msg_send![
+        class!(NSData),
+        dataWithBytes: text.as_ptr() as *const c_void
+        length: text.len()
+    ];
+    let () = msg_send![filter, setValue: message forKey: cached_nsstring("inputMessage")];
+    let () = msg_send![
+        filter,
+        setValue: cached_nsstring("M")
+        forKey: cached_nsstring("inputCorrectionLevel")
+    ];
+    let output: *mut AnyObject = msg_send![filter, outputImage];
+    if output.is_null() {
+        return None;
+    }
+    // 1セル1ピクセルの画像ができるので、整数倍に拡大してセルの境目をそろえる
+    let rep: *mut AnyObject = msg_send![class!(NSBitmapImageRep), alloc];
+    let rep = Owned::from_raw(msg_send![rep, initWithCIImage: output])?;
+    let modules: isize = msg_send![rep.as_ptr(), pixelsWide];
+    if modules <= 0 {
+        return None;
+    }
+    let modules = modules as f64;
+    let size = NSSize {
+        width: modules,
+        height: modules,
+    };
+    let image: *mut AnyObject = msg_send![class!(NSImage), alloc];
+    let image = Owned::from_raw(msg_send![image, initWithSize: size])?;
+    let () = msg_send![image.as_ptr(), addRepresentation: rep.as_ptr()];
+    let side = modules * (CLIP_THUMBNAIL_MAX_PIXELS / modules).floor().max(1.0);
+    draw_image_png(image.as_ptr(), side, side, IMAGE_INTERPOLATION_NONE)
+}
+
+// `image`（NSImage）を、長辺が CLIP_THUMBNAIL_MAX_PIXELS に収まる PNG にする
+unsafe fn thumbnail_png(image: *mut AnyObject, width: f64, height: f64) -> Option<Vec<u8>> {
+    let ratio = (CLIP_THUMBNAIL_MAX_PIXELS / width.max(height)).min(1.0);
+    draw_image_png(
+        image,
+        (width * ratio).round().max(1.0),
+        (height * ratio).round().max(1.0),
+        IMAGE_INTERPOLATION_HIGH,
+    )
+}
+
+// `image`（NSImage）を `width`×`height` ピクセルに描き直して PNG にする
+unsafe fn draw_image_png(
+    image: *mut AnyObject,
+    width: f64,
+    height: f64,
+    interpolation: usize,
+) -> Option<Vec<u8>> {
+    let bounds = NSRect {
+        origin: NSPoint { x: 0.0, y: 0.0 },
+        size: NSSize { width, height },
+    };
+    let thumbnail = create_bitmap_rep_for_bounds(bounds, 1.0).ok()?;
+    let context: *mut AnyObject = msg_send![
+        class!(NSGraphicsContext),
+        graphicsContextWithBitmapImageRep: thumbnail.as_ptr()
+    ];
+    if context.is_null() {
+        return None;
+    }
+    let () = msg_send![class!(NSGraphicsContext), saveGraphicsState];
+    let () = msg_send![class!(NSGraphicsContext), setCurrentContext: context];
+    let () = msg_send![context, setImageInterpolation: interpolation];
+    let () = msg_send![image, drawInRect: bounds];
+    let () = msg_send![class!(NSGraphicsContext), restoreGraphicsState];
+
+    let properties: *mut AnyObject = msg_send![class!(NSDictionary), dictionary];
+    let png: *mut AnyObject = msg_send![
+        thumbnail.as_ptr(),
+        representationUsingType: BITMAP_IMAGE_FILE_TYPE_PNG
+        properties: properties
+    ];
+    if png.is_null() {
+        return None;
+    }
+    let png = nsdata_to_vec(png);
+    (!png.is_empty()).then_some(png)
+}
+
+/// # Safety
+///
+/// `label` は有効な `NSTextField` で、メインスレッドから呼び出すこと。
+pub unsafe fn set_label_text(label: *mut AnyObject, text: &str) {
+    let message = nsstring_from_str(text);
+    let () = msg_send![label, setStringValue: message];
+    let () = msg_send![message, release];
+}
+
+/// VoiceOver などの支援技術に `text` を読み上げてもらうよう通知する。
+///
+/// # Safety
+///
+/// `element` は有効なアクセシビリティ要素（`NSApp` やウィンドウ）で、メインスレッドから呼び出すこと。
+pub unsafe fn announce_for_accessibility(element: *mut AnyObject, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    let announcement = nsstring_from_str(text);
+    let priority: *mut AnyObject =
+        msg_send![class!(NSNumber), numberWithInteger: ACCESSIBILITY_PRIORITY_HIGH];
+    let user_info: *mut AnyObject = msg_send![class!(NSMutableDictionary), dictionary];
+    let () = msg_send![
+        user_info,
+        setObject: announcement
+        forKey: cached_nsstring("AXAnnouncementKey")
+    ];
+    let () = msg_send![user_info, setObject: priority forKey: cached_nsstring("AXPriorityKey")];
+    let () = msg_send![announcement, release];
+    NSAccessibilityPostNotificationWithUserInfo(
+        element,
+        cached_nsstring("AXAnnouncementRequested"),
+        user_info,
+    );
+}
+
+// `emphasis`（バイト位置）の外側を薄くして、範囲内の文字を目立たせる
+unsafe fn set_label_emphasis(label: *mut AnyObject, text: &str, emphasis: Range<usize>) {
+    let (Some(before), Some(inside)) = (text.get(..emphasis.start), text.get(emphasis.clone()))
+    else {
+        set_label_text(label, text);
+        return;
+    };
+    let string = nsstring_from_str(text);
+    let attributed: *mut AnyObject = msg_send![class!(NSMutableAttributedString), alloc];
+    let attributed: *mut AnyObject = msg_send![attributed, initWithString: string];
+    let () = msg_send![string, release];
+
+    let font_key = cached_nsstring("NSFont");
+    let color_key = cached_nsstring("NSColor");
+    let full_range = NSRange {
+        location: 0,
+        length: text.encode_utf16().count(),
+    };
+    let font: *mut AnyObject = msg_send![label, font];
+    if !font.is_null() {
+        let () = msg_send![attributed, addAttribute: font_key value: font range: full_range];
+    }
+    let text_color: *mut AnyObject = msg_send![label, textColor];
+    if !text_color.is_null() {
+        let dimmed: *mut AnyObject =
+            msg_send![text_color, colorWithAlphaComponent: DIMMED_TEXT_ALPHA];
+        let () = msg_send![attributed, addAttribute: color_key value: dimmed range: full_range];
+        let emphasis_range = NSRange {
+            location: before.encode_utf16().count(),
+            length: inside.encode_utf16().count(),
+        };
+        let () =
+            msg_send![attributed, addAttribute: color_key value: text_color range: emphasis_range];
+    }
+    let () = msg_send![label, setAttributedStringValue: attributed];
+    let () = msg_send![attributed, release];
+}
+
+unsafe fn set_label_diff(label: *mut AnyObject, spans: &[DiffSpan]) {
+    let joined: String = spans.iter().map(|span| span.text.as_str()).collect();
+    let string = nsstring_from_str(&joined);
+    let attributed: *mut AnyObject = msg_send![class!(NSMutableAttributedString), alloc];
+    let attributed: *mut AnyObject = msg_send![attributed, initWithString: string];
+    let () = msg_send![string, release];
+
+    let font_key = cached_nsstring("NSFont");
+    let color_key = cached_nsstring("NSColor");
+    let strikethrough_key = cached_nsstring("NSStrikethrough");
+    let full_range = NSRange {
+        location: 0,
+        length: joined.encode_utf16().count(),
+    };
+    let font: *mut AnyObject = msg_send![label, font];
+    if !font.is_null() {
+        let () = msg_send![attributed, addAttribute: font_key value: font range: full_range];
+    }
+    let text_color: *mut AnyObject = msg_send![label, textColor];
+    if !text_color.is_null() {
+        let () = msg_send![attributed, addAttribute: color_key value: text_color range: full_range];
+    }
+
+    let mut location = 0;
+    for span in spans {
+        let length = span.text.encode_utf16().count();
+        let range = NSRange { location, length };
+        location += length;
+        let (r, g, b) = match span.kind {
+            DiffKind::Equal => continue,
+            DiffKind::Added => (0.45f64, 0.9f64, 0.5f64),
+            DiffKind::Removed => (1.0f64, 0.45f64, 0.45f64),
+        };
+        let color: *mut AnyObject = msg_send![
+            class!(NSColor),
+            colorWithCalibratedRed: r
+            green: g
+            blue: b
+            alpha: 1.0f64
+        ];
+        let () = msg_send![attributed, addAttribute: color_key value: color range: range];
+        if span.kind == DiffKind::Removed {
+            let single: *mut AnyObject = msg_send![class!(NSNumber), numberWithInteger: 1isize];
+            let () =
+                msg_send![attributed, addAttribute: strikethrough_key value: single range: range];
+        }
+    }
+    let () = msg_send![label, setAttributedStringValue: attributed];
+    let () = msg_send![attributed, release];
+}
+
+// 太字と見出しはラベルのフォントの太字、コードは等幅フォントに薄い背景を付ける。
+// 文字の大きさは変えないので、行の高さは書式なしと同じになる
+unsafe fn set_label_markdown(label: *mut AnyObject, spans: &[MarkdownSpan]) {
+    let joined: String = spans.iter().map(|span| span.text.as_str()).collect();
+    let font: *mut AnyObject = msg_send![label, font];
+    if font.is_null() {
+        set_label_text(label, &joined);
+        return;
+    }
+    let string = nsstring_from_str(&joined);
+    let attributed: *mut AnyObject = msg_send![class!(NSMutableAttributedString), alloc];
+    let attributed: *mut AnyObject = msg_send![attributed, initWithString: string];
+    let () = msg_send![string, release];
+
+    let font_key = cached_nsstring("NSFont");
+    let color_key = cached_nsstring("NSColor");
+    let background_key = cached_nsstring("NSBackgroundColor");
+    let full_range = NSRange {
+        location: 0,
+        length: joined.encode_utf16().count(),
+    };
+    let () = msg_send![attributed, addAttribute: font_key value: font range: full_range];
+    let text_color: *mut AnyObject = msg_send![label, textColor];
+    if !text_color.is_null() {
+        let () = msg_send![attributed, addAttribute: color_key value: text_color range: full_range];
+    }
+    let font_manager: *mut AnyObject = msg_send![class!(NSFontManager), sharedFontManager];
+    let bold_font: *mut AnyObject =
+        msg_send![font_manager, convertFont: font toHaveTrait: BOLD_FONT_MASK];
+    let point_size: f64 = msg_send![font, pointSize];
+    let code_font: *mut AnyObject = msg_send![class!(NSFont), userFixedPitchFontOfSize: point_size];
+    let code_background: *mut AnyObject = msg_send![
+        class!(NSColor),
+        colorWithCalibratedWhite: 1.0f64
+        alpha: MARKDOWN_CODE_BACKGROUND_ALPHA
+    ];
+
+    let mut location = 0;
+    for span in spans {
+        let length = span.text.encode_utf16().count();
+        let range = NSRange { location, length };
+        location += length;
+        match span.style {
+            MarkdownStyle::Plain => {}
+            MarkdownStyle::Bold | MarkdownStyle::Heading => {
+                let () =
+                    msg_send![attributed, addAttribute: font_key value: bold_font range: range];
+            }
+            MarkdownStyle::Code => {
+                if !code_font.is_null() {
+                    let () =
+                        msg_send![attributed, addAttribute: font_key value: code_font range: range];
+                }
+                let () = msg_send![
+                    attributed,
+                    addAttribute: background_key
+                    value: code_background
+                    range: range
+                ];
+            }
+        }
+    }
+    let () = msg_send![label, setAttributedStringValue: attributed];
+    let () = msg_send![attributed, release];
+}
+
+// 本文の行間を段落スタイルで付ける。書式付きの本文にも重ね、行間を 0 に戻したときは外す
+unsafe fn set_label_line_spacing(label: *mut AnyObject, settings: DisplaySettings) {
+    let line_spacing = hud_dimensions(settings).line_spacing;
+    let current: *mut AnyObject = msg_send![label, attributedStringValue];
+    let length: usize = if current.is_null() {
+        0
+    } else {
+        msg_send![current, length]
+    };
+    if length == 0 {
+        return;
+    }
+    let style_key = cached_nsstring("NSParagraphStyle");
+    let existing: *mut AnyObject = msg_send![
+        current,
+        attribute: style_key
+        atIndex: 0usize
+        effectiveRange: ptr::null_mut::<NSRange>()
+    ];
+    let existing_spacing: f64 = if existing.is_null() {
+        0.0
+    } else {
+        msg_send![existing, lineSpacing]
+    };
+    if existing_spacing == line_spacing {
+        return;
+    }
+    // ラベルの折り返しや揃えの設定を引き継ぐ
+    let base_style: *mut AnyObject = if existing.is_null() {
+        msg_send![class!(NSParagraphStyle), defaultParagraphStyle]
+    } else {
+        existing
+    };
+    let style: *mut AnyObject = msg_send![base_style, mutableCopy];
+    let () = msg_send![style, setLineSpacing: line_spacing];
+    let attributed: *mut AnyObject = msg_send![current, mutableCopy];
+    let full_range = NSRange {
+        location: 0,
+        length,
+    };
+    let () = msg_send![attributed, addAttribute: style_key value: style range: full_range];
+    let () = msg_send![label, setAttributedStringValue: attributed];
+    let () = msg_send![attributed, release];
+    let () = msg_send![style, release];
+}
+
+unsafe fn set_optional_label_text(label: *mut AnyObject, text: Option<&str>) {
+    set_label_text(label, text.unwrap_or(""));
+    let () = msg_send![label, setHidden: text.is_none()];
+}
+
+// 直前のレイアウトを決めた入力。バッジの文言は枠の大きさに影響しないので含めない
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct LayoutKey {
+    window: usize,
+    text_hash: u64,
+    has_badge: bool,
+    has_footer: bool,
+    has_thumbnail: bool,
+    has_header: bool,
+    scale_bits: u64,
+    padding_bits: [u64; 2],
+    font: Option<&'static str>,
+    font_size_bits: u64,
+    line_spacing_bits: u64,
+    position: HudPosition,
+    screen_frame_bits: Option<[u64; 4]>,
+}
+
+impl LayoutKey {
+    unsafe fn for_views(
+        views: &HudViews,
+        settings: DisplaySettings,
+        has_badge: bool,
+        has_footer: bool,
+        has_thumbnail: bool,
+        has_header: bool,
+    ) -> Self {
+        let mut hasher = DefaultHasher::new();
+        label_string(views.label).hash(&mut hasher);
+        if has_footer {
+            label_string(views.footer_label).hash(&mut hasher);
+        }
+        if has_header {
+            label_string(views.header_label).hash(&mut hasher);
+        }
+        // オフスクリーンでは画面を参照しない（WindowServer に接続しない）
+        let screen_frame = if views.window.is_null() {
+            None
+        } else {
+            main_screen_visible_frame()
+        };
+        Self {
+            window: views.window as usize,
+            text_hash: hasher.finish(),
+            has_badge,
+            has_footer,
+            has_thumbnail,
+            has_header,
+            scale_bits: settings.hud_scale.to_bits(),
+            padding_bits: [
+                settings.hud_padding.0.to_bits(),
+                settings.hud_padding.1.to_bits(),
+            ],
+            font: settings.hud_font,
+            font_size_bits: settings.hud_font_size.to_bits(),
+            line_spacing_bits: settings.hud_line_spacing.to_bits(),
+            position: settings.hud_position,
+            screen_frame_bits: screen_frame.map(|frame| {
+                [
+                    frame.origin.x.to_bits(),
+                    frame.origin.y.to_bits(),
+                    frame.size.width.to_bits(),
+                    frame.size.height.to_bits(),
+                ]
+            }),
+        }
+    }
+}
+
+thread_local! {
+    static LAST_LAYOUT: Cell<Option<LayoutKey>> = const { Cell::new(None) };
+}
+
+unsafe fn label_string(label: *mut AnyObject) -> Option<String> {
+    let value: *mut AnyObject = msg_send![label, stringValue];
+    nsstring_to_string(value)
+}
+
+// ラベルに入っている本文とフッターを実際のフォントで計測して、HUD の寸法を決める
+unsafe fn measure_hud_layout(
+    views: &HudViews,
+    settings: DisplaySettings,
+    has_badge: bool,
+    has_footer: bool,
+    has_thumbnail: bool,
+    has_header: bool,
+) -> HudLayoutMetrics {
+    let dims = hud_dimensions(settings);
+    let (leading_width, leading_height) = leading_size(&dims, has_thumbnail);
+    let mut natural_width = measure_text_natural_width(views.label, settings);
+    if has_footer {
+        natural_width = natural_width.max(measure_text_natural_width(views.footer_label, settings));
+    }
+    if has_header {
+        natural_width = natural_width.max(
+            measure_text_natural_width(views.header_label, settings) + header_icon_width(&dims),
+        );
+    }
+    // 計測した幅はアイコンの分を含むので、サムネイルとの差だけ広げる
+    natural_width += leading_width - dims.icon_width;
+    let clamped_width = natural_width.clamp(dims.min_width, dims.max_width);
+    let text_width = clamped_width - (dims.horizontal_padding * 2.0 + leading_width + dims.gap);
+    let mut measured_text_height = measure_text_height(views.label, text_width, settings);
+    if has_badge {
+        // バッジをアイコンの下に置けるだけの高さを確保する
+        measured_text_height = measured_text_height.max(leading_height + dims.badge_height);
+    }
+    if has_thumbnail {
+        measured_text_height = measured_text_height.max(leading_height);
+    }
+    let footer_height = if has_footer { dims.footer_height } else { 0.0 };
+    let header_height = if has_header { dims.header_height } else { 0.0 };
+    compute_hud_layout_metrics_with_header(
+        clamped_width,
+        measured_text_height,
+        (header_height, footer_height),
+        (leading_width, leading_height),
+        settings,
+    )
+}
+
+// ヘッダーの文字の左に置くアイコンと、その右の余白
+fn header_icon_width(dims: &HudDimensions) -> f64 {
+    dims.header_height + dims.gap / 2.0
+}
+
+// 本文の左に置く要素（アイコンかサムネイル）の幅と高さ
+fn leading_size(dims: &HudDimensions, has_thumbnail: bool) -> (f64, f64) {
+    if has_thumbnail {
+        (dims.thumbnail_size, dims.thumbnail_size)
+    } else {
+        (dims.icon_width, dims.icon_height)
+    }
+}
+
+unsafe fn layout_hud(
+    views: &HudViews,
+    settings: DisplaySettings,
+    has_badge: bool,
+    has_footer: bool,
+    has_thumbnail: bool,
+    has_header: bool,
+) {
+    // 同じ内容・同じ設定での再表示では計測も配置もやり直さず、前回の枠をそのまま使う
+    let key = LayoutKey::for_views(
+        views,
+        settings,
+        has_badge,
+        has_footer,
+        has_thumbnail,
+        has_header,
+    );
+    if LAST_LAYOUT.with(Cell::get) == Some(key) {
+        return;
+    }
+    LAST_LAYOUT.with(|last| last.set(Some(key)));
+
+    let metrics = measure_hud_layout(
+        views,
+        settings,
+        has_badge,
+        has_footer,
+        has_thumbnail,
+        has_header,
+    );
+    let dims = hud_dimensions(settings);
+    let (leading_width, leading_height) = leading_size(&dims, has_thumbnail);
+    let icon_rect = NSRect {
+        origin: NSPoint {
+            x: dims.horizontal_padding,
+            y: metrics.icon_y,
+        },
+        size: NSSize {
+            width: leading_width,
+            height: leading_height,
+        },
+    };
+    let label_rect = NSRect {
+        origin: NSPoint {
+            x: dims.horizontal_padding + leading_width + dims.gap,
+            y: metrics.label_y,
+        },
+        size: NSSize {
+            width: metrics.text_width,
+            height: metrics.text_height,
+        },
+    };
+
+    let badge_rect = NSRect {
+        origin: NSPoint {
+            x: dims.horizontal_padding - dims.gap / 2.0,
+            y: metrics.icon_y - dims.badge_height,
+        },
+        size: NSSize {
+            width: leading_width + dims.gap,
+            height: dims.badge_height,
+        },
+    };
+
+    let footer_rect = NSRect {
+        origin: NSPoint {
+            x: dims.horizontal_padding + leading_width + dims.gap,
+            y: metrics.footer_y,
+        },
+        size: NSSize {
+            width: metrics.text_width,
+            height: dims.footer_height,
+        },
+    };
+
+    let () = msg_send![views.icon_label, setFrame: icon_rect];
+    let () = msg_send![views.thumbnail_view, setFrame: icon_rect];
+    let () = msg_send![views.icon_image_view, setFrame: icon_rect];
+    let () = msg_send![views.badge_label, setFrame: badge_rect];
+    let () = msg_send![views.label, setFrame: label_rect];
+    let () = msg_send![views.footer_label, setFrame: footer_rect];
+    let header_icon_rect = NSRect {
+        origin: NSPoint {
+            x: label_rect.origin.x,
+            y: metrics.header_y,
+        },
+        size: NSSize {
+            width: dims.header_height,
+            height: dims.header_height,
+        },
+    };
+    let header_rect = NSRect {
+        origin: NSPoint {
+            x: label_rect.origin.x + header_icon_width(&dims),
+            y: metrics.header_y,
+        },
+        size: NSSize {
+            width: (metrics.text_width - header_icon_width(&dims)).max(0.0),
+            height: dims.header_height,
+        },
+    };
+    let () = msg_send![views.header_icon_view, setFrame: header_icon_rect];
+    let () = msg_send![views.header_label, setFrame: header_rect];
+    if views.window.is_null() {
+        // オフスクリーンではウィンドウの代わりに、ラベルの親ビューを HUD の大きさにする
+        let root: *mut AnyObject = msg_send![views.label, superview];
+        let () = msg_send![
+            root,
+            setFrameSize: NSSize {
+                width: metrics.width,
+                height: metrics.height,
+            }
+        ];
+    } else {
+        position_window(
+            views.window,
+            metrics.width,
+            metrics.height,
+            settings.hud_position,
+        );
+    }
+}
+
+thread_local! {
+    // (フォント名, ポイントサイズ) ごとの行幅キャッシュ。同じクリップの再表示で計測し直さない
+    static LINE_WIDTH_CACHE: RefCell<HashMap<(String, u64), HashMap<String, f64>>> =
+        RefCell::new(HashMap::new());
+}
+
+/// `NSAttributedString` の `size` でフォントの実測幅を返す計測器。
+struct FontTextMeasurer {
+    font: *mut AnyObject,
+    cache_key: (String, u64),
+}
+
+impl FontTextMeasurer {
+    unsafe fn for_label(label: *mut AnyObject) -> Option<Self> {
+        let font: *mut AnyObject = msg_send![label, font];
+        if font.is_null() {
+            return None;
+        }
+        let font_name: *mut AnyObject = msg_send![font, fontName];
+        let point_size: f64 = msg_send![font, pointSize];
+        Some(Self {
+            font,
+            cache_key: (
+                nsstring_to_string(font_name).unwrap_or_default(),
+                point_size.to_bits(),
+            ),
+        })
+    }
+
+    unsafe fn measure(&self, line: &str) -> f64 {
+        let string = nsstring_from_str(line);
+        let font_key = cached_nsstring("NSFont");
+        let attributes: *mut AnyObject = msg_send![
+            class!(NSDictionary),
+            dictionaryWithObject: self.font
+            forKey: font_key
+        ];
+        let attributed: *mut AnyObject = msg_send![class!(NSAttributedString), alloc];
+        let attributed: *mut AnyObject =
+            msg_send![attributed, initWithString: string attributes: attributes];
+        let size: NSSize = msg_send![attributed, size];
+        let () = msg_send![attributed, release];
+        let () = msg_send![string, release];
+        size.width.ceil() + HUD_TEXT_CELL_HORIZONTAL_INSET * 2.0
+    }
+}
+
+impl TextMeasurer for FontTextMeasurer {
+    fn line_width(&mut self, line: &str) -> f64 {
+        if let Some(width) = LINE_WIDTH_CACHE.with(|cache| {
+            cache
+                .borrow()
+                .get(&self.cache_key)
+                .and_then(|widths| widths.get(line).copied())
+        }) {
+            return width;
+        }
+
+        let width = unsafe { self.measure(line) };
+        LINE_WIDTH_CACHE.with(|cache| {
+            let mut cache = cache.borrow_mut();
+            let widths = cache.entry(self.cache_key.clone()).or_default();
+            if widths.len() >= LINE_WIDTH_CACHE_CAPACITY {
+                widths.clear();
+            }
+            widths.insert(line.to_string(), width);
+        });
+        width
+    }
+}
+
+unsafe fn measure_text_natural_width(label: *mut AnyObject, settings: DisplaySettings) -> f64 {
+    let dims = hud_dimensions(settings);
+    let Some(mut measurer) = FontTextMeasurer::for_label(label) else {
+        return dims.min_width;
+    };
+    let value: *mut AnyObject = msg_send![label, stringValue];
+    let text = nsstring_to_string(value).unwrap_or_default();
+    hud_width_for_text_with_measurer(&text, &mut measurer, settings)
+}
+
+unsafe fn measure_text_height(
+    label: *mut AnyObject,
+    text_width: f64,
+    settings: DisplaySettings,
+) -> f64 {
+    let dims = hud_dimensions(settings);
+    let cell: *mut AnyObject = msg_send![label, cell];
+    if cell.is_null() {
+        let value: *mut AnyObject = msg_send![label, stringValue];
+        let text = nsstring_to_string(value).unwrap_or_default();
+        return estimated_text_height(&text, settings);
+    }
+
+    let bounds = NSRect {
+        origin: NSPoint { x: 0.0, y: 0.0 },
+        size: NSSize {
+            width: text_width.max(1.0),
+            height: HUD_TEXT_MEASURE_HEIGHT,
+        },
+    };
+    // 行間は本文の段落スタイル（set_label_line_spacing）に入っているので、実測の高さに含まれる
+    let size: NSSize = msg_send![cell, cellSizeForBounds: bounds];
+    size.height.ceil().max(dims.line_height_estimate)
+}
@@ -0,0 +1,307 @@
+use chrono::{Datelike, Local, NaiveDateTime, Timelike, Weekday};
+use serde::{Deserialize, Serialize};
+
+// DisplaySettings を Copy のまま保つため、範囲は固定長の配列に持つ
+pub const MAX_QUIET_RANGES: usize = 8;
+const MINUTES_PER_DAY: u16 = 24 * 60;
+const WEEKDAYS: [(Weekday, &str, &str); 7] = [
+    (Weekday::Mon, "mon", "monday"),
+    (Weekday::Tue, "tue", "tuesday"),
+    (Weekday::Wed, "wed", "wednesday"),
+    (Weekday::Thu, "thu", "thursday"),
+    (Weekday::Fri, "fri", "friday"),
+    (Weekday::Sat, "sat", "saturday"),
+    (Weekday::Sun, "sun", "sunday"),
+];
+
+/// 静かな時間帯に HUD をどう扱うか。
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum QuietMode {
+    /// HUD を表示しない
+    #[default]
+    Suppress,
+    /// バッジ・フッターを省き、1行だけの HUD にする
+    Minimal,
+}
+
+impl QuietMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Suppress => "suppress",
+            Self::Minimal => "minimal",
+        }
+    }
+}
+
+pub fn parse_quiet_mode(raw: &str) -> Option<QuietMode> {
+    match raw.trim().to_ascii_lowercase().as_str() {
+        "suppress" | "off" => Some(QuietMode::Suppress),
+        "minimal" => Some(QuietMode::Minimal),
+        _ => None,
+    }
+}
+
+/// `mon-fri 22:00-07:00` のような1つの時間帯。曜日を省くと毎日。
+///
+/// 終了が開始より前なら日をまたぐ範囲で、曜日は開始側の日で判定する。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct QuietRange {
+    days: Option<(Weekday, Weekday)>,
+    start_minute: u16,
+    end_minute: u16,
+}
+
+impl QuietRange {
+    fn includes_day(&self, day: Weekday) -> bool {
+        let Some((first, last)) = self.days else {
+            return true;
+        };
+        let offset =
+            |day: Weekday| (day.num_days_from_monday() + 7 - first.num_days_from_monday()) % 7;
+        offset(day) <= offset(last)
+    }
+
+    fn contains(&self, day: Weekday, minute: u16) -> bool {
+        if self.start_minute < self.end_minute {
+            return self.includes_day(day)
+                && (self.start_minute..self.end_minute).contains(&minute);
+        }
+        (self.includes_day(day) && minute >= self.start_minute)
+            || (self.includes_day(day.pred()) && minute < self.end_minute)
+    }
+
+    fn as_string(&self) -> String {
+        let range = format!(
+            "{}-{}",
+            format_minute(self.start_minute),
+            format_minute(self.end_minute)
+        );
+        match self.days {
+            None => range,
+            Some((first, last)) if first == last => format!("{} {range}", weekday_name(first)),
+            Some((first, last)) => {
+                format!("{}-{} {range}", weekday_name(first), weekday_name(last))
+            }
+        }
+    }
+}
+
+/// HUD を静かにする時間帯の集まり。設定ファイルでは文字列の配列で書く。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(try_from = "Vec<String>", into = "Vec<String>")]
+pub struct QuietHours {
+    ranges: [Option<QuietRange>; MAX_QUIET_RANGES],
+}
+
+impl QuietHours {
+    pub fn is_empty(&self) -> bool {
+        self.ranges.iter().all(Option::is_none)
+    }
+
+    /// `now` が静かな時間帯に入っているか。
+    pub fn contains(&self, now: NaiveDateTime) -> bool {
+        let minute = (now.hour() * 60 + now.minute()) as u16;
+        let day = now.weekday();
+        self.ranges
+            .iter()
+            .flatten()
+            .any(|range| range.contains(day, minute))
+    }
+
+    /// 現在のローカル時刻で [`QuietHours::contains`] を評価する。
+    pub fn contains_now(&self) -> bool {
+        !self.is_empty() && self.contains(Local::now().naive_local())
+    }
+
+    pub fn as_strings(&self) -> Vec<String> {
+        self.ranges
+            .iter()
+            .flatten()
+            .map(QuietRange::as_string)
+            .collect()
+    }
+
+    /// `--config set` や環境変数向けの、カンマ区切りの表記。
+    pub fn as_string(&self) -> String {
+        self.as_strings().join(",")
+    }
+}
+
+impl TryFrom<Vec<String>> for QuietHours {
+    type Error = String;
+
+    fn try_from(value: Vec<String>) -> Result<Self, Self::Error> {
+        parse_quiet_ranges(value.iter().map(String::as_str))
+    }
+}
+
+impl From<QuietHours> for Vec<String> {
+    fn from(value: QuietHours) -> Self {
+        value.as_strings()
+    }
+}
+
+/// カンマ区切りの時間帯を読む。空文字列は「静かな時間帯なし」。
+pub fn parse_quiet_hours(raw: &str) -> Result<QuietHours, String> {
+    parse_quiet_ranges(raw.split(',').filter(|entry| !entry.trim().is_empty()))
+}
+
+fn parse_quiet_ranges<'a>(entries: impl Iterator<Item = &'a str>) -> Result<QuietHours, String> {
+    let mut hours = QuietHours::default();
+    for (index, entry) in entries.enumerate() {
+        let slot = hours
+            .ranges
+            .get_mut(index)
+            .ok_or_else(|| format!("too many quiet_hours ranges (max: {MAX_QUIET_RANGES})"))?;
+        *slot = Some(
+            parse_quiet_range(entry)
+                .ok_or_else(|| format!("invalid quiet_hours range: {}", entry.trim()))?,
+        );
+    }
+    Ok(hours)
+}
+
+fn parse_quiet_range(raw: &str) -> Option<QuietRange> {
+    let raw = raw.trim().to_ascii_lowercase();
+    let (days, range) = match raw.split_once(char::is_whitespace) {
+        Some((days, range)) => (Some(parse_weekday_range(days)?), range.trim()),
+        None => (None, raw.as_str()),
+    };
+    let (start, end) = range.split_once('-')?;
+    let start_minute = parse_minute(start)?;
+    let end_minute = parse_minute(end)?;
+    if start_minute == end_minute || start_minute == MINUTES_PER_DAY {
+        return None;
+    }
+    Some(QuietRange {
+        days,
+        start_minute,
+        end_minute,
+    })
+}
+
+fn parse_weekday_range(raw: &str) -> Option<(Weekday, Weekday)> {
+    match raw.split_once('-') {
+        Some((first, last)) => Some((parse_weekday(first)?, parse_weekday(last)?)),
+        None => {
+            let day = parse_weekday(raw)?;
+            Some((day, day))
+        }
+    }
+}
+
+fn parse_weekday(raw: &str) -> Option<Weekday> {
+    let raw = raw.trim();
+    WEEKDAYS
+        .iter()
+        .find(|(_, short, long)| raw == *short || raw == *long)
+        .map(|(day, _, _)| *day)
+}
+
+fn weekday_name(day: Weekday) -> &'static str {
+    WEEKDAYS[day.num_days_from_monday() as usize].1
+}
+
+fn parse_minute(raw: &str) -> Option<u16> {
+    let (hour, minute) = raw.trim().split_once(':')?;
+    if hour.is_empty() || hour.len() > 2 || minute.len() != 2 {
+        return None;
+    }
+    let hour = hour.parse::<u16>().ok()?;
+    let minute = minute.parse::<u16>().ok()?;
+    if minute >= 60 {
+        return None;
+    }
+    let total = hour * 60 + minute;
+    (total <= MINUTES_PER_DAY).then_some(total)
+}
+
+fn format_minute(minute: u16) -> String {
+    format!("{:02}:{:02}", minute / 60, minute % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::{parse_quiet_hours, parse_quiet_mode, QuietHours, QuietMode, MAX_QUIET_RANGES};
+
+    fn at(day: u32, hour: u32, minute: u32) -> chrono::NaiveDateTime {
+        // 2024-01-01 は月曜日
+        NaiveDate::from_ymd_opt(2024, 1, day)
+            .and_then(|date| date.and_hms_opt(hour, minute, 0))
+            .expect("valid date")
+    }
+
+    #[test]
+    fn quiet_hours_wraps_past_midnight() {
+        let hours = parse_quiet_hours("22:00-07:00").expect("parse");
+        assert!(hours.contains(at(1, 22, 0)));
+        assert!(hours.contains(at(2, 6, 59)));
+        assert!(!hours.contains(at(2, 7, 0)));
+        assert!(!hours.contains(at(2, 21, 59)));
+    }
+
+    #[test]
+    fn quiet_hours_weekday_range_uses_start_day_for_overnight_ranges() {
+        let hours = parse_quiet_hours("mon-fri 22:00-07:00, sat-sun 00:00-24:00").expect("parse");
+        assert!(hours.contains(at(5, 23, 0)));
+        assert!(hours.contains(at(6, 12, 0)));
+        assert!(hours.contains(at(7, 12, 0)));
+        assert!(!hours.contains(at(1, 12, 0)));
+        // 月曜の朝に続く範囲は日曜の夜に始まらないので、静かにならない
+        assert!(!hours.contains(at(8, 6, 0)));
+        // 金曜に始まる範囲は土曜の朝まで続くが、土曜の夜には始まらない
+        let weekdays = parse_quiet_hours("Friday 22:00-07:00").expect("parse");
+        assert!(weekdays.contains(at(6, 6, 0)));
+        assert!(!weekdays.contains(at(6, 23, 0)));
+    }
+
+    #[test]
+    fn quiet_hours_wraps_weekday_ranges_across_sunday() {
+        let hours = parse_quiet_hours("sat-mon 09:00-12:00").expect("parse");
+        assert!(hours.contains(at(6, 10, 0)));
+        assert!(hours.contains(at(7, 10, 0)));
+        assert!(hours.contains(at(1, 10, 0)));
+        assert!(!hours.contains(at(2, 10, 0)));
+    }
+
+    #[test]
+    fn quiet_hours_round_trips_normalized_strings() {
+        let hours = parse_quiet_hours(" 22:00-7:00 , SAT-sunday 9:30-24:00,wed 12:00-13:00")
+            .expect("parse");
+        assert_eq!(
+            hours.as_string(),
+            "22:00-07:00,sat-sun 09:30-24:00,wed 12:00-13:00"
+        );
+        let from_strings = QuietHours::try_from(hours.as_strings()).expect("round trip");
+        assert_eq!(from_strings, hours);
+        assert!(parse_quiet_hours("").expect("empty").is_empty());
+    }
+
+    #[test]
+    fn quiet_hours_rejects_invalid_ranges() {
+        for raw in [
+            "22:00",
+            "22:00-22:00",
+            "24:00-07:00",
+            "07:00-24:30",
+            "7-9",
+            "07:60-09:00",
+            "mo 07:00-09:00",
+            "mon-xyz 07:00-09:00",
+        ] {
+            assert!(parse_quiet_hours(raw).is_err(), "{raw} should be rejected");
+        }
+        let too_many = ["01:00-02:00"; MAX_QUIET_RANGES + 1].join(",");
+        assert!(parse_quiet_hours(&too_many).is_err());
+    }
+
+    #[test]
+    fn parse_quiet_mode_accepts_known_values() {
+        assert_eq!(parse_quiet_mode("suppress"), Some(QuietMode::Suppress));
+        assert_eq!(parse_quiet_mode(" Minimal "), Some(QuietMode::Minimal));
+        assert_eq!(parse_quiet_mode("loud"), None);
+    }
+}
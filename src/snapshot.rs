@@ -0,0 +1,425 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::config::{
+    apply_config_file, default_display_settings, parse_config_key, set_config_value, AppConfigFile,
+    DisplaySettings,
+};
+use crate::image_diff::{
+    parse_diff_threshold, DiffOptions, DiffSummary, DiffThreshold, ResizePolicy,
+};
+use crate::rendering::{
+    generate_diff_png, render_hud_png, SnapshotOptions, MAX_RENDER_SCALE_FACTOR,
+    MIN_RENDER_SCALE_FACTOR,
+};
+
+/// マニフェストで `threshold` を省略したときは、1ピクセルでも違えば失敗にする。
+pub const DEFAULT_SNAPSHOT_THRESHOLD: DiffThreshold = DiffThreshold::Pixels(0);
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ManifestFile {
+    threshold: Option<String>,
+    scale_factor: Option<f64>,
+    deterministic: Option<bool>,
+    offscreen: Option<bool>,
+    #[serde(default)]
+    settings: BTreeMap<String, toml::Value>,
+    #[serde(default, rename = "case")]
+    cases: Vec<CaseFile>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CaseFile {
+    name: String,
+    text: Option<String>,
+    text_file: Option<PathBuf>,
+    #[serde(default)]
+    settings: BTreeMap<String, toml::Value>,
+}
+
+/// スナップショットテストのマニフェスト（`cases.toml`）を読み込んだ結果。
+#[derive(Debug, Clone)]
+pub struct SnapshotManifest {
+    pub threshold: DiffThreshold,
+    pub options: SnapshotOptions,
+    pub cases: Vec<SnapshotCase>,
+}
+
+/// 1枚のスナップショット。設定は既定値にマニフェストとケースの `settings` を重ねたもので、
+/// ユーザーの設定ファイルや環境変数には左右されない。
+#[derive(Debug, Clone)]
+pub struct SnapshotCase {
+    pub name: String,
+    pub text: String,
+    pub settings: DisplaySettings,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CaseOutcome {
+    /// 閾値以内。差分画像は削除済み。
+    Passed(DiffSummary),
+    /// 閾値超え。差分画像と合成画像を残す。
+    Regressed(DiffSummary),
+    MissingBaseline,
+}
+
+/// ケースごとの生成物の置き場所。
+#[derive(Debug, Clone)]
+pub struct SnapshotPaths {
+    pub baseline: PathBuf,
+    pub current: PathBuf,
+    pub diff: PathBuf,
+    pub composite: PathBuf,
+}
+
+impl SnapshotPaths {
+    pub fn new(name: &str, baseline_dir: &Path, artifact_dir: &Path) -> Self {
+        Self {
+            baseline: baseline_path(baseline_dir, name),
+            current: artifact_dir.join(format!("{name}.current.png")),
+            diff: artifact_dir.join(format!("{name}.diff.png")),
+            composite: artifact_dir.join(format!("{name}.composite.png")),
+        }
+    }
+}
+
+fn baseline_path(baseline_dir: &Path, name: &str) -> PathBuf {
+    baseline_dir.join(format!("{name}.png"))
+}
+
+pub fn load_snapshot_manifest(path: &Path) -> Result<SnapshotManifest, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|err| format!("failed to read manifest {}: {err}", path.display()))?;
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    parse_snapshot_manifest(&content, base_dir)
+        .map_err(|err| format!("invalid manifest {}: {err}", path.display()))
+}
+
+/// `text_file` は `base_dir`（マニフェストのあるディレクトリ）からの相対パスとして読む。
+pub fn parse_snapshot_manifest(content: &str, base_dir: &Path) -> Result<SnapshotManifest, String> {
+    let manifest: ManifestFile = toml::from_str(content).map_err(|err| err.to_string())?;
+
+    let threshold = match manifest.threshold.as_deref() {
+        Some(raw) => parse_diff_threshold(raw)
+            .ok_or_else(|| format!("invalid threshold: {raw} (e.g. 0.5% or 120px)"))?,
+        None => DEFAULT_SNAPSHOT_THRESHOLD,
+    };
+    let mut options = SnapshotOptions::default();
+    if let Some(scale_factor) = manifest.scale_factor {
+        if !(MIN_RENDER_SCALE_FACTOR..=MAX_RENDER_SCALE_FACTOR).contains(&scale_factor) {
+            return Err(format!(
+                "invalid scale_factor: {scale_factor} (allowed range: {MIN_RENDER_SCALE_FACTOR}..={MAX_RENDER_SCALE_FACTOR})"
+            ));
+        }
+        options.scale_factor = scale_factor;
+    }
+    options.deterministic = manifest.deterministic.unwrap_or(false);
+    options.offscreen = manifest.offscreen.unwrap_or(false);
+
+    let mut shared = AppConfigFile::default();
+    apply_manifest_settings(&mut shared, &manifest.settings)?;
+
+    let mut names = HashSet::new();
+    let mut cases = Vec::with_capacity(manifest.cases.len());
+    for case in manifest.cases {
+        let name = case.name;
+        if !is_valid_case_name(&name) {
+            return Err(format!(
+                "invalid case name: {name:?} (use ASCII letters, digits, '_' and '-')"
+            ));
+        }
+        if !names.insert(name.clone()) {
+            return Err(format!("duplicate case name: {name}"));
+        }
+        let text = match (case.text, case.text_file) {
+            (Some(text), None) => text,
+            (None, Some(text_file)) => {
+                let path = base_dir.join(text_file);
+                let text = fs::read_to_string(&path).map_err(|err| {
+                    format!("case {name}: failed to read {}: {err}", path.display())
+                })?;
+                strip_final_newline(text)
+            }
+            _ => return Err(format!("case {name}: set exactly one of text or text_file")),
+        };
+        let mut overrides = shared.clone();
+        apply_manifest_settings(&mut overrides, &case.settings)
+            .map_err(|err| format!("case {name}: {err}"))?;
+        cases.push(SnapshotCase {
+            name,
+            text,
+            settings: apply_config_file(default_display_settings(), &overrides),
+        });
+    }
+    if cases.is_empty() {
+        return Err("no [[case]] entries".to_string());
+    }
+
+    Ok(SnapshotManifest {
+        threshold,
+        options,
+        cases,
+    })
+}
+
+// 値の検証は --config set と同じ。範囲外の値を黙って丸めるとベースラインの意図と
+// ずれるので、警告ではなくエラーにする
+fn apply_manifest_settings(
+    config: &mut AppConfigFile,
+    settings: &BTreeMap<String, toml::Value>,
+) -> Result<(), String> {
+    for (raw_key, value) in settings {
+        let key = parse_config_key(raw_key).ok_or_else(|| format!("unknown setting: {raw_key}"))?;
+        let value = match value {
+            toml::Value::String(value) => value.clone(),
+            toml::Value::Integer(value) => value.to_string(),
+            toml::Value::Float(value) => value.to_string(),
+            toml::Value::Boolean(value) => value.to_string(),
+            other => {
+                return Err(format!(
+                    "invalid value for {raw_key}: {other} (expected string, number or bool)"
+                ))
+            }
+        };
+        if let Some(warning) = set_config_value(config, key, &value)? {
+            return Err(warning);
+        }
+    }
+    Ok(())
+}
+
+// 生成物のファイル名に使うので、パス区切りや空白を含めない
+fn is_valid_case_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'-')
+}
+
+/// エディタやヒアドキュメントが末尾に付ける改行は、フィクスチャの内容に含めない。
+pub fn strip_final_newline(mut text: String) -> String {
+    if text.ends_with('\n') {
+        text.pop();
+        if text.ends_with('\r') {
+            text.pop();
+        }
+    }
+    text
+}
+
+/// `only` が空ならすべてのケース、そうでなければ名前が一致するケースだけをマニフェストの順に返す。
+pub fn select_snapshot_cases<'a>(
+    manifest: &'a SnapshotManifest,
+    only: &[String],
+) -> Result<Vec<&'a SnapshotCase>, String> {
+    if let Some(unknown) = only
+        .iter()
+        .find(|name| !manifest.cases.iter().any(|case| &case.name == *name))
+    {
+        return Err(format!("unknown case: {unknown}"));
+    }
+    Ok(manifest
+        .cases
+        .iter()
+        .filter(|case| only.is_empty() || only.contains(&case.name))
+        .collect())
+}
+
+/// ケースを描画して、そのまま `baseline_dir` のベースラインとして書き出す。書き出したパスを返す。
+pub fn update_snapshot_case(
+    case: &SnapshotCase,
+    manifest: &SnapshotManifest,
+    baseline_dir: &Path,
+) -> Result<PathBuf, String> {
+    let baseline = baseline_path(baseline_dir, &case.name);
+    render_hud_png(
+        &case.text,
+        &baseline.to_string_lossy(),
+        case.settings,
+        manifest.options,
+    )?;
+    Ok(baseline)
+}
+
+/// 1ケースを描画してベースラインと比較する。描画や比較ができなかった場合は `Err` を返す。
+///
+/// 比較は `--diff-png` と同じ AppKit 実装で、サイズが違う場合は余白を足して比べる。
+pub fn run_snapshot_case(
+    case: &SnapshotCase,
+    manifest: &SnapshotManifest,
+    paths: &SnapshotPaths,
+) -> Result<CaseOutcome, String> {
+    remove_artifact(&paths.diff)?;
+    remove_artifact(&paths.composite)?;
+    render_hud_png(
+        &case.text,
+        &paths.current.to_string_lossy(),
+        case.settings,
+        manifest.options,
+    )?;
+    if !paths.baseline.is_file() {
+        return Ok(CaseOutcome::MissingBaseline);
+    }
+
+    let options = DiffOptions {
+        composite_path: Some(paths.composite.to_string_lossy().into_owned()),
+        resize_policy: ResizePolicy::Pad,
+        ..DiffOptions::default()
+    };
+    let summary = generate_diff_png(
+        &paths.baseline.to_string_lossy(),
+        &paths.current.to_string_lossy(),
+        &paths.diff.to_string_lossy(),
+        &options,
+    )?;
+    if summary.exceeds(manifest.threshold) {
+        return Ok(CaseOutcome::Regressed(summary));
+    }
+    remove_artifact(&paths.diff)?;
+    remove_artifact(&paths.composite)?;
+    Ok(CaseOutcome::Passed(summary))
+}
+
+fn remove_artifact(path: &Path) -> Result<(), String> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(format!("failed to remove {}: {err}", path.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{
+        parse_snapshot_manifest, select_snapshot_cases, strip_final_newline,
+        DEFAULT_SNAPSHOT_THRESHOLD,
+    };
+    use crate::config::{default_display_settings, HudBackgroundColor, HudPosition};
+    use crate::image_diff::DiffThreshold;
+
+    #[test]
+    fn manifest_layers_case_settings_over_shared_settings() {
+        let manifest = parse_snapshot_manifest(
+            r#"
+threshold = "12%"
+scale_factor = 2.0
+
+[settings]
+hud_position = "top"
+max_lines = 3
+
+[[case]]
+name = "ascii_short"
+text = "hello clipboard"
+
+[[case]]
+name = "setting_hud_scale_15"
+text = "line1\nline2"
+settings = { max_lines = 2, hud_scale = 1.5, hud_background_color = "blue" }
+"#,
+            Path::new("."),
+        )
+        .expect("parse manifest");
+
+        assert_eq!(manifest.threshold, DiffThreshold::Ratio(0.12));
+        assert_eq!(manifest.options.scale_factor, 2.0);
+        assert!(!manifest.options.deterministic);
+        assert!(!manifest.options.offscreen);
+        let [short, scaled] = manifest.cases.as_slice() else {
+            panic!("expected two cases");
+        };
+        let defaults = default_display_settings();
+        assert_eq!(short.settings.hud_position, HudPosition::Top);
+        assert_eq!(short.settings.truncate_max_lines, 3);
+        assert_eq!(short.settings.hud_scale, defaults.hud_scale);
+        assert_eq!(scaled.text, "line1\nline2");
+        assert_eq!(scaled.settings.hud_position, HudPosition::Top);
+        assert_eq!(scaled.settings.truncate_max_lines, 2);
+        assert_eq!(scaled.settings.hud_scale, 1.5);
+        assert_eq!(
+            scaled.settings.hud_background_color,
+            HudBackgroundColor::Blue
+        );
+    }
+
+    #[test]
+    fn manifest_rejects_invalid_cases() {
+        let parse = |content: &str| parse_snapshot_manifest(content, Path::new("."));
+
+        let duplicate =
+            parse("[[case]]\nname = \"a\"\ntext = \"x\"\n[[case]]\nname = \"a\"\ntext = \"y\"\n");
+        assert!(duplicate
+            .expect_err("duplicate")
+            .contains("duplicate case name: a"));
+        let path_like = parse("[[case]]\nname = \"../a\"\ntext = \"x\"\n");
+        assert!(path_like.expect_err("path").contains("invalid case name"));
+        let both = parse("[[case]]\nname = \"a\"\ntext = \"x\"\ntext_file = \"a.txt\"\n");
+        assert!(both
+            .expect_err("both")
+            .contains("exactly one of text or text_file"));
+        let unknown = parse("[[case]]\nname = \"a\"\ntext = \"x\"\nsettings = { max_line = 2 }\n");
+        assert!(unknown
+            .expect_err("unknown")
+            .contains("unknown setting: max_line"));
+        let clamped =
+            parse("[[case]]\nname = \"a\"\ntext = \"x\"\nsettings = { max_lines = 99 }\n");
+        assert!(clamped.is_err());
+        assert!(parse("threshold = \"150%\"\n[[case]]\nname = \"a\"\ntext = \"x\"\n").is_err());
+        assert!(parse("threshold = \"1%\"\n").is_err());
+
+        let default_threshold = parse("[[case]]\nname = \"a-1\"\ntext = \"x\"\n").expect("parse");
+        assert_eq!(default_threshold.threshold, DEFAULT_SNAPSHOT_THRESHOLD);
+    }
+
+    #[test]
+    fn select_snapshot_cases_filters_by_name_in_manifest_order() {
+        let manifest = parse_snapshot_manifest(
+            "[[case]]\nname = \"a\"\ntext = \"x\"\n[[case]]\nname = \"b\"\ntext = \"y\"\n[[case]]\nname = \"c\"\ntext = \"z\"\n",
+            Path::new("."),
+        )
+        .expect("parse manifest");
+        let names = |only: &[&str]| {
+            let only: Vec<String> = only.iter().map(|name| name.to_string()).collect();
+            select_snapshot_cases(&manifest, &only).map(|cases| {
+                cases
+                    .iter()
+                    .map(|case| case.name.as_str())
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        assert_eq!(names(&[]), Ok(vec!["a", "b", "c"]));
+        assert_eq!(names(&["c", "a"]), Ok(vec!["a", "c"]));
+        assert_eq!(names(&["a", "d"]), Err("unknown case: d".to_string()));
+    }
+
+    #[test]
+    fn visual_regression_manifest_is_valid() {
+        let manifest = parse_snapshot_manifest(
+            include_str!("../tests/visual/cases.toml"),
+            Path::new("tests/visual"),
+        )
+        .expect("parse tests/visual/cases.toml");
+
+        assert_eq!(manifest.threshold, DiffThreshold::Ratio(0.12));
+        assert!(manifest.cases.iter().any(|case| case.name == "ascii_short"));
+    }
+
+    #[test]
+    fn strip_final_newline_removes_only_one_line_ending() {
+        assert_eq!(
+            strip_final_newline("日本語\n絵文字 🎉\n".to_string()),
+            "日本語\n絵文字 🎉"
+        );
+        assert_eq!(strip_final_newline("line\r\n".to_string()), "line");
+        assert_eq!(strip_final_newline("line\n\n".to_string()), "line\n");
+        assert_eq!(strip_final_newline("  ".to_string()), "  ");
+    }
+}
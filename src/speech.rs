@@ -0,0 +1,92 @@
+use objc2::runtime::AnyObject;
+use objc2::{class, msg_send};
+
+use crate::owned::Owned;
+use crate::pasteboard::nsstring_from_str;
+
+/// 読み上げに使う声の識別子（例: `com.apple.voice.compact.ja-JP.Kyoko`）。
+/// 未設定ならシステム設定の声を使う。
+pub fn speech_voice() -> Option<String> {
+    let voice = std::env::var("CLIIP_SHOW_ACCESSIBILITY_SPEAK_VOICE").ok()?;
+    let trimmed = voice.trim();
+    (!trimmed.is_empty()).then(|| trimmed.to_string())
+}
+
+/// 読み上げる文字列を返す。空白だけのクリップや、`max_chars` 文字を超える長いクリップは読み上げない。
+pub fn speakable_text(text: &str, max_chars: usize) -> Option<&str> {
+    let trimmed = text.trim();
+    if trimmed.is_empty() || trimmed.chars().count() > max_chars {
+        return None;
+    }
+    Some(trimmed)
+}
+
+/// `NSSpeechSynthesizer` の所有者。新しいクリップを読み上げるときは前の読み上げを止める。
+#[derive(Debug)]
+pub struct SpeechSynthesizer {
+    synthesizer: Owned,
+    // rate を 0（システム設定）に戻したときに使う、作成時の速度
+    default_rate: f32,
+}
+
+impl SpeechSynthesizer {
+    /// 指定した声が見つからなければ、システム設定の声で作り直す。
+    ///
+    /// # Safety
+    ///
+    /// メインスレッドから呼び出すこと。
+    pub unsafe fn new(voice: Option<&str>) -> Option<Self> {
+        let synthesizer: *mut AnyObject = msg_send![class!(NSSpeechSynthesizer), alloc];
+        let synthesizer: *mut AnyObject = match voice {
+            Some(voice) => {
+                let identifier = nsstring_from_str(voice);
+                let synthesizer: *mut AnyObject = msg_send![synthesizer, initWithVoice: identifier];
+                let () = msg_send![identifier, release];
+                if synthesizer.is_null() {
+                    eprintln!("warning: unknown speech voice: {voice}; using the system voice");
+                    return Self::new(None);
+                }
+                synthesizer
+            }
+            None => msg_send![synthesizer, init],
+        };
+        let synthesizer = Owned::from_raw(synthesizer)?;
+        let default_rate: f32 = msg_send![synthesizer.as_ptr(), rate];
+        Some(Self {
+            synthesizer,
+            default_rate,
+        })
+    }
+
+    /// `rate` は1分あたりの単語数。0 ならシステム設定の速度で読み上げる。
+    ///
+    /// # Safety
+    ///
+    /// メインスレッドから呼び出すこと。
+    pub unsafe fn speak(&self, text: &str, rate: f64) {
+        let synthesizer = self.synthesizer.as_ptr();
+        let () = msg_send![synthesizer, stopSpeaking];
+        let rate = if rate > 0.0 {
+            rate as f32
+        } else {
+            self.default_rate
+        };
+        let () = msg_send![synthesizer, setRate: rate];
+        let string = nsstring_from_str(text);
+        let _: bool = msg_send![synthesizer, startSpeakingString: string];
+        let () = msg_send![string, release];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::speakable_text;
+
+    #[test]
+    fn speakable_text_skips_blank_and_long_clips() {
+        assert_eq!(speakable_text("  hello \n", 5), Some("hello"));
+        assert_eq!(speakable_text("こんにちは", 5), Some("こんにちは"));
+        assert_eq!(speakable_text("こんにちは!", 5), None);
+        assert_eq!(speakable_text(" \n\t", 5), None);
+    }
+}
@@ -0,0 +1,192 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, FixedOffset};
+
+use crate::encryption::HistoryCipher;
+use crate::history::{
+    import_history, load_history, overwrite_history, HistoryImport, HistoryLimits,
+};
+
+const SYNC_FILE_EXTENSION: &str = "jsonl";
+const DEFAULT_SYNC_DEVICE_NAME: &str = "mac";
+
+/// `history sync` の結果。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistorySync {
+    /// 読んだほかの Mac の同期ファイルの数
+    pub peers: usize,
+    pub imported: HistoryImport,
+    /// 自分の同期ファイルに書いた件数
+    pub pushed: usize,
+}
+
+/// 共有フォルダー `dir` を介して、ほかの Mac と履歴を同期する。
+///
+/// ほかの Mac の同期ファイル（`<端末名>.jsonl`）を読んで今の履歴に混ぜてから、混ぜたあとの
+/// 履歴全体で自分の同期ファイルを書き直す。同期ファイルの行はすべて `sync_cipher` で
+/// 暗号化するので、フォルダーを預かるサービスからは本文が見えない。
+pub fn sync_history(
+    history_path: &Path,
+    history_cipher: Option<&HistoryCipher>,
+    dir: &Path,
+    device: &str,
+    sync_cipher: &HistoryCipher,
+    limits: HistoryLimits,
+    now: DateTime<FixedOffset>,
+) -> Result<HistorySync, String> {
+    fs::create_dir_all(dir)
+        .map_err(|err| format!("failed to create sync directory {}: {err}", dir.display()))?;
+    let own_path = sync_file_path(dir, device);
+    let mut peer_paths: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|err| format!("failed to read sync directory {}: {err}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            *path != own_path
+                && path
+                    .extension()
+                    .is_some_and(|extension| extension == SYNC_FILE_EXTENSION)
+        })
+        .collect();
+    peer_paths.sort();
+
+    let mut remote = Vec::new();
+    for path in &peer_paths {
+        // 鍵が違う Mac のファイルは読めないので、黙って混ぜずにエラーにする
+        let entries = load_history(path, Some(sync_cipher))
+            .map_err(|err| format!("{}: {err}", path.display()))?;
+        remote.extend(entries);
+    }
+    let imported = import_history(history_path, remote, limits, now, history_cipher)?;
+
+    let entries = load_history(history_path, history_cipher)?;
+    overwrite_history(&own_path, &entries, Some(sync_cipher))?;
+    Ok(HistorySync {
+        peers: peer_paths.len(),
+        imported,
+        pushed: entries.len(),
+    })
+}
+
+/// 同期フォルダーの中の、この Mac が書く同期ファイル。
+pub fn sync_file_path(dir: &Path, device: &str) -> PathBuf {
+    dir.join(format!("{device}.{SYNC_FILE_EXTENSION}"))
+}
+
+/// ホスト名を同期ファイルの名前に使える形にする。`.local` を外し、英数字と `-` `_` 以外は `-` にする。
+pub fn sync_device_name(host_name: &str) -> String {
+    let host_name = host_name.trim();
+    let host_name = host_name.strip_suffix(".local").unwrap_or(host_name);
+    let name: String = host_name
+        .chars()
+        .map(|ch| {
+            if ch.is_ascii_alphanumeric() || ch == '-' || ch == '_' {
+                ch
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    if name.trim_matches('-').is_empty() {
+        DEFAULT_SYNC_DEVICE_NAME.to_string()
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use chrono::{DateTime, Local};
+
+    use crate::encryption::{is_encrypted_line, HistoryCipher};
+    use crate::history::{load_history, HistoryLimits, HistoryStore};
+
+    use super::{sync_device_name, sync_file_path, sync_history};
+
+    #[test]
+    fn sync_device_name_makes_file_safe_names() {
+        assert_eq!(
+            sync_device_name("Somei-MacBook-Pro.local"),
+            "Somei-MacBook-Pro"
+        );
+        assert_eq!(sync_device_name("studio.example.com"), "studio-example-com");
+        assert_eq!(sync_device_name("../"), "mac");
+    }
+
+    #[test]
+    fn sync_history_exchanges_encrypted_entries_between_devices() {
+        let dir = std::env::temp_dir().join(format!("cliip-show-sync-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let sync_dir = dir.join("shared");
+        let limits = HistoryLimits {
+            max_entries: 100,
+            max_age: None,
+            max_bytes: None,
+        };
+        let now = DateTime::parse_from_rfc3339("2024-05-03T09:00:00+09:00").expect("now");
+        let sync_cipher = HistoryCipher::new(&[3; 32]);
+        let mut paths = Vec::new();
+        for (device, text) in [("office", "from office"), ("home", "from home")] {
+            let path = dir.join(format!("{device}.jsonl"));
+            let mut store = HistoryStore::open(path.clone(), None).expect("open");
+            store
+                .append(
+                    Local::now().to_rfc3339(),
+                    None,
+                    text.to_string(),
+                    limits,
+                    true,
+                )
+                .expect("append");
+            paths.push((device, path));
+        }
+
+        let (office, office_path) = &paths[0];
+        let first = sync_history(
+            office_path,
+            None,
+            &sync_dir,
+            office,
+            &sync_cipher,
+            limits,
+            now,
+        )
+        .expect("first sync");
+        assert_eq!((first.peers, first.imported.added, first.pushed), (0, 0, 1));
+        let pushed = fs::read_to_string(sync_file_path(&sync_dir, office)).expect("sync file");
+        assert!(pushed.lines().all(is_encrypted_line));
+        assert!(!pushed.contains("from office"));
+
+        let (home, home_path) = &paths[1];
+        let second = sync_history(home_path, None, &sync_dir, home, &sync_cipher, limits, now)
+            .expect("second sync");
+        assert_eq!(
+            (second.peers, second.imported.added, second.pushed),
+            (1, 1, 2)
+        );
+        let texts: Vec<_> = load_history(home_path, None)
+            .expect("load")
+            .into_iter()
+            .map(|entry| entry.text)
+            .collect();
+        assert_eq!(texts.len(), 2);
+        assert!(texts.contains(&"from office".to_string()));
+
+        // 鍵が違えば同期しない
+        let other_cipher = HistoryCipher::new(&[4; 32]);
+        assert!(sync_history(
+            office_path,
+            None,
+            &sync_dir,
+            office,
+            &other_cipher,
+            limits,
+            now
+        )
+        .is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,209 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::config::{
+    config_file_path, intern_str, parse_f64_value, parse_hex_rgba, parse_hud_font, DisplaySettings,
+    HudBackgroundColor, HudMaterial, HudTextColor, MAX_HUD_FONT_SIZE, MAX_HUD_LINE_SPACING,
+    MIN_HUD_FONT_SIZE,
+};
+
+const THEMES_DIR_NAME: &str = "themes";
+const THEME_FILE_EXTENSION: &str = "toml";
+const MAX_THEME_BORDER_WIDTH: f64 = 8.0;
+const MAX_THEME_CORNER_RADIUS: f64 = 40.0;
+const MAX_THEME_PADDING: f64 = 48.0;
+
+/// テーマファイル（`themes/<名前>.toml`）の中身。書いていない項目は設定をそのまま使う。
+///
+/// 寸法はどれも `hud_scale` を掛ける前のポイントで書く。
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HudTheme {
+    pub background_color: Option<HudBackgroundColor>,
+    pub text_color: Option<HudTextColor>,
+    pub material: Option<HudMaterial>,
+    /// `#RRGGBB` か `#RRGGBBAA`
+    pub border_color: Option<String>,
+    pub border_width: Option<f64>,
+    pub corner_radius: Option<f64>,
+    /// 本文のフォント名（`Menlo` など）
+    pub font: Option<String>,
+    pub font_size: Option<f64>,
+    pub line_spacing: Option<f64>,
+    pub horizontal_padding: Option<f64>,
+    pub vertical_padding: Option<f64>,
+}
+
+pub fn themes_dir() -> Result<PathBuf, String> {
+    if let Ok(path) = std::env::var("CLIIP_SHOW_THEMES_DIR") {
+        let trimmed = path.trim();
+        if !trimmed.is_empty() {
+            return Ok(PathBuf::from(trimmed));
+        }
+    }
+    let config_path = config_file_path()?;
+    let parent = config_path
+        .parent()
+        .ok_or_else(|| format!("invalid config path: {}", config_path.display()))?;
+    Ok(parent.join(THEMES_DIR_NAME))
+}
+
+/// テーマ名として使えるか。ファイル名にするので英数字と `-` `_` だけを許す。
+pub fn is_valid_theme_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_')
+}
+
+pub fn theme_file_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{name}.{THEME_FILE_EXTENSION}"))
+}
+
+/// `dir` から `name` のテーマを読む。ファイルがない・書式が違うときはエラーにする。
+pub fn load_theme(dir: &Path, name: &str) -> Result<HudTheme, String> {
+    if !is_valid_theme_name(name) {
+        return Err(format!(
+            "invalid theme name: {name} (allowed: letters, digits, '-', '_')"
+        ));
+    }
+    let path = theme_file_path(dir, name);
+    let raw = fs::read_to_string(&path)
+        .map_err(|err| format!("failed to read theme {}: {err}", path.display()))?;
+    let theme: HudTheme = toml::from_str(&raw)
+        .map_err(|err| format!("failed to parse theme {}: {err}", path.display()))?;
+    if let Some(color) = &theme.border_color {
+        if parse_hex_rgba(color).is_none() {
+            return Err(format!(
+                "invalid border_color in theme {}: {color} (expected #RRGGBB or #RRGGBBAA)",
+                path.display()
+            ));
+        }
+    }
+    Ok(theme)
+}
+
+/// テーマの値を `base` に重ねる。範囲外の寸法は丸める。
+pub fn apply_theme(base: DisplaySettings, theme: &HudTheme) -> DisplaySettings {
+    let mut settings = base;
+    if let Some(value) = theme.background_color {
+        settings.hud_background_color = value;
+    }
+    if let Some(value) = theme.text_color {
+        settings.hud_text_color = value;
+    }
+    if let Some(value) = theme.material {
+        settings.hud_material = value;
+    }
+    if let Some(value) = theme.border_color.as_deref().and_then(parse_hex_rgba) {
+        settings.hud_border_color = Some(value);
+    }
+    if let Some(value) = theme.border_width.filter(|value| value.is_finite()) {
+        settings.hud_border_width = Some(value.clamp(0.0, MAX_THEME_BORDER_WIDTH));
+    }
+    if let Some(value) = theme.corner_radius.filter(|value| value.is_finite()) {
+        settings.hud_corner_radius = Some(value.clamp(0.0, MAX_THEME_CORNER_RADIUS));
+    }
+    if let Some(Ok(value)) = theme.font.as_deref().map(parse_hud_font) {
+        settings.hud_font = value.map(intern_str);
+    }
+    if let Some(value) = theme.font_size {
+        settings.hud_font_size = parse_f64_value(
+            value,
+            settings.hud_font_size,
+            MIN_HUD_FONT_SIZE,
+            MAX_HUD_FONT_SIZE,
+        );
+    }
+    if let Some(value) = theme.line_spacing {
+        settings.hud_line_spacing =
+            parse_f64_value(value, settings.hud_line_spacing, 0.0, MAX_HUD_LINE_SPACING);
+    }
+    let padding = |value: Option<f64>| {
+        value
+            .filter(|value| value.is_finite())
+            .map(|value| value.clamp(0.0, MAX_THEME_PADDING))
+    };
+    if let Some(value) = padding(theme.horizontal_padding) {
+        settings.hud_padding.0 = value;
+    }
+    if let Some(value) = padding(theme.vertical_padding) {
+        settings.hud_padding.1 = value;
+    }
+    settings
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::config::{default_display_settings, HudBackgroundColor, HudMaterial, HudTextColor};
+
+    use super::{apply_theme, is_valid_theme_name, load_theme, theme_file_path};
+
+    #[test]
+    fn load_theme_reads_named_file_and_applies_values() {
+        let dir = std::env::temp_dir().join(format!("cliip-show-themes-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("create dir");
+        fs::write(
+            theme_file_path(&dir, "solarized"),
+            r##"
+background_color = "#002B36"
+text_color = "#93A1A1"
+material = "popover"
+border_color = "#586E75"
+border_width = 2
+corner_radius = 99
+font = "Monaco"
+font_size = 99
+line_spacing = 4
+horizontal_padding = 20
+"##,
+        )
+        .expect("write theme");
+
+        let theme = load_theme(&dir, "solarized").expect("load");
+        let base = default_display_settings();
+        let settings = apply_theme(base, &theme);
+        assert_eq!(
+            settings.hud_background_color,
+            HudBackgroundColor::Rgba([0x00, 0x2B, 0x36, 0xFF])
+        );
+        assert_eq!(
+            settings.hud_text_color,
+            HudTextColor::Rgba([0x93, 0xA1, 0xA1, 0xFF])
+        );
+        assert_eq!(settings.hud_material, HudMaterial::Popover);
+        assert_eq!(settings.hud_border_color, Some([0x58, 0x6E, 0x75, 0xFF]));
+        assert_eq!(settings.hud_border_width, Some(2.0));
+        assert_eq!(settings.hud_corner_radius, Some(40.0));
+        assert_eq!(settings.hud_font, Some("Monaco"));
+        assert_eq!(settings.hud_font_size, 40.0);
+        assert_eq!(settings.hud_line_spacing, 4.0);
+        assert_eq!(settings.hud_padding, (20.0, base.hud_padding.1));
+
+        assert!(load_theme(&dir, "missing").is_err());
+        fs::write(theme_file_path(&dir, "broken"), "border_color = \"navy\"\n")
+            .expect("write theme");
+        assert!(load_theme(&dir, "broken").is_err());
+        fs::write(
+            theme_file_path(&dir, "typo"),
+            "backgroud_color = \"black\"\n",
+        )
+        .expect("write theme");
+        assert!(load_theme(&dir, "typo").is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn theme_names_cannot_escape_the_themes_directory() {
+        assert!(is_valid_theme_name("solarized-dark_2"));
+        assert!(!is_valid_theme_name(""));
+        assert!(!is_valid_theme_name("../config"));
+        assert!(!is_valid_theme_name("a/b"));
+    }
+}
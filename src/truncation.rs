@@ -0,0 +1,260 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+/// `text` を最大 `max_lines` 行、1行あたり最大 `max_width` 文字に切り詰める。
+///
+/// 文字数は書記素クラスタ（結合文字や ZWJ でつながった絵文字を含めて1文字）で数え、
+/// クラスタの途中では切らない。省いた部分は `...` で示し、`...` も `max_width` に含める。
+pub fn truncate_text(text: &str, max_width: usize, max_lines: usize) -> String {
+    // 巨大なクリップでも全体を分割しないよう、表示する max_lines 行だけを取り出し、
+    // 残りは空行以外が続くか（省略記号が必要か）だけを調べる
+    let mut rest = text
+        .split_terminator('\n')
+        .map(|line| line.trim_end_matches('\r'));
+    let mut kept: Vec<&str> = rest.by_ref().take(max_lines).collect();
+    let overflowed = rest.any(|line| !line.trim().is_empty());
+    if !overflowed {
+        while matches!(kept.last(), Some(last) if last.trim().is_empty()) {
+            kept.pop();
+        }
+        if kept.is_empty() {
+            kept.push("");
+        }
+    }
+
+    let mut lines: Vec<String> = kept
+        .into_iter()
+        .map(|line| truncate_line(line, max_width))
+        .collect();
+    if overflowed {
+        if let Some(last) = lines.last_mut() {
+            *last = append_ellipsis(last, max_width);
+        }
+    }
+
+    lines.join("\n")
+}
+
+fn truncate_line(line: &str, max_width: usize) -> String {
+    if line.graphemes(true).nth(max_width).is_none() {
+        return line.to_string();
+    }
+
+    if max_width <= 3 {
+        return "...".chars().take(max_width).collect();
+    }
+
+    format!("{}...", grapheme_prefix(line, max_width - 3))
+}
+
+fn append_ellipsis(line: &str, max_width: usize) -> String {
+    if max_width == 0 {
+        return String::new();
+    }
+
+    if max_width <= 3 {
+        return "...".chars().take(max_width).collect();
+    }
+
+    let current_len = line.graphemes(true).count();
+    if current_len + 3 <= max_width {
+        return format!("{line}...");
+    }
+
+    format!("{}...", grapheme_prefix(line, max_width - 3))
+}
+
+/// 1行を `max_width` 文字に収まるよう、中央を `...` で省く。
+///
+/// 先頭の `keep_head` 文字（URL のスキームとホストなど）は、収まる限り残す。
+pub fn truncate_middle(line: &str, max_width: usize, keep_head: usize) -> String {
+    let total = line.graphemes(true).count();
+    if total <= max_width {
+        return line.to_string();
+    }
+    if max_width <= 3 {
+        return "...".chars().take(max_width).collect();
+    }
+    let available = max_width - 3;
+    let head = keep_head.max(available.div_ceil(2)).min(available);
+    let tail = available - head;
+    let tail_start = line
+        .grapheme_indices(true)
+        .nth(total - tail)
+        .map_or(line.len(), |(index, _)| index);
+    format!("{}...{}", grapheme_prefix(line, head), &line[tail_start..])
+}
+
+fn grapheme_prefix(line: &str, count: usize) -> &str {
+    match line.grapheme_indices(true).nth(count) {
+        Some((end, _)) => &line[..end],
+        None => line,
+    }
+}
+
+/// 改行で分割し、末尾の空行（空白だけの行を含む）を取り除く。`\r\n` の `\r` も落とす。
+/// 空のテキストでも1行（`""`）を返す。
+pub fn split_non_trailing_lines(text: &str) -> Vec<&str> {
+    let mut lines: Vec<&str> = text
+        .split_terminator('\n')
+        .map(|line| line.trim_end_matches('\r'))
+        .collect();
+
+    while matches!(lines.last(), Some(last) if last.trim().is_empty()) {
+        lines.pop();
+    }
+
+    if lines.is_empty() {
+        lines.push("");
+    }
+    lines
+}
+
+/// 1行を等幅フォントで描いたときの幅を、半角1文字を 1 とした単位で見積もる。
+///
+/// East Asian Width に従う（全角・絵文字は 2、結合文字は 0）。空行も1文字分とする。
+pub fn line_display_units(line: &str) -> f64 {
+    let units = unicode_width::UnicodeWidthStr::width(line) as f64;
+    units.max(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use unicode_segmentation::UnicodeSegmentation;
+
+    use super::{line_display_units, split_non_trailing_lines, truncate_middle, truncate_text};
+
+    // 結合文字・ZWJ 絵文字・CRLF・空白行を混ぜたテキスト
+    fn clip_text() -> impl Strategy<Value = String> {
+        let piece = prop_oneof![
+            Just("a"),
+            Just("."),
+            Just(" "),
+            Just("あ"),
+            Just("e\u{301}"),
+            Just("👍🏽"),
+            Just("👨‍👩‍👧"),
+            Just("\n"),
+            Just("\r\n"),
+        ];
+        prop::collection::vec(piece, 0..80).prop_map(|pieces| pieces.concat())
+    }
+
+    proptest! {
+        #[test]
+        fn truncated_text_fits_max_width_and_lines(
+            text in clip_text(),
+            max_width in 0usize..40,
+            max_lines in 1usize..8,
+        ) {
+            let truncated = truncate_text(&text, max_width, max_lines);
+            let lines: Vec<&str> = truncated.split('\n').collect();
+            prop_assert!(lines.len() <= max_lines);
+            for line in lines {
+                prop_assert!(line.graphemes(true).count() <= max_width);
+            }
+        }
+
+        #[test]
+        fn truncated_lines_end_on_grapheme_boundaries(
+            text in clip_text(),
+            max_width in 0usize..40,
+            max_lines in 1usize..8,
+        ) {
+            let truncated = truncate_text(&text, max_width, max_lines);
+            let source: Vec<&str> = text
+                .split_terminator('\n')
+                .map(|line| line.trim_end_matches('\r'))
+                .collect();
+            for (index, line) in truncated.split('\n').enumerate() {
+                // 省略記号は max_width が 3 未満だと "." や ".." になるので、末尾の "." ごと外す
+                let kept = line.trim_end_matches('.');
+                let original = source.get(index).copied().unwrap_or("");
+                prop_assert!(original.starts_with(kept), "{line:?} is not a prefix of {original:?}");
+                prop_assert!(
+                    kept.len() == original.len()
+                        || original.grapheme_indices(true).any(|(at, _)| at == kept.len()),
+                    "{line:?} splits a grapheme of {original:?}"
+                );
+            }
+        }
+
+        #[test]
+        fn text_that_fits_is_left_unchanged(
+            text in clip_text(),
+            spare_lines in 0usize..3,
+        ) {
+            let lines = split_non_trailing_lines(&text);
+            let max_width = lines
+                .iter()
+                .map(|line| line.graphemes(true).count())
+                .max()
+                .unwrap_or(0);
+            let max_lines = lines.len() + spare_lines;
+            prop_assert_eq!(truncate_text(&text, max_width, max_lines), lines.join("\n"));
+        }
+    }
+
+    #[test]
+    fn truncates_single_long_line() {
+        let input = "abcdefghijklmnopqrstuvwxyz";
+        assert_eq!(truncate_text(input, 10, 5), "abcdefg...");
+    }
+
+    #[test]
+    fn truncate_middle_keeps_head_and_tail() {
+        let url = "https://example.com/very/long/path/to/page.html";
+        assert_eq!(truncate_middle(url, 100, 19), url);
+        assert_eq!(
+            truncate_middle(url, 30, 19),
+            "https://example.com...age.html"
+        );
+        assert_eq!(truncate_middle("abcdefghij", 7, 0), "ab...ij");
+        assert_eq!(truncate_middle("abcdefghij", 2, 0), "..");
+    }
+
+    #[test]
+    fn truncates_lines_count_and_adds_ellipsis_to_last_line() {
+        let input = "line1\nline2\nline3\nline4\nline5\nline6";
+        assert_eq!(
+            truncate_text(input, 100, 5),
+            "line1\nline2\nline3\nline4\nline5..."
+        );
+    }
+
+    #[test]
+    fn ignores_trailing_blank_lines_beyond_max_lines() {
+        let input = "line1\nline2\n\n  \n\r\n";
+        assert_eq!(truncate_text(input, 100, 2), "line1\nline2");
+        assert_eq!(truncate_text(input, 100, 4), "line1\nline2");
+        assert_eq!(truncate_text("\n\n", 100, 5), "");
+        assert_eq!(truncate_text("line1\n\n\nline4", 100, 2), "line1\n...");
+    }
+
+    #[test]
+    fn handles_utf8_by_char_count() {
+        let input = "あいうえおかきくけこ";
+        assert_eq!(truncate_text(input, 6, 5), "あいう...");
+    }
+
+    #[test]
+    fn does_not_split_combining_marks_or_emoji_sequences() {
+        assert_eq!(
+            truncate_text(&"e\u{301}".repeat(10), 6, 1),
+            "e\u{301}e\u{301}e\u{301}..."
+        );
+        assert_eq!(truncate_text("👨‍👩‍👧👨‍👩‍👧", 2, 1), "👨‍👩‍👧👨‍👩‍👧");
+        assert_eq!(truncate_text("ab\nc", 4, 1), "a...");
+    }
+
+    #[test]
+    fn line_display_units_follows_east_asian_width() {
+        assert_eq!(line_display_units("hello"), 5.0);
+        assert_eq!(line_display_units("привет"), 6.0);
+        assert_eq!(line_display_units("αβγ"), 3.0);
+        assert_eq!(line_display_units("日本語"), 6.0);
+        assert_eq!(line_display_units("📋✅"), 4.0);
+        assert_eq!(line_display_units("e\u{301}"), 1.0);
+        assert_eq!(line_display_units(""), 1.0);
+    }
+}
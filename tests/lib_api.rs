@@ -0,0 +1,33 @@
+#![cfg(target_os = "macos")]
+
+use cliip_show::config::{
+    apply_config_file, default_display_settings, parse_config_key, set_config_value, AppConfigFile,
+    ConfigKey, HudPosition,
+};
+use cliip_show::layout::{compute_hud_layout_metrics_with_scale, hud_origin_for_frame};
+use cliip_show::truncation::truncate_text;
+use objc2_foundation::{NSPoint, NSRect, NSSize};
+
+#[test]
+fn config_round_trips_through_public_api() {
+    let mut config = AppConfigFile::default();
+    let key = parse_config_key("hud-position").expect("known key");
+    assert_eq!(key, ConfigKey::HudPosition);
+    set_config_value(&mut config, key, "bottom").expect("valid value");
+
+    let settings = apply_config_file(default_display_settings(), &config);
+    assert_eq!(settings.hud_position, HudPosition::Bottom);
+}
+
+#[test]
+fn truncation_and_layout_are_usable_without_the_binary() {
+    let text = truncate_text("line1\nline2\nline3", 100, 2);
+    assert_eq!(text, "line1\nline2...");
+
+    let metrics = compute_hud_layout_metrics_with_scale(320.0, 44.0, 0.0, 1.0);
+    assert!(metrics.height >= 44.0);
+
+    let frame = NSRect::new(NSPoint::new(0.0, 0.0), NSSize::new(1440.0, 900.0));
+    let (x, _) = hud_origin_for_frame(frame, metrics.width, metrics.height, HudPosition::Center);
+    assert_eq!(x, (1440.0 - metrics.width) / 2.0);
+}